@@ -0,0 +1,83 @@
+//! Compares building and packing sprite quads with per vertex [`Vertex`] maps
+//! against the packed [`VertexData`] the renderer uses
+//!
+//! Run with `cargo bench --bench vertex_packing`
+
+use std::{collections::BTreeMap, hint::black_box, time::Instant};
+
+use nimbus::mesh::{
+    AttributeValue, MeshAttribute, Vertex, VertexData, QUAD_UVS, QUAD_VERTEX_POSITIONS,
+};
+
+const SPRITES: usize = 10_000;
+const ITERATIONS: u32 = 50;
+const ATTRIBUTES: [MeshAttribute; 3] = [
+    MeshAttribute::Position,
+    MeshAttribute::UV,
+    MeshAttribute::Color,
+];
+
+fn sprite_positions(index: usize) -> [[f32; 3]; 4] {
+    let offset = index as f32;
+    QUAD_VERTEX_POSITIONS.map(|position| [position.x + offset, position.y, 0.])
+}
+
+fn vertex_maps(bytes: &mut Vec<u8>) {
+    for index in 0..SPRITES {
+        let vertices: Vec<Vertex> = sprite_positions(index)
+            .iter()
+            .zip(QUAD_UVS)
+            .map(|(position, uv)| {
+                Vertex(BTreeMap::from([
+                    (MeshAttribute::Position, AttributeValue::Position(*position)),
+                    (MeshAttribute::UV, AttributeValue::UV(uv.into())),
+                    (MeshAttribute::Color, AttributeValue::Color([1.; 4])),
+                ]))
+            })
+            .collect();
+
+        for vertex in &vertices {
+            bytes.append(&mut vertex.get_bytes());
+        }
+    }
+}
+
+fn vertex_data(bytes: &mut Vec<u8>) {
+    for index in 0..SPRITES {
+        let vertices = VertexData {
+            positions: sprite_positions(index).to_vec(),
+            uvs: QUAD_UVS.map(Into::into).to_vec(),
+            colors: vec![[1.; 4]; 4],
+            normals: Vec::new(),
+        };
+
+        vertices.write_bytes(&ATTRIBUTES, bytes);
+    }
+}
+
+fn bench(name: &str, f: fn(&mut Vec<u8>)) -> f64 {
+    let mut bytes = Vec::new();
+    // Warm up
+    f(&mut bytes);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        bytes.clear();
+        f(black_box(&mut bytes));
+    }
+    let per_iteration = start.elapsed().as_secs_f64() / ITERATIONS as f64;
+
+    println!(
+        "{name:>12}: {:8.3} ms per {SPRITES} sprites",
+        per_iteration * 1000.
+    );
+
+    per_iteration
+}
+
+fn main() {
+    let maps = bench("Vertex", vertex_maps);
+    let packed = bench("VertexData", vertex_data);
+
+    println!("{:>12}: {:.1}x faster", "VertexData", maps / packed);
+}
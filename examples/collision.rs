@@ -23,10 +23,7 @@ impl Nimbus for GameExample {
     }
 
     fn update(&mut self, engine: &mut Engine, _delta: f32) {
-        if let Some(pos) = engine
-            .camera
-            .viewport_to_world_position(engine.input.mouse_position, engine.get_viewport())
-        {
+        if let Some(pos) = engine.viewport_to_world_position(engine.input.mouse_position) {
             let new_rect = Rect::from_center_size(pos.truncate(), Vec2::splat(50.));
 
             if !rect_rect_collision(new_rect, self.rect) {
@@ -29,10 +29,7 @@ impl Nimbus for GameExample {
     }
 
     fn update(&mut self, engine: &mut Engine, delta: f32) {
-        if let Some(pos) = engine
-            .camera
-            .viewport_to_world_position(engine.input.mouse_position, engine.get_viewport())
-        {
+        if let Some(pos) = engine.viewport_to_world_position(engine.input.mouse_position) {
             self.mouse_line.1 = pos.truncate();
         }
     }
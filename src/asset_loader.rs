@@ -4,12 +4,13 @@ use tobj::LoadOptions;
 
 use crate::arena::ArenaId;
 use crate::components::color::Color;
-use crate::material::Material;
+use crate::levels::{self, Level};
+use crate::material::{Material, MaterialHandle};
 use crate::mesh::{AttributeValue, Mesh, MeshAttribute, MeshBuilder, Vertex};
 use crate::model::Model;
 use crate::renderer::{
     fonts::Font,
-    texture::{Image, Texture},
+    texture::{Image, Texture, TextureSamplerType},
 };
 use image::EncodableLayout;
 use std::io::Cursor;
@@ -76,6 +77,8 @@ pub struct AssetPipeline {
     // pub(crate) textures: Arena<Texture>,
     #[cfg(feature = "hot-reloading")]
     watcher: FilesystemWatcher,
+    /// Whether loaded textures get a mip chain
+    pub(crate) texture_mipmaps: bool,
 }
 
 impl Engine {
@@ -83,7 +86,8 @@ impl Engine {
         let image = self
             .asset_pipeline
             .load_texture_from_bytes(bytes, extension)
-            .unwrap();
+            .unwrap()
+            .with_mipmaps(self.asset_pipeline.texture_mipmaps);
 
         self.renderer.add_texture(image)
     }
@@ -115,10 +119,40 @@ impl Engine {
         (aseprite_json.frames, texture)
     }
 
+    /// Loads a Tiled map saved as TMX or JSON, along with its tilesets
+    /// Tile layers become [`crate::tilemap::Tilemap`]s and object layers [`crate::levels::LevelObject`]s
+    pub fn load_tiled_map<P: AsRef<Path>>(&mut self, path: P) -> Result<Level, String> {
+        levels::tiled::load_map(self, path.as_ref())
+    }
+
+    /// Loads every level of an LDtk project, along with its tilesets
+    /// Tile layers become [`crate::tilemap::Tilemap`]s and entity layers [`crate::levels::LevelObject`]s
+    pub fn load_ldtk_project<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<Level>, String> {
+        levels::ldtk::load_project(self, path.as_ref())
+    }
+
+    /// Whether textures loaded afterwards get a full mip chain, see [`Image::mipmaps`]
+    pub fn set_texture_mipmaps(&mut self, mipmaps: bool) {
+        self.asset_pipeline.texture_mipmaps = mipmaps;
+    }
+
     pub fn load_texture<P: AsRef<Path>>(&mut self, path: P) -> ArenaId<Texture> {
+        self.load_texture_with_sampler(path, TextureSamplerType::default())
+    }
+
+    /// Loads a texture sampled with `sampler`, like a repeating [`crate::texture::SamplerConfig`] for tiled backgrounds
+    pub fn load_texture_with_sampler<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        sampler: impl Into<TextureSamplerType>,
+    ) -> ArenaId<Texture> {
         match self.asset_pipeline.load_texture(&path) {
             Ok(image) => {
-                let id = self.renderer.add_texture(image);
+                let id = self.renderer.add_texture(
+                    image
+                        .with_sampler(sampler)
+                        .with_mipmaps(self.asset_pipeline.texture_mipmaps),
+                );
 
                 #[cfg(feature = "hot-reloading")]
                 self.asset_pipeline
@@ -134,7 +168,47 @@ impl Engine {
 
     pub fn reload_texture(&mut self, absoulte_file: PathBuf, handle: ArenaId<Texture>) {
         let image = self.asset_pipeline.load_texture(&absoulte_file).unwrap();
-        self.renderer.replace_image(handle, image);
+        // The file doesn't know the sampler and mips the texture was loaded with
+        let Some(texture) = self.renderer.textures.get(handle) else {
+            return;
+        };
+        let sampler = texture.sampler;
+        let mipmaps = texture.texture.mip_level_count() > 1;
+        self.renderer
+            .replace_image(handle, image.with_mipmaps(mipmaps));
+        self.renderer.textures.get_mut(handle).unwrap().sampler = sampler;
+    }
+
+    /// Pushes `material` drawn with the WGSL shader at `path` instead of [`Material::shader`]
+    /// With hot reloading the pipeline is rebuilt when the file changes,
+    /// compile errors are logged and listed in [`crate::Renderer::shader_errors`]
+    pub fn load_material<P: AsRef<Path>>(
+        &mut self,
+        material: impl Material + 'static,
+        path: P,
+    ) -> MaterialHandle {
+        let handle = self.renderer.push_material(material);
+        self.reload_shader(path.as_ref().to_path_buf(), handle);
+
+        #[cfg(feature = "hot-reloading")]
+        self.asset_pipeline
+            .watch_file(&path, handle, AssetType::Shader);
+
+        handle
+    }
+
+    pub fn reload_shader(&mut self, path: PathBuf, handle: MaterialHandle) {
+        let source = match self.asset_pipeline.load_path(&path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                println!("Couldn't load shader {:?}: {}", path, e);
+                return;
+            }
+        };
+
+        if let Err(e) = self.renderer.reload_material_shader(handle, source) {
+            println!("{}", e.message);
+        }
     }
 
     #[allow(clippy::collapsible_match)]
@@ -153,6 +227,11 @@ impl Engine {
                                 let id = id.into();
                                 self.reload_texture(pathbuf.to_owned(), id)
                             }
+                            AssetType::Shader => {
+                                let id = *id;
+                                let id = id.into();
+                                self.reload_shader(pathbuf.to_owned(), id)
+                            }
                             _ => {}
                         },
                         None => {
@@ -13,6 +13,7 @@ pub enum AssetType {
     Texture,
     Audio,
     Scene,
+    Shader,
 }
 
 // pub struct FileChange {
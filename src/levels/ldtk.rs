@@ -0,0 +1,366 @@
+use std::path::Path;
+
+use glam::{UVec2, Vec2};
+use serde::Deserialize;
+
+use crate::{components::color::Color, texture_atlas::TextureAtlas, tilemap::Tile};
+
+use super::{
+    pixel_rect, tileset_atlas, Level, LevelAssets, LevelObject, LevelTilemaps, ObjectLayer,
+    Properties, PropertyValue,
+};
+
+#[derive(Deserialize, Debug)]
+struct LdtkProject {
+    defs: LdtkDefinitions,
+    levels: Vec<LdtkLevel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkDefinitions {
+    tilesets: Vec<LdtkTileset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkTileset {
+    uid: i64,
+    #[serde(rename = "relPath")]
+    rel_path: Option<String>,
+    #[serde(rename = "__cWid")]
+    columns: usize,
+    #[serde(rename = "__cHei")]
+    rows: usize,
+    #[serde(rename = "tileGridSize")]
+    tile_grid_size: f32,
+    #[serde(default)]
+    spacing: f32,
+    #[serde(default)]
+    padding: f32,
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkLevel {
+    identifier: String,
+    #[serde(rename = "worldX")]
+    world_x: f32,
+    #[serde(rename = "worldY")]
+    world_y: f32,
+    #[serde(rename = "pxWid")]
+    width: f32,
+    #[serde(rename = "pxHei")]
+    height: f32,
+    #[serde(rename = "fieldInstances", default)]
+    fields: Vec<LdtkField>,
+    /// Null when the level is saved in its own file
+    #[serde(rename = "layerInstances")]
+    layers: Option<Vec<LdtkLayer>>,
+    #[serde(rename = "externalRelPath")]
+    external_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkLayer {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    /// `IntGrid`, `Entities`, `Tiles` or `AutoLayer`
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__cWid")]
+    columns: u32,
+    #[serde(rename = "__cHei")]
+    rows: u32,
+    #[serde(rename = "__gridSize")]
+    grid_size: f32,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset: Option<i64>,
+    #[serde(rename = "gridTiles", default)]
+    grid_tiles: Vec<LdtkTile>,
+    #[serde(rename = "autoLayerTiles", default)]
+    auto_layer_tiles: Vec<LdtkTile>,
+    #[serde(rename = "entityInstances", default)]
+    entities: Vec<LdtkEntity>,
+    #[serde(default = "visible_by_default")]
+    visible: bool,
+}
+
+fn visible_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkTile {
+    /// Position in the layer in pixels
+    px: [f32; 2],
+    /// Tile id in the tileset
+    t: usize,
+    /// Bit 0 flips x, bit 1 flips y
+    #[serde(default)]
+    f: u8,
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkEntity {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    iid: String,
+    /// Position of the pivot in the layer in pixels
+    px: [f32; 2],
+    #[serde(rename = "__pivot")]
+    pivot: [f32; 2],
+    width: f32,
+    height: f32,
+    #[serde(rename = "fieldInstances", default)]
+    fields: Vec<LdtkField>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LdtkField {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__value")]
+    value: serde_json::Value,
+}
+
+/// Loads every level of an LDtk project, including levels saved in separate files
+/// Cells with stacked auto layer tiles only keep the topmost one
+pub(crate) fn load_project(
+    assets: &mut impl LevelAssets,
+    path: &Path,
+) -> Result<Vec<Level>, String> {
+    let bytes = assets.load_file(path)?;
+    let project: LdtkProject =
+        serde_json::from_slice(&bytes).map_err(|error| format!("{}: {error}", path.display()))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut tilesets = Vec::new();
+    for tileset in &project.defs.tilesets {
+        // Tilesets made from LDtk's embedded icons have no image
+        let Some(rel_path) = &tileset.rel_path else {
+            continue;
+        };
+        let texture = assets.load_texture(&directory.join(rel_path));
+        let atlas = tileset_atlas(
+            texture,
+            Vec2::splat(tileset.tile_grid_size),
+            tileset.columns,
+            tileset.rows,
+            tileset.spacing,
+            tileset.padding,
+        );
+        tilesets.push((tileset.uid, atlas));
+    }
+
+    let mut levels = Vec::new();
+    for mut level in project.levels {
+        if let (None, Some(external_path)) = (&level.layers, &level.external_path) {
+            let path = directory.join(external_path);
+            let bytes = assets.load_file(&path)?;
+            let external: LdtkLevel = serde_json::from_slice(&bytes)
+                .map_err(|error| format!("{}: {error}", path.display()))?;
+            level.layers = external.layers;
+        }
+
+        levels.push(
+            build_level(&level, &tilesets)
+                .map_err(|error| format!("{}: {}: {error}", path.display(), level.identifier))?,
+        );
+    }
+
+    Ok(levels)
+}
+
+fn build_level(level: &LdtkLevel, tilesets: &[(i64, TextureAtlas)]) -> Result<Level, String> {
+    let origin = Vec2::new(level.world_x, level.world_y);
+    let mut tilemaps =
+        LevelTilemaps::new(Vec2::new(origin.x, -origin.y).extend(0.), tilesets.len());
+    let mut object_layers = Vec::new();
+
+    // LDtk lists the topmost layer first
+    for layer in level.layers.iter().flatten().rev() {
+        if layer.kind == "Entities" {
+            object_layers.push(ObjectLayer {
+                name: layer.identifier.clone(),
+                objects: layer
+                    .entities
+                    .iter()
+                    .map(|entity| {
+                        let size = Vec2::new(entity.width, entity.height);
+                        let position = Vec2::from(entity.px) - Vec2::from(entity.pivot) * size;
+                        LevelObject {
+                            id: entity.iid.clone(),
+                            name: String::new(),
+                            kind: entity.identifier.clone(),
+                            bounds: pixel_rect(origin, position, size),
+                            properties: properties(&entity.fields),
+                        }
+                    })
+                    .collect(),
+            });
+            continue;
+        }
+
+        let Some(tileset) = layer
+            .tileset
+            .and_then(|uid| tilesets.iter().position(|(tileset, _)| *tileset == uid))
+        else {
+            continue;
+        };
+        let tiles = layer.grid_tiles.iter().chain(&layer.auto_layer_tiles);
+        if tiles.clone().next().is_none() {
+            continue;
+        }
+
+        let index = tilemaps.add_layer(layer.identifier.clone(), layer.visible);
+        let size = UVec2::new(layer.columns, layer.rows);
+        let atlas = &tilesets[tileset].1;
+        for tile in tiles {
+            let position = (Vec2::from(tile.px) / layer.grid_size).as_uvec2();
+            let tile_data = Tile::new(tile.t)
+                .with_flip_x(tile.f & 1 != 0)
+                .with_flip_y(tile.f & 2 != 0);
+            tilemaps.set_tile(tileset, atlas, index, size, position, tile_data)?;
+        }
+    }
+
+    let (tilemaps, tile_layers) = tilemaps.finish();
+
+    Ok(Level {
+        name: level.identifier.clone(),
+        bounds: pixel_rect(origin, Vec2::ZERO, Vec2::new(level.width, level.height)),
+        tilemaps,
+        tile_layers,
+        object_layers,
+        properties: properties(&level.fields),
+    })
+}
+
+fn properties(fields: &[LdtkField]) -> Properties {
+    fields
+        .iter()
+        .map(|field| {
+            (
+                field.identifier.clone(),
+                field_value(&field.kind, &field.value),
+            )
+        })
+        .collect()
+}
+
+fn field_value(kind: &str, value: &serde_json::Value) -> PropertyValue {
+    if let Some(item_kind) = kind
+        .strip_prefix("Array<")
+        .and_then(|kind| kind.strip_suffix('>'))
+    {
+        let items = value.as_array().map(Vec::as_slice).unwrap_or_default();
+        return PropertyValue::Array(
+            items
+                .iter()
+                .map(|item| field_value(item_kind, item))
+                .collect(),
+        );
+    }
+
+    match (kind, value) {
+        (_, serde_json::Value::Null) => PropertyValue::Null,
+        ("Color", serde_json::Value::String(color)) => Color::hex(color)
+            .map(PropertyValue::Color)
+            .unwrap_or(PropertyValue::Null),
+        ("Float", value) => value
+            .as_f64()
+            .map(PropertyValue::Float)
+            .unwrap_or(PropertyValue::Null),
+        ("Point", value) => match (value["cx"].as_f64(), value["cy"].as_f64()) {
+            (Some(x), Some(y)) => PropertyValue::Point(Vec2::new(x as f32, y as f32)),
+            _ => PropertyValue::Null,
+        },
+        ("EntityRef", value) => PropertyValue::from_json(&value["entityIid"]),
+        (_, value) => PropertyValue::from_json(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, Vec2};
+
+    use crate::{
+        arena::ArenaId,
+        levels::{tileset_atlas, PropertyValue},
+        tilemap::Tile,
+    };
+
+    use super::{build_level, field_value, LdtkProject};
+
+    const PROJECT: &str = r##"{
+        "defs": { "tilesets": [
+            { "uid": 1, "identifier": "Cavern", "relPath": "cavern.png", "__cWid": 4, "__cHei": 4,
+              "tileGridSize": 8, "spacing": 0, "padding": 0 }
+        ] },
+        "levels": [{
+            "identifier": "Level_0", "worldX": 64, "worldY": 0, "pxWid": 16, "pxHei": 16,
+            "fieldInstances": [{ "__identifier": "dark", "__type": "Bool", "__value": true }],
+            "layerInstances": [
+                { "__identifier": "Entities", "__type": "Entities", "__cWid": 2, "__cHei": 2,
+                  "__gridSize": 8, "__tilesetDefUid": null, "entityInstances": [
+                    { "__identifier": "Chest", "iid": "a1", "px": [8, 16], "__pivot": [0.5, 1],
+                      "width": 8, "height": 8, "fieldInstances": [
+                        { "__identifier": "loot", "__type": "Array<String>", "__value": ["gem", "key"] },
+                        { "__identifier": "target", "__type": "Point", "__value": { "cx": 1, "cy": 0 } }
+                    ] }
+                ] },
+                { "__identifier": "Walls", "__type": "IntGrid", "__cWid": 2, "__cHei": 2,
+                  "__gridSize": 8, "__tilesetDefUid": 1,
+                  "autoLayerTiles": [{ "px": [8, 8], "t": 5, "f": 1 }] },
+                { "__identifier": "Floor", "__type": "Tiles", "__cWid": 2, "__cHei": 2,
+                  "__gridSize": 8, "__tilesetDefUid": 1, "visible": false,
+                  "gridTiles": [{ "px": [0, 0], "t": 3, "f": 0 }] }
+            ]
+        }]
+    }"##;
+
+    #[test]
+    fn ldtk_levels_load_layers_bottom_up() {
+        let project: LdtkProject = serde_json::from_str(PROJECT).unwrap();
+        let atlas = tileset_atlas(ArenaId::first(), Vec2::splat(8.), 4, 4, 0., 0.);
+        let level = build_level(&project.levels[0], &[(1, atlas)]).unwrap();
+
+        assert_eq!(level.name, "Level_0");
+        assert_eq!(level.properties["dark"], PropertyValue::Bool(true));
+        assert_eq!(level.tile_layers, ["Floor", "Walls"]);
+
+        let tilemap = &level.tilemaps[0];
+        assert_eq!(tilemap.position().truncate(), Vec2::new(64., 0.));
+        assert!(!tilemap.layers()[0].visible);
+        assert_eq!(tilemap.get_tile(0, UVec2::new(0, 0)), Some(Tile::new(3)));
+        assert_eq!(
+            tilemap.get_tile(1, UVec2::new(1, 1)),
+            Some(Tile::new(5).with_flip_x(true))
+        );
+
+        let chest = &level.object_layers[0].objects[0];
+        assert_eq!(chest.kind, "Chest");
+        assert_eq!(chest.bounds.min, Vec2::new(68., -16.));
+        assert_eq!(chest.bounds.max, Vec2::new(76., -8.));
+        assert_eq!(
+            chest.properties["loot"],
+            PropertyValue::Array(vec![
+                PropertyValue::String("gem".to_string()),
+                PropertyValue::String("key".to_string())
+            ])
+        );
+        assert_eq!(chest.properties["target"], PropertyValue::Point(Vec2::X));
+    }
+
+    #[test]
+    fn null_fields_stay_null() {
+        assert_eq!(
+            field_value("Color", &serde_json::Value::Null),
+            PropertyValue::Null
+        );
+        assert_eq!(
+            field_value("Int", &serde_json::json!(4)),
+            PropertyValue::Int(4)
+        );
+    }
+}
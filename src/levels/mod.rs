@@ -0,0 +1,233 @@
+//! Levels made in the Tiled and LDtk editors, see [`Engine::load_tiled_map`] and [`Engine::load_ldtk_project`]
+//!
+//! Levels are placed in world units of one pixel with y up, so the top left of a Tiled map
+//! is at the origin and its tiles go down from there
+
+pub(crate) mod ldtk;
+pub(crate) mod tiled;
+
+use std::{collections::HashMap, path::Path};
+
+use glam::{UVec2, Vec2, Vec3};
+
+use crate::{
+    arena::ArenaId,
+    components::color::Color,
+    rect::Rect,
+    texture::Texture,
+    texture_atlas::TextureAtlas,
+    tilemap::{Tile, Tilemap},
+    Engine,
+};
+
+/// A custom property of a level or object
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Color(Color),
+    /// A grid cell, like an LDtk point field
+    Point(Vec2),
+    Array(Vec<PropertyValue>),
+    Null,
+}
+
+impl PropertyValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            PropertyValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Ints convert to floats too
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            PropertyValue::Float(value) => Some(*value),
+            PropertyValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Best effort conversion of a JSON value without a known type
+    pub(crate) fn from_json(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => PropertyValue::Null,
+            serde_json::Value::Bool(value) => PropertyValue::Bool(*value),
+            serde_json::Value::Number(number) => match number.as_i64() {
+                Some(value) => PropertyValue::Int(value),
+                None => PropertyValue::Float(number.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::String(value) => PropertyValue::String(value.clone()),
+            serde_json::Value::Array(values) => {
+                PropertyValue::Array(values.iter().map(PropertyValue::from_json).collect())
+            }
+            serde_json::Value::Object(_) => PropertyValue::String(value.to_string()),
+        }
+    }
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+/// An object of a Tiled object layer or an entity of an LDtk entity layer
+#[derive(Clone, Debug)]
+pub struct LevelObject {
+    /// The Tiled object id or the LDtk entity iid
+    pub id: String,
+    /// Empty for LDtk entities, they only have a [`LevelObject::kind`]
+    pub name: String,
+    /// The Tiled class or the LDtk entity identifier
+    pub kind: String,
+    /// World bounds, points have a zero size
+    pub bounds: Rect,
+    pub properties: Properties,
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub objects: Vec<LevelObject>,
+}
+
+#[derive(Debug)]
+pub struct Level {
+    pub name: String,
+    /// World bounds of the whole level
+    pub bounds: Rect,
+    /// A tilemap for each tileset the level uses, each with a layer per tile layer
+    /// Layer `i` of every tilemap is [`Level::tile_layers`]`[i]` and renders on layer `i`,
+    /// so later layers in the editor's order draw on top
+    pub tilemaps: Vec<Tilemap>,
+    pub tile_layers: Vec<String>,
+    pub object_layers: Vec<ObjectLayer>,
+    pub properties: Properties,
+}
+
+/// Where level files get their external files and tileset textures from
+pub(crate) trait LevelAssets {
+    fn load_file(&mut self, path: &Path) -> Result<Vec<u8>, String>;
+    fn load_texture(&mut self, path: &Path) -> ArenaId<Texture>;
+}
+
+impl LevelAssets for Engine {
+    fn load_file(&mut self, path: &Path) -> Result<Vec<u8>, String> {
+        Engine::load_file(self, path).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    fn load_texture(&mut self, path: &Path) -> ArenaId<Texture> {
+        Engine::load_texture(self, path)
+    }
+}
+
+/// Atlas of a tileset image cut into a grid
+pub(crate) fn tileset_atlas(
+    texture: ArenaId<Texture>,
+    tile_size: Vec2,
+    columns: usize,
+    rows: usize,
+    spacing: f32,
+    margin: f32,
+) -> TextureAtlas {
+    TextureAtlas::new_padding_offset(
+        texture,
+        tile_size,
+        columns,
+        rows,
+        Some(Vec2::splat(spacing)),
+        Some(Vec2::splat(margin)),
+    )
+}
+
+/// Collects the tiles of every tile layer into one tilemap per tileset
+pub(crate) struct LevelTilemaps {
+    position: Vec3,
+    layers: Vec<(String, bool)>,
+    tilemaps: Vec<Option<Tilemap>>,
+}
+
+impl LevelTilemaps {
+    pub(crate) fn new(position: Vec3, tileset_count: usize) -> Self {
+        Self {
+            position,
+            layers: Vec::new(),
+            tilemaps: (0..tileset_count).map(|_| None).collect(),
+        }
+    }
+
+    /// Adds a tile layer to every tilemap, returning its index
+    pub(crate) fn add_layer(&mut self, name: String, visible: bool) -> usize {
+        let index = self.layers.len();
+        self.layers.push((name, visible));
+        for tilemap in self.tilemaps.iter_mut().flatten() {
+            tilemap.add_layer(index as i32);
+            tilemap.layer_mut(index).visible = visible;
+        }
+
+        index
+    }
+
+    /// Places a tile of `tileset` on the tilemap of a layer `size` tiles big
+    pub(crate) fn set_tile(
+        &mut self,
+        tileset: usize,
+        atlas: &TextureAtlas,
+        layer: usize,
+        size: UVec2,
+        position: UVec2,
+        tile: Tile,
+    ) -> Result<(), String> {
+        let layers = &self.layers;
+        let tilemap = self.tilemaps[tileset].get_or_insert_with(|| {
+            let mut tilemap = Tilemap::new(atlas.clone(), size).with_position(self.position);
+            for (index, (_, visible)) in layers.iter().enumerate() {
+                tilemap.add_layer(index as i32);
+                tilemap.layer_mut(index).visible = *visible;
+            }
+            tilemap
+        });
+
+        if tilemap.size() != size {
+            return Err(format!(
+                "Layer {} uses a tileset with a different grid than another layer",
+                self.layers[layer].0
+            ));
+        }
+        if position.cmplt(size).all() {
+            tilemap.set_tile(layer, position, Some(tile));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> (Vec<Tilemap>, Vec<String>) {
+        (
+            self.tilemaps.into_iter().flatten().collect(),
+            self.layers.into_iter().map(|(name, _)| name).collect(),
+        )
+    }
+}
+
+/// World bounds of an area given in level pixels, y down from the top left `origin`
+pub(crate) fn pixel_rect(origin: Vec2, position: Vec2, size: Vec2) -> Rect {
+    let top_left = origin + position;
+    Rect::from_corners(
+        Vec2::new(top_left.x, -top_left.y),
+        Vec2::new(top_left.x + size.x, -(top_left.y + size.y)),
+    )
+}
@@ -0,0 +1,740 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use glam::{UVec2, Vec2, Vec3};
+use serde::Deserialize;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    components::color::Color,
+    texture_atlas::TextureAtlas,
+    tilemap::{Tile, TileRotation},
+};
+
+use super::{
+    pixel_rect, tileset_atlas, Level, LevelAssets, LevelObject, LevelTilemaps, ObjectLayer,
+    Properties, PropertyValue,
+};
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+/// Only used by hexagonal maps, ignored
+const ROTATED_HEXAGONAL: u32 = 0x1000_0000;
+
+#[derive(Deserialize, Debug, Default)]
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    layers: Vec<TiledLayer>,
+    #[serde(default)]
+    tilesets: Vec<TiledTileset>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct TiledTileset {
+    #[serde(default)]
+    firstgid: u32,
+    /// Path of an external tileset file
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: f32,
+    #[serde(default)]
+    tileheight: f32,
+    #[serde(default)]
+    columns: usize,
+    #[serde(default)]
+    tilecount: usize,
+    #[serde(default)]
+    spacing: f32,
+    #[serde(default)]
+    margin: f32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum TiledData {
+    Csv(Vec<u32>),
+    Encoded(String),
+}
+
+impl Default for TiledData {
+    fn default() -> Self {
+        TiledData::Csv(Vec::new())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TiledLayer {
+    /// `tilelayer`, `objectgroup`, `group` or `imagelayer`
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    #[serde(default)]
+    data: TiledData,
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[serde(default)]
+    objects: Vec<TiledObject>,
+    /// Layers of a group
+    #[serde(default)]
+    layers: Vec<TiledLayer>,
+    #[serde(default = "visible_by_default")]
+    visible: bool,
+    tintcolor: Option<String>,
+}
+
+fn visible_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct TiledObject {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type", alias = "class")]
+    kind: String,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    /// Tile objects are placed by their bottom left corner
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TiledProperty {
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+/// Loads a Tiled map saved as TMX, or as JSON with any other extension
+pub(crate) fn load_map(assets: &mut impl LevelAssets, path: &Path) -> Result<Level, String> {
+    let bytes = assets.load_file(path)?;
+    let map = if path.extension().is_some_and(|extension| extension == "tmx") {
+        map_from_xml(&parse_xml(&bytes)?)?
+    } else {
+        serde_json::from_slice(&bytes).map_err(|error| error.to_string())?
+    };
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    build_level(assets, map, path.parent().unwrap_or(Path::new("")), name)
+        .map_err(|error| format!("{}: {error}", path.display()))
+}
+
+fn build_level(
+    assets: &mut impl LevelAssets,
+    map: TiledMap,
+    directory: &Path,
+    name: String,
+) -> Result<Level, String> {
+    if map.infinite {
+        return Err("infinite maps aren't supported".to_string());
+    }
+
+    let mut tilesets = Vec::new();
+    for tileset in map.tilesets {
+        let firstgid = tileset.firstgid;
+        let (tileset, directory) = match &tileset.source {
+            Some(source) => {
+                let path = directory.join(source);
+                let bytes = assets.load_file(&path)?;
+                let tileset = if path.extension().is_some_and(|extension| extension == "tsx") {
+                    tileset_from_xml(&parse_xml(&bytes)?)?
+                } else {
+                    serde_json::from_slice(&bytes).map_err(|error| error.to_string())?
+                };
+                (
+                    tileset,
+                    path.parent().unwrap_or(Path::new("")).to_path_buf(),
+                )
+            }
+            None => (tileset, directory.to_path_buf()),
+        };
+
+        let Some(image) = &tileset.image else {
+            return Err(format!(
+                "tileset {} isn't a single image, image collections aren't supported",
+                tileset.name
+            ));
+        };
+        let texture = assets.load_texture(&directory.join(image));
+        let columns = tileset.columns.max(1);
+        let atlas = tileset_atlas(
+            texture,
+            Vec2::new(tileset.tilewidth, tileset.tileheight),
+            columns,
+            tileset.tilecount.div_ceil(columns),
+            tileset.spacing,
+            tileset.margin,
+        );
+        tilesets.push((firstgid, atlas));
+    }
+    // Gids belong to the tileset with the highest first gid below them
+    tilesets.sort_by_key(|(firstgid, _)| *firstgid);
+
+    let size = UVec2::new(map.width, map.height);
+    let tile_size = Vec2::new(map.tilewidth, map.tileheight);
+    let mut level = Level {
+        name,
+        bounds: pixel_rect(Vec2::ZERO, Vec2::ZERO, size.as_vec2() * tile_size),
+        tilemaps: Vec::new(),
+        tile_layers: Vec::new(),
+        object_layers: Vec::new(),
+        properties: properties(&map.properties),
+    };
+    let mut tilemaps = LevelTilemaps::new(Vec3::ZERO, tilesets.len());
+    add_layers(&mut level, &mut tilemaps, &tilesets, &map.layers)?;
+    (level.tilemaps, level.tile_layers) = tilemaps.finish();
+    for tilemap in &mut level.tilemaps {
+        tilemap.set_tile_size(tile_size);
+    }
+
+    Ok(level)
+}
+
+fn add_layers(
+    level: &mut Level,
+    tilemaps: &mut LevelTilemaps,
+    tilesets: &[(u32, TextureAtlas)],
+    layers: &[TiledLayer],
+) -> Result<(), String> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let gids = layer_gids(layer)?;
+                let tint = match &layer.tintcolor {
+                    Some(tint) => Some(parse_color(tint)?),
+                    None => None,
+                };
+                let size = UVec2::new(layer.width, layer.height);
+                let index = tilemaps.add_layer(layer.name.clone(), layer.visible);
+
+                for (position, gid) in gids.into_iter().enumerate() {
+                    let Some((gid, mut tile)) = tile_from_gid(gid) else {
+                        continue;
+                    };
+                    let Some(tileset) = tilesets.iter().rposition(|(firstgid, _)| *firstgid <= gid)
+                    else {
+                        continue;
+                    };
+                    let (firstgid, atlas) = &tilesets[tileset];
+                    tile.index = (gid - firstgid) as usize;
+                    tile.tint = tint;
+
+                    let position = UVec2::new(position as u32 % size.x, position as u32 / size.x);
+                    tilemaps.set_tile(tileset, atlas, index, size, position, tile)?;
+                }
+            }
+            "objectgroup" => level.object_layers.push(ObjectLayer {
+                name: layer.name.clone(),
+                objects: layer.objects.iter().map(level_object).collect(),
+            }),
+            "group" => add_layers(level, tilemaps, tilesets, &layer.layers)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn layer_gids(layer: &TiledLayer) -> Result<Vec<u32>, String> {
+    match (&layer.data, layer.encoding.as_deref(), &layer.compression) {
+        (_, _, Some(compression)) if !compression.is_empty() => Err(format!(
+            "layer {} uses {compression} compression, save it uncompressed or as CSV",
+            layer.name
+        )),
+        (TiledData::Csv(gids), _, _) => Ok(gids.clone()),
+        (TiledData::Encoded(data), Some("base64"), _) => {
+            let bytes = decode_base64(data)
+                .ok_or_else(|| format!("layer {} has invalid base64 data", layer.name))?;
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect())
+        }
+        (TiledData::Encoded(data), _, _) => data
+            .split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| format!("invalid tile {gid}")))
+            .collect(),
+    }
+}
+
+/// Splits the flip flags off a gid, None for empty tiles
+/// Tiled flips diagonally first and then horizontally and vertically, which maps onto
+/// flipping first and then rotating
+fn tile_from_gid(gid: u32) -> Option<(u32, Tile)> {
+    let flags = (
+        gid & FLIPPED_DIAGONALLY != 0,
+        gid & FLIPPED_HORIZONTALLY != 0,
+        gid & FLIPPED_VERTICALLY != 0,
+    );
+    let gid =
+        gid & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+    if gid == 0 {
+        return None;
+    }
+
+    let tile = match flags {
+        (false, flip_x, flip_y) => Tile::default().with_flip_x(flip_x).with_flip_y(flip_y),
+        (true, false, false) => Tile::default()
+            .with_flip_x(true)
+            .with_rotation(TileRotation::Clockwise270),
+        (true, true, false) => Tile::default().with_rotation(TileRotation::Clockwise90),
+        (true, false, true) => Tile::default().with_rotation(TileRotation::Clockwise270),
+        (true, true, true) => Tile::default()
+            .with_flip_x(true)
+            .with_rotation(TileRotation::Clockwise90),
+    };
+
+    Some((gid, tile))
+}
+
+fn level_object(object: &TiledObject) -> LevelObject {
+    let size = Vec2::new(object.width, object.height);
+    let mut position = Vec2::new(object.x, object.y);
+    if object.gid.is_some() {
+        position.y -= size.y;
+    }
+
+    LevelObject {
+        id: object.id.to_string(),
+        name: object.name.clone(),
+        kind: object.kind.clone(),
+        bounds: pixel_rect(Vec2::ZERO, position, size),
+        properties: properties(&object.properties),
+    }
+}
+
+fn properties(properties: &[TiledProperty]) -> Properties {
+    properties
+        .iter()
+        .map(|property| {
+            let value = match (property.kind.as_str(), &property.value) {
+                ("color", serde_json::Value::String(color)) => parse_color(color)
+                    .map(PropertyValue::Color)
+                    .unwrap_or(PropertyValue::Null),
+                ("float", value) => value
+                    .as_f64()
+                    .map(PropertyValue::Float)
+                    .unwrap_or(PropertyValue::Null),
+                (_, value) => PropertyValue::from_json(value),
+            };
+            (property.name.clone(), value)
+        })
+        .collect()
+}
+
+/// Tiled colors are `#AARRGGBB` or `#RRGGBB`
+fn parse_color(color: &str) -> Result<Color, String> {
+    let hex = color.trim_start_matches('#');
+    let hex = match hex.len() {
+        8 => format!("{}{}", &hex[2..], &hex[..2]),
+        _ => hex.to_string(),
+    };
+
+    Color::hex(hex).map_err(|_| format!("invalid color {color}"))
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for character in data.bytes().filter(|byte| !byte.is_ascii_whitespace()) {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+/// An element of a TMX or TSX file
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attribute<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        self.attributes
+            .get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid {name} {value} on <{}>", self.name))
+            })
+            .transpose()
+    }
+
+    fn attribute_or_default<T: FromStr + Default>(&self, name: &str) -> Result<T, String> {
+        Ok(self.attribute(name)?.unwrap_or_default())
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+fn parse_xml(bytes: &[u8]) -> Result<XmlElement, String> {
+    let mut stack = vec![XmlElement::default()];
+    for event in EventReader::new(bytes) {
+        match event.map_err(|error| error.to_string())? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(XmlElement {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|attribute| (attribute.name.local_name, attribute.value))
+                    .collect(),
+                ..Default::default()
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().expect("Unbalanced XML");
+                stack
+                    .last_mut()
+                    .expect("Unbalanced XML")
+                    .children
+                    .push(element);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| "empty XML document".to_string())
+}
+
+fn map_from_xml(map: &XmlElement) -> Result<TiledMap, String> {
+    Ok(TiledMap {
+        width: map.attribute_or_default("width")?,
+        height: map.attribute_or_default("height")?,
+        tilewidth: map.attribute_or_default("tilewidth")?,
+        tileheight: map.attribute_or_default("tileheight")?,
+        infinite: map.attribute::<u8>("infinite")? == Some(1),
+        layers: layers_from_xml(map)?,
+        tilesets: map
+            .children("tileset")
+            .map(tileset_from_xml)
+            .collect::<Result<_, _>>()?,
+        properties: properties_from_xml(map)?,
+    })
+}
+
+fn tileset_from_xml(tileset: &XmlElement) -> Result<TiledTileset, String> {
+    let image = tileset.children("image").next();
+
+    Ok(TiledTileset {
+        firstgid: tileset.attribute_or_default("firstgid")?,
+        source: tileset.attribute("source")?,
+        name: tileset.attribute_or_default("name")?,
+        image: image.and_then(|image| image.attributes.get("source").cloned()),
+        tilewidth: tileset.attribute_or_default("tilewidth")?,
+        tileheight: tileset.attribute_or_default("tileheight")?,
+        columns: tileset.attribute_or_default("columns")?,
+        tilecount: tileset.attribute_or_default("tilecount")?,
+        spacing: tileset.attribute_or_default("spacing")?,
+        margin: tileset.attribute_or_default("margin")?,
+    })
+}
+
+fn layers_from_xml(parent: &XmlElement) -> Result<Vec<TiledLayer>, String> {
+    let mut layers = Vec::new();
+    for element in &parent.children {
+        let kind = match element.name.as_str() {
+            "layer" => "tilelayer",
+            "objectgroup" => "objectgroup",
+            "group" => "group",
+            _ => continue,
+        };
+        let data = element.children("data").next();
+        let data_attribute = |name| -> Result<Option<String>, String> {
+            data.map_or(Ok(None), |data| data.attribute(name))
+        };
+        let encoding = data_attribute("encoding")?;
+        let data = match (data, &encoding) {
+            (Some(data), Some(_)) => TiledData::Encoded(data.text.trim().to_string()),
+            // Without an encoding every tile is a child element
+            (Some(data), None) => TiledData::Csv(
+                data.children("tile")
+                    .map(|tile| tile.attribute_or_default("gid"))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (None, _) => TiledData::default(),
+        };
+
+        layers.push(TiledLayer {
+            kind: kind.to_string(),
+            name: element.attribute_or_default("name")?,
+            width: element.attribute_or_default("width")?,
+            height: element.attribute_or_default("height")?,
+            data,
+            encoding: encoding.filter(|encoding| encoding != "csv"),
+            compression: data_attribute("compression")?,
+            objects: element
+                .children("object")
+                .map(object_from_xml)
+                .collect::<Result<_, _>>()?,
+            layers: layers_from_xml(element)?,
+            visible: element.attribute::<u8>("visible")? != Some(0),
+            tintcolor: element.attribute("tintcolor")?,
+        });
+    }
+
+    Ok(layers)
+}
+
+fn object_from_xml(object: &XmlElement) -> Result<TiledObject, String> {
+    Ok(TiledObject {
+        id: object.attribute_or_default("id")?,
+        name: object.attribute_or_default("name")?,
+        kind: match object.attribute("class")? {
+            Some(class) => class,
+            None => object.attribute_or_default("type")?,
+        },
+        x: object.attribute_or_default("x")?,
+        y: object.attribute_or_default("y")?,
+        width: object.attribute_or_default("width")?,
+        height: object.attribute_or_default("height")?,
+        gid: object.attribute("gid")?,
+        properties: properties_from_xml(object)?,
+    })
+}
+
+/// TMX property values are strings, typed like their JSON counterparts here
+fn properties_from_xml(parent: &XmlElement) -> Result<Vec<TiledProperty>, String> {
+    let Some(properties) = parent.children("properties").next() else {
+        return Ok(Vec::new());
+    };
+
+    properties
+        .children("property")
+        .map(|property| {
+            let kind: String = property.attribute_or_default("type")?;
+            // Multiline strings are stored as the element's text
+            let text = match property.attributes.get("value") {
+                Some(value) => value.clone(),
+                None => property.text.clone(),
+            };
+            let value = match kind.as_str() {
+                "bool" => serde_json::Value::Bool(text == "true"),
+                "int" | "object" => text
+                    .parse::<i64>()
+                    .map(Into::into)
+                    .map_err(|_| format!("invalid int {text}"))?,
+                "float" => text
+                    .parse::<f64>()
+                    .map(Into::into)
+                    .map_err(|_| format!("invalid float {text}"))?,
+                _ => serde_json::Value::String(text),
+            };
+
+            Ok(TiledProperty {
+                name: property.attribute_or_default("name")?,
+                kind,
+                value,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use glam::{UVec2, Vec2};
+
+    use crate::{
+        arena::ArenaId,
+        levels::{LevelAssets, PropertyValue},
+        texture::Texture,
+        tilemap::{Tile, TileRotation},
+    };
+
+    use super::{load_map, tile_from_gid, FLIPPED_DIAGONALLY, FLIPPED_HORIZONTALLY};
+
+    #[derive(Default)]
+    struct TestAssets {
+        files: HashMap<&'static str, &'static str>,
+        textures: Vec<String>,
+    }
+
+    impl LevelAssets for TestAssets {
+        fn load_file(&mut self, path: &Path) -> Result<Vec<u8>, String> {
+            let path = path.to_str().unwrap();
+            self.files
+                .get(path)
+                .map(|file| file.as_bytes().to_vec())
+                .ok_or_else(|| format!("missing {path}"))
+        }
+
+        fn load_texture(&mut self, path: &Path) -> ArenaId<Texture> {
+            self.textures.push(path.to_str().unwrap().to_string());
+            ArenaId::first()
+        }
+    }
+
+    const TMX: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="music" value="forest.ogg"/>
+  <property name="gravity" type="float" value="9.5"/>
+ </properties>
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="3" height="2">
+  <data encoding="csv">
+1,0,2,
+0,3,2147483649
+</data>
+ </layer>
+ <objectgroup id="2" name="Spawns">
+  <object id="4" name="Player" class="Spawn" x="16" y="8" width="8" height="4">
+   <properties>
+    <property name="lives" type="int" value="3"/>
+    <property name="tint" type="color" value="#ff00ff00"/>
+   </properties>
+  </object>
+ </objectgroup>
+</map>"##;
+
+    const TSX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="terrain" tilewidth="16" tileheight="16" spacing="1" tilecount="4" columns="2">
+ <image source="terrain.png" width="33" height="33"/>
+</tileset>"#;
+
+    #[test]
+    fn tmx_maps_load_tiles_and_objects() {
+        let mut assets = TestAssets::default();
+        assets.files.insert("maps/level.tmx", TMX);
+        assets.files.insert("maps/terrain.tsx", TSX);
+
+        let level = load_map(&mut assets, Path::new("maps/level.tmx")).unwrap();
+        assert_eq!(assets.textures, ["maps/terrain.png"]);
+        assert_eq!(level.name, "level");
+        assert_eq!(level.tile_layers, ["Ground"]);
+        assert_eq!(level.properties["gravity"].as_float(), Some(9.5));
+
+        let tilemap = &level.tilemaps[0];
+        assert_eq!(tilemap.size(), UVec2::new(3, 2));
+        assert_eq!(tilemap.atlas().textures[1].min, Vec2::new(17., 0.));
+        assert_eq!(tilemap.get_tile(0, UVec2::new(0, 0)), Some(Tile::new(0)));
+        assert_eq!(tilemap.get_tile(0, UVec2::new(1, 0)), None);
+        assert_eq!(tilemap.get_tile(0, UVec2::new(1, 1)), Some(Tile::new(2)));
+        assert_eq!(
+            tilemap.get_tile(0, UVec2::new(2, 1)),
+            Some(Tile::new(0).with_flip_x(true))
+        );
+
+        let player = &level.object_layers[0].objects[0];
+        assert_eq!(
+            (player.name.as_str(), player.kind.as_str()),
+            ("Player", "Spawn")
+        );
+        assert_eq!(player.bounds.min, Vec2::new(16., -12.));
+        assert_eq!(player.bounds.max, Vec2::new(24., -8.));
+        assert_eq!(player.properties["lives"], PropertyValue::Int(3));
+        assert!(matches!(player.properties["tint"], PropertyValue::Color(_)));
+    }
+
+    #[test]
+    fn json_maps_match_tmx() {
+        let json = r#"{
+            "width": 2, "height": 1, "tilewidth": 8, "tileheight": 8,
+            "tilesets": [{ "firstgid": 1, "image": "tiles.png", "tilewidth": 8, "tileheight": 8,
+                "columns": 4, "tilecount": 8 }],
+            "layers": [
+                { "type": "group", "name": "Group", "layers": [
+                    { "type": "tilelayer", "name": "Walls", "width": 2, "height": 1,
+                      "data": "AgAAAAAAAAA=", "encoding": "base64" }
+                ] },
+                { "type": "objectgroup", "name": "Items", "objects": [
+                    { "id": 7, "type": "Coin", "x": 0, "y": 8, "width": 8, "height": 8, "gid": 3 }
+                ] }
+            ]
+        }"#;
+        let mut assets = TestAssets::default();
+        assets.files.insert("level.tmj", json);
+
+        let level = load_map(&mut assets, Path::new("level.tmj")).unwrap();
+        assert_eq!(level.tile_layers, ["Walls"]);
+        assert_eq!(
+            level.tilemaps[0].get_tile(0, UVec2::new(0, 0)),
+            Some(Tile::new(1))
+        );
+        assert_eq!(level.tilemaps[0].tile_size(), Vec2::splat(8.));
+
+        // Tile objects are placed by their bottom left corner
+        let coin = &level.object_layers[0].objects[0];
+        assert_eq!((coin.id.as_str(), coin.kind.as_str()), ("7", "Coin"));
+        assert_eq!(coin.bounds.max, Vec2::new(8., 0.));
+    }
+
+    #[test]
+    fn gid_flags_become_flips_and_rotations() {
+        assert_eq!(tile_from_gid(0), None);
+        assert_eq!(
+            tile_from_gid(5 | FLIPPED_DIAGONALLY | FLIPPED_HORIZONTALLY),
+            Some((5, Tile::default().with_rotation(TileRotation::Clockwise90)))
+        );
+        assert_eq!(
+            tile_from_gid(5 | FLIPPED_DIAGONALLY),
+            Some((
+                5,
+                Tile::default()
+                    .with_flip_x(true)
+                    .with_rotation(TileRotation::Clockwise270)
+            ))
+        );
+    }
+}
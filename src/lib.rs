@@ -6,6 +6,7 @@ pub mod components;
 pub mod file_system_watcher;
 pub mod input;
 pub mod internal_image;
+pub mod levels;
 pub mod renderer;
 pub mod time;
 pub mod utils;
@@ -28,10 +29,12 @@ use egui_winit_platform::{Platform, PlatformDescriptor};
 pub use glam as math;
 pub use renderer::*;
 
-use glam::UVec2;
+use glam::{UVec2, Vec2, Vec3};
 use input::InputManager;
 // use old_renderer::{ui::Ui, Renderer};
-use renderer::{camera::Camera, Renderer};
+use renderer::screenshot::CaptureStage;
+use renderer::{camera::Camera, camera_view::CameraView, Renderer};
+use std::path::PathBuf;
 use time::Time;
 use window::{WindowAbstraction, WindowDescriptor, WindowEngineAbstraction};
 
@@ -64,6 +67,8 @@ impl Default for EditorState {
 
 pub struct Engine {
     pub camera: Camera,
+    /// Extra cameras with their own viewports and layers, used instead of [`Engine::camera`] when there are any
+    pub cameras: Arena<CameraView>,
     window: Window,
     pub renderer: Renderer,
     pub input: InputManager,
@@ -75,6 +80,10 @@ pub struct Engine {
     #[cfg(feature = "egui")]
     pub egui_platform: Platform,
     pub editor_state: EditorState,
+    /// Whether screenshots include the egui windows
+    pub screenshot_stage: CaptureStage,
+    /// Saved when the next frame is rendered
+    pending_screenshots: Vec<PathBuf>,
 }
 
 impl Engine {
@@ -95,12 +104,19 @@ impl Engine {
             window_descriptor.render_resolution.map(|v| v.as_vec2()),
         );
 
-        let renderer = pollster::block_on(Renderer::new(
+        let mut renderer = pollster::block_on(Renderer::new(
             &window.window,
             (window_size.x, window_size.y),
         ))
         .unwrap();
 
+        if let (Some(render_resolution), Some(pixel_perfect)) = (
+            window_descriptor.render_resolution,
+            window_descriptor.pixel_perfect,
+        ) {
+            renderer.set_pixel_perfect(render_resolution, pixel_perfect);
+        }
+
         #[cfg(feature = "egui")]
         let egui_platform = Platform::new(PlatformDescriptor {
             physical_width: window_size.x as u32,
@@ -111,6 +127,7 @@ impl Engine {
 
         Self {
             camera,
+            cameras: Arena::new(),
             window,
             renderer,
             input,
@@ -122,6 +139,8 @@ impl Engine {
             #[cfg(feature = "egui")]
             egui_platform,
             editor_state: EditorState::default(),
+            screenshot_stage: CaptureStage::default(),
+            pending_screenshots: Vec::new(),
         }
     }
 
@@ -150,20 +169,44 @@ impl Engine {
                     let delta_seconds = self.time.raw_delta_seconds_f64();
                     ui.label(format!("Frame time: {}", (delta_seconds * 1000.0) as i32));
                     ui.label(format!("FPS: {}", (1. / delta_seconds) as i32));
+                    ui.collapsing("Render stats", |ui| {
+                        if !self.renderer.gpu_timing_supported() {
+                            ui.label("No GPU pass timings, the adapter lacks timestamp queries");
+                        }
+                        self.renderer.render_stats().inspect("render_stats", ui);
+                    });
                     ui.checkbox(&mut self.editor_state.paused, "Pause Game");
                     ui.add(
                         egui::Slider::new(&mut self.editor_state.delta_time_multiplier, 0.0..=2.0)
                             .text("Delta time multiplier"),
                     );
                     self.camera.inspect_mut("test", ui);
+                    ui.collapsing("Post processing", |ui| {
+                        for pass in self.renderer.post_process_passes_mut() {
+                            ui.checkbox(&mut pass.enabled, pass.name.as_str());
+                        }
+                    });
                 });
         }
 
+        #[cfg(feature = "egui")]
+        if self.renderer.shader_errors().next().is_some() {
+            egui::Window::new("Shader errors").show(&self.egui_ctx(), |ui| {
+                for (_, error) in self.renderer.shader_errors() {
+                    ui.colored_label(egui::Color32::LIGHT_RED, error);
+                }
+            });
+        }
+
         let delta = self.time.delta_seconds() * self.editor_state.delta_time_multiplier;
 
         if !self.editor_state.paused {
             game.update(self, delta);
         }
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        if self.just_pressed(input::Input::F12) {
+            self.capture_screenshot(renderer::screenshot::timestamped_screenshot_path());
+        }
         self.clear_inputs();
 
         #[cfg(feature = "egui")]
@@ -171,16 +214,28 @@ impl Engine {
         #[cfg(feature = "egui")]
         let paint_jobs = self.egui_platform.context().tessellate(full_output.shapes);
 
+        if !self.pending_screenshots.is_empty() {
+            self.renderer.request_frame_capture();
+        }
         let mut ctx = self.renderer.begin();
         game.render(&mut self.renderer, delta);
-        self.renderer.render(
-            &mut ctx,
-            Some(Color::hex("#6b6ab3").unwrap().as_rgba_linear().into()),
-            &self.camera,
-        );
+        let clear_color = Some(Color::hex("#6b6ab3").unwrap().as_rgba_linear());
+        if self.cameras.is_empty() {
+            self.renderer.render(&mut ctx, clear_color, &self.camera);
+        } else {
+            self.renderer
+                .render_cameras(&mut ctx, clear_color, self.cameras.as_slice());
+        }
+        self.renderer.apply_post_processing(&mut ctx);
+        if self.screenshot_stage == CaptureStage::BeforeEgui {
+            self.save_screenshots(&mut ctx);
+        }
 
         self.renderer
             .render_egui(&mut ctx, &full_output.textures_delta, &paint_jobs);
+        if self.screenshot_stage == CaptureStage::AfterEgui {
+            self.save_screenshots(&mut ctx);
+        }
         self.renderer.end_frame(ctx);
         self.renderer.end_egui(full_output.textures_delta);
         self.time.update();
@@ -196,9 +251,44 @@ impl Engine {
         }
     }
 
+    /// Saves the next rendered frame as a PNG at `path`, see [`Engine::screenshot_stage`]
+    /// F12 saves one into [`renderer::screenshot::SCREENSHOT_DIR`] in debug builds
+    pub fn capture_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.pending_screenshots.push(path.into());
+    }
+
+    fn save_screenshots(&mut self, render_context: &mut RenderContext) {
+        if self.pending_screenshots.is_empty() {
+            return;
+        }
+
+        let frame = self
+            .renderer
+            .capture_frame(render_context)
+            .map_err(|error| error.message);
+        for path in self.pending_screenshots.drain(..) {
+            let saved = frame
+                .as_ref()
+                .map_err(Clone::clone)
+                .and_then(|image| image.save_png(&path));
+            if let Err(error) = saved {
+                eprintln!("Failed to save screenshot {}: {error}", path.display());
+            }
+        }
+    }
+
     pub fn get_viewport(&self) -> (u32, u32) {
         self.renderer.get_viewport_size()
     }
+
+    /// Converts a window position, like the mouse position, to a world position using the engine camera
+    /// Takes the pixel perfect scaling into account
+    pub fn viewport_to_world_position(&self, viewport_position: Vec2) -> Option<Vec3> {
+        self.camera.viewport_to_world_position(
+            self.renderer.viewport_to_render_position(viewport_position),
+            self.renderer.get_render_size(),
+        )
+    }
 }
 
 impl Default for Engine {
@@ -1,119 +1,406 @@
+use std::{ops::Range, sync::Arc};
+
 use crate::{
     arena::ArenaId,
-    mesh::{Indices, Mesh},
+    material_params::MaterialParams,
+    mesh::{Indices, Mesh, MeshAttribute, VertexData},
     pipeline::Pipeline,
 };
-use wgpu::{util::DeviceExt, BindGroup, Buffer};
+use glam::Vec3;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BufferSlice, Sampler,
+};
+
+use super::{texture::Texture, Renderer};
 
-use super::Renderer;
+/// Texture, sampler and whether the layout is filterable
+pub(crate) type TextureBindGroupKey = (ArenaId<Texture>, ArenaId<Sampler>, bool);
 
 #[derive(Debug)]
 pub(crate) struct DrawCall {
-    pub(crate) vertex_buffer: Buffer,
-    pub(crate) index_buffer: Buffer,
+    /// Byte range in the renderer vertex buffer
+    pub(crate) vertex_range: Range<u64>,
+    /// Byte range in the renderer index buffer
+    pub(crate) index_range: Range<u64>,
     pub(crate) vert_len: u32,
     pub(crate) indices_len: u32,
+    pub(crate) instance_count: u32,
     pub(crate) material_handle: ArenaId<Pipeline>,
+    pub(crate) texture_bind_group: Option<TextureBindGroupKey>,
     pub(crate) bind_groups: Vec<BindGroup>,
+    pub(crate) params: Option<ArenaId<MaterialParams>>,
     pub(crate) index_format: wgpu::IndexFormat,
+    pub(crate) cast_shadows: bool,
+    /// Drawn from these instead of the renderer buffers when set
+    pub(crate) buffers: Option<Arc<MeshBuffers>>,
+}
+
+impl DrawCall {
+    pub(crate) fn vertex_slice<'a>(&'a self, vertex_buffer: &'a wgpu::Buffer) -> BufferSlice<'a> {
+        match &self.buffers {
+            Some(buffers) => buffers.vertex_buffer.slice(..),
+            None => vertex_buffer.slice(self.vertex_range.clone()),
+        }
+    }
+
+    pub(crate) fn index_slice<'a>(&'a self, index_buffer: &'a wgpu::Buffer) -> BufferSlice<'a> {
+        match &self.buffers {
+            Some(buffers) => buffers.index_buffer.slice(..),
+            None => index_buffer.slice(self.index_range.clone()),
+        }
+    }
+}
+
+/// Vertices and indices of a mesh that stay on the GPU between frames, see [`Renderer::upload_mesh`]
+#[derive(Debug)]
+pub(crate) struct MeshBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vert_len: u32,
+    indices_len: u32,
+    index_format: wgpu::IndexFormat,
 }
 
 impl Renderer {
-    pub(crate) fn prepare_mesh_batch(&mut self) -> Vec<DrawCall> {
-        let mut meshes = self.meshes.drain(0..).collect::<Vec<Mesh>>();
-
-        // meshes.sort_by(|a, b| {
-        //     a.sort_value
-        //         .partial_cmp(&b.sort_value)
-        //         .unwrap_or(std::cmp::Ordering::Equal)
-        // });
-
-        let mut current_batch_texture_handle = ArenaId::default();
-        let mut current_material_handle_id = ArenaId::default();
-        let mut batches: Vec<Mesh> = Vec::new();
-
-        for mesh in meshes {
-            if current_batch_texture_handle == mesh.texture_handle.unwrap_or(ArenaId::default())
-                && current_material_handle_id == mesh.material_handle
-                && mesh.batch
-            {
-                let length = batches.len();
+    pub(crate) fn prepare_mesh_batch(&mut self, camera_position: Vec3) -> Vec<DrawCall> {
+        let meshes = std::mem::take(&mut self.meshes);
+        self.frame_stats.meshes += meshes.len() as u32;
+        let meshes = self.sort_meshes(meshes, camera_position);
 
-                let current_mesh = &mut batches[length - 1];
-                let vert_count = current_mesh.vertices.len();
-                let indices = mesh.indices.add(vert_count);
+        let mut batches = merge_batches(meshes);
+        // wgpu can't bind an empty buffer slice, so batches with nothing to draw are dropped
+        batches.retain(|batch| {
+            if batch.buffers.is_some() {
+                return true;
+            }
+            let instanced = self
+                .materials
+                .get(batch.material_handle)
+                .is_some_and(|pipeline| pipeline.material.instanced());
+            if instanced {
+                !batch.instances.is_empty()
+            } else {
+                !batch.vertices.is_empty()
+            }
+        });
 
-                current_mesh.concat(mesh.vertices, indices);
+        // Every batch is packed into one upload per buffer
+        let mut vertex_data: Vec<u8> = Vec::new();
+        let mut index_data: Vec<u8> = Vec::new();
+        let mut ranges = Vec::with_capacity(batches.len());
+
+        for batch in &batches {
+            let material = &self
+                .materials
+                .get(batch.material_handle)
+                .expect("Cant find material for batch")
+                .material;
+
+            let vertex_start = vertex_data.len() as u64;
+            if let Some(buffers) = &batch.buffers {
+                let index_start = index_data.len() as u64;
+                ranges.push((vertex_start..vertex_start, index_start..index_start));
+                self.frame_stats.vertices += buffers.vert_len;
+                self.frame_stats.indices += buffers.indices_len;
+                continue;
+            }
+            if material.instanced() {
+                vertex_data.extend_from_slice(bytemuck::cast_slice(&batch.instances));
             } else {
-                current_batch_texture_handle = mesh.texture_handle.unwrap_or(ArenaId::default());
-                current_material_handle_id = mesh.material_handle;
-                batches.push(mesh);
+                // Vertices are laid out to match the pipeline's vertex buffer layout
+                let attributes = material
+                    .vertex_attributes()
+                    .into_iter()
+                    .collect::<Vec<MeshAttribute>>();
+                batch.vertices.write_bytes(&attributes, &mut vertex_data);
             }
+
+            let index_start = index_data.len() as u64;
+            index_data.extend_from_slice(batch.indices.cast_slice());
+            let index_end = index_data.len() as u64;
+            // Keeps the next batch aligned for u32 indices and buffer writes
+            index_data.resize(
+                wgpu::util::align_to(index_end, wgpu::COPY_BUFFER_ALIGNMENT) as usize,
+                0,
+            );
+
+            ranges.push((
+                vertex_start..vertex_data.len() as u64,
+                index_start..index_end,
+            ));
+
+            self.frame_stats.vertices += batch.vertices.len() as u32;
+            self.frame_stats.indices += batch.indices.len() as u32;
+            self.frame_stats.instances += batch.instances.len() as u32;
         }
+        self.frame_stats.uploaded_bytes += (vertex_data.len() + index_data.len()) as u64;
+
+        let vertex_offset = self
+            .vertex_buffer
+            .write(&self.device, &self.queue, &vertex_data);
+        let index_offset = self
+            .index_buffer
+            .write(&self.device, &self.queue, &index_data);
 
         batches
             .iter()
-            .map(|batch| {
-                let index_format = batch.indices.wgpu_index_format();
-                let vertex_buffer =
-                    self.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Vertex Buffer"),
-                            contents: &batch
-                                .vertices
-                                .iter()
-                                .map(|v| v.get_bytes())
-                                .flatten()
-                                .collect::<Vec<u8>>(),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        });
-
-                let index_buffer =
-                    self.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Index Buffer"),
-                            contents: batch.indices.cast_slice(),
-                            usage: wgpu::BufferUsages::INDEX,
-                        });
-
-                let mut bind_groups = Vec::default();
-
-                let material = self
-                    .materials
-                    .get(batch.material_handle)
-                    .expect("Cant find material for batch");
-
-                if let Some(texture_handle) = batch.texture_handle {
-                    if material.material.has_texture() {
-                        let texture = self.textures.get(texture_handle).unwrap();
-                        let sampler = self.samplers.get(texture.sampler).unwrap();
-                        let texture_bind_group = texture.create_bind_group(
-                            &self.device,
-                            &material.render_pipeline.get_bind_group_layout(1),
-                            &sampler,
-                        );
-
-                        bind_groups.push(texture_bind_group);
+            .zip(ranges)
+            .map(|(batch, (vertex_range, index_range))| {
+                let (vert_len, indices_len, index_format) = match &batch.buffers {
+                    Some(buffers) => (buffers.vert_len, buffers.indices_len, buffers.index_format),
+                    None => (
+                        batch.vertices.len() as u32,
+                        batch.indices.len() as u32,
+                        batch.indices.wgpu_index_format(),
+                    ),
+                };
+
+                let (has_texture, filterable) = {
+                    let material = self
+                        .materials
+                        .get(batch.material_handle)
+                        .expect("Cant find material for batch");
+
+                    (
+                        material.material.has_texture(),
+                        material.material.filterable_texture(),
+                    )
+                };
+
+                let texture_bind_group = match batch.texture_handle {
+                    Some(texture_handle) if has_texture => {
+                        Some(self.cache_texture_bind_group(texture_handle, filterable))
                     }
+                    _ => None,
+                };
+
+                let material = self.materials.get(batch.material_handle).unwrap();
+                let params = batch.params.or(material.default_params);
+                if let Some(params) = params {
+                    self.cache_params_bind_group(params);
                 }
 
-                let mut mat_bind_groups =
+                let material = self.materials.get(batch.material_handle).unwrap();
+                let bind_groups =
                     material
                         .material
-                        .get_bind_groups(&batch, &self, &material.render_pipeline);
-
-                bind_groups.append(&mut mat_bind_groups);
+                        .get_bind_groups(batch, self, &material.render_pipeline);
 
                 DrawCall {
-                    vertex_buffer,
-                    index_buffer,
+                    vertex_range: vertex_offset + vertex_range.start
+                        ..vertex_offset + vertex_range.end,
+                    index_range: index_offset + index_range.start..index_offset + index_range.end,
+                    texture_bind_group,
                     bind_groups,
-                    vert_len: batch.vertices.len() as _,
-                    indices_len: batch.indices.len() as _,
+                    params,
+                    vert_len,
+                    instance_count: batch.instances.len() as _,
+                    indices_len,
                     material_handle: batch.material_handle,
                     index_format,
+                    cast_shadows: batch.cast_shadows,
+                    buffers: batch.buffers.clone(),
                 }
             })
             .collect()
     }
+
+    /// Moves the vertices and indices into buffers of the mesh's own, laid out for its material,
+    /// so pushing it again doesn't upload them every frame. For meshes that rarely change, like tilemap chunks
+    /// Clones of the mesh share the buffers, and it's no longer batched with its neighbours
+    pub(crate) fn upload_mesh(&mut self, mesh: &mut Mesh) {
+        let material = &self
+            .materials
+            .get(mesh.material_handle)
+            .expect("Cant find material for mesh")
+            .material;
+        debug_assert!(!material.instanced(), "Instanced meshes can't be uploaded");
+
+        let attributes = material
+            .vertex_attributes()
+            .into_iter()
+            .collect::<Vec<MeshAttribute>>();
+        let mut vertex_data = Vec::new();
+        mesh.vertices.write_bytes(&attributes, &mut vertex_data);
+
+        let vertex_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Mesh Vertex Buffer"),
+            contents: &vertex_data,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Mesh Index Buffer"),
+            contents: mesh.indices.cast_slice(),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        self.frame_stats.uploaded_bytes +=
+            (vertex_data.len() + mesh.indices.cast_slice::<u8>().len()) as u64;
+
+        mesh.buffers = Some(Arc::new(MeshBuffers {
+            vertex_buffer,
+            index_buffer,
+            vert_len: mesh.vertices.len() as u32,
+            indices_len: mesh.indices.len() as u32,
+            index_format: mesh.indices.wgpu_index_format(),
+        }));
+        mesh.vertices = VertexData::default();
+        mesh.indices = Indices::default();
+        mesh.batch = false;
+    }
+
+    /// Creates the bind group for the texture the first time it's drawn, and reuses it after that
+    pub(crate) fn cache_texture_bind_group(
+        &mut self,
+        texture_handle: ArenaId<Texture>,
+        filterable: bool,
+    ) -> TextureBindGroupKey {
+        let texture = self
+            .textures
+            .get(texture_handle)
+            .expect("Mesh is missing texture");
+        let key = (texture_handle, texture.sampler, filterable);
+
+        if !self.texture_bind_groups.contains_key(&key) {
+            let sampler = self.samplers.get(texture.sampler).unwrap();
+            let bind_group = texture.create_bind_group(
+                &self.device,
+                self.texture_bind_group_layout(filterable),
+                sampler,
+            );
+            self.texture_bind_groups.insert(key, bind_group);
+        }
+
+        key
+    }
+
+    /// Drops cached bind groups for a texture that has been replaced or removed
+    pub(crate) fn invalidate_texture_bind_groups(&mut self, texture_handle: ArenaId<Texture>) {
+        self.texture_bind_groups
+            .retain(|(handle, ..), _| *handle != texture_handle);
+        self.invalidate_params_bind_groups(texture_handle);
+    }
+}
+
+/// Neighbours in the sorted queue that share a texture and material are merged,
+/// meshes are never moved past each other so the sort order is kept
+/// Batches past 65536 vertices are switched to u32 indices by [`crate::mesh::Indices::add`]
+fn merge_batches(meshes: Vec<Mesh>) -> Vec<Mesh> {
+    let mut batches: Vec<Mesh> = Vec::new();
+
+    for mut mesh in meshes {
+        match batches.last_mut() {
+            Some(current_mesh)
+                if current_mesh.batch
+                    && mesh.batch
+                    && current_mesh.texture_handle == mesh.texture_handle
+                    && current_mesh.material_handle == mesh.material_handle
+                    && current_mesh.cast_shadows == mesh.cast_shadows
+                    && current_mesh.params == mesh.params =>
+            {
+                let vert_count = current_mesh.vertices.len();
+                let indices = mesh.indices.add(vert_count);
+
+                current_mesh.concat(mesh.vertices, indices);
+                current_mesh.instances.append(&mut mesh.instances);
+            }
+            _ => batches.push(mesh),
+        }
+    }
+
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::merge_batches;
+    use crate::{
+        arena::ArenaId,
+        camera::Camera,
+        components::color::Color,
+        headless::test_renderer,
+        mesh::{Indices, Mesh, MeshBuilder},
+        sprite::Sprite,
+        transform::Transform,
+    };
+
+    const SPRITES: usize = 20_000;
+
+    fn quads() -> Vec<Mesh> {
+        (0..SPRITES)
+            .map(|index| {
+                MeshBuilder::quad(
+                    Vec2::ONE,
+                    Transform::from_position(Vec3::new(index as f32, 0., 0.)),
+                )
+                .build()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn large_batches_use_u32_indices() {
+        let batches = merge_batches(quads());
+        assert_eq!(batches.len(), 1);
+
+        let batch = &batches[0];
+        assert_eq!(batch.vertices.len(), SPRITES * 4);
+        assert_eq!(batch.indices.len(), SPRITES * 6);
+        assert!(matches!(batch.indices, Indices::U32(_)));
+
+        // Every quad still points at its own vertices
+        let indices: Vec<usize> = batch.indices.iter().collect();
+        for (sprite, quad) in indices.chunks(6).enumerate() {
+            assert!(quad.iter().all(|index| index / 4 == sprite));
+        }
+    }
+
+    #[test]
+    fn small_batches_keep_u16_indices() {
+        let batches = merge_batches(quads().into_iter().take(1000).collect());
+        assert_eq!(batches.len(), 1);
+        assert!(matches!(batches[0].indices, Indices::U16(_)));
+    }
+
+    #[test]
+    fn large_batch_draws_last_sprite() {
+        let mut renderer = test_renderer((8, 8));
+
+        // The default material keeps the sprites on the vertex path
+        let material = renderer.material_map.default;
+        let sprite = |color: Color| Sprite {
+            color: color.as_rgba_f32(),
+            custom_size: Some(Vec2::splat(8.)),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        // 20k quads is past the u16 index range, the last one only shows if the batch indices are right
+        let mut ctx = renderer.begin();
+        for _ in 0..20_000 {
+            renderer.draw_sprite(&sprite(Color::BLUE), Transform::IDENTITY);
+        }
+        renderer.draw_sprite(&sprite(Color::GREEN), Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn empty_mesh_is_skipped() {
+        let mut renderer = test_renderer((4, 4));
+
+        let mut ctx = renderer.begin();
+        renderer.push(MeshBuilder::new().build());
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        assert_eq!(renderer.render_stats().draw_calls, 0);
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+    }
 }
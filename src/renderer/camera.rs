@@ -1,7 +1,8 @@
 #[cfg(feature = "egui")]
 use egui_inspect::EguiInspect;
 use glam::{Mat4, Quat, Vec2, Vec3};
-use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, Device};
+
+use super::rect::Rect;
 
 pub const DEFAULT_ORTHO_CAMERA_DEPTH: f32 = 1000.0;
 
@@ -131,14 +132,17 @@ impl Camera {
         }
     }
 
-    pub(crate) fn create_bind_group(
-        &self,
-        device: &Device,
-        viewport_size: (u32, u32),
-        bind_group_layout: &BindGroupLayout,
-    ) -> BindGroup {
+    pub(crate) fn create_uniform(&self, viewport_size: (u32, u32)) -> CameraUniform {
         let projection = self.compute_projection_matrix(viewport_size);
+        let view_projection = projection * self.view_matrix(viewport_size).inverse();
 
+        CameraUniform {
+            view_proj: view_projection.to_cols_array_2d(),
+        }
+    }
+
+    /// The camera's transform, from view space to world space
+    pub(crate) fn view_matrix(&self, viewport_size: (u32, u32)) -> Mat4 {
         let additive = if let Projection::Orthographic { origin, .. } = self.projection {
             if let CameraOrigin::TopLeft = origin {
                 Vec3::new(
@@ -153,32 +157,11 @@ impl Camera {
             Vec3::ZERO
         };
 
-        let view = Mat4::from_scale_rotation_translation(
+        Mat4::from_scale_rotation_translation(
             Vec3::splat(1.),
             self.rotation,
             self.position + additive,
-        );
-        let inverse_view = view.inverse();
-        let view_projection = projection * inverse_view;
-
-        let camera_uniform = CameraUniform {
-            view_proj: view_projection.to_cols_array_2d(),
-        };
-
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
-            contents: bytemuck::cast_slice(&[camera_uniform]),
-        });
-
-        device.create_bind_group(&wgpu::BindGroupDescriptor {
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("Camera bind group"),
-            layout: &bind_group_layout,
-        })
+        )
     }
 
     pub(crate) fn compute_projection_matrix(&self, viewport_size: (u32, u32)) -> Mat4 {
@@ -225,6 +208,24 @@ impl Camera {
         }
     }
 
+    /// The world area an orthographic camera sees on the z = 0 plane, bounding it when rotated
+    /// None for other projections
+    pub fn visible_rect_2d(&self, viewport_size: (u32, u32)) -> Option<Rect> {
+        let Projection::Orthographic { .. } = self.projection else {
+            return None;
+        };
+
+        let ndc_to_world = self.view_matrix(viewport_size)
+            * self.compute_projection_matrix(viewport_size).inverse();
+        let corners = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+            .map(|(x, y)| ndc_to_world.project_point3(Vec3::new(x, y, 0.5)).truncate());
+
+        Some(corners.into_iter().fold(
+            Rect::from_corners(corners[0], corners[0]),
+            |rect, corner| Rect::from_corners(rect.min.min(corner), rect.max.max(corner)),
+        ))
+    }
+
     pub fn viewport_to_world_position(
         &self,
         viewport_position: Vec2,
@@ -0,0 +1,388 @@
+use std::{collections::BTreeSet, mem};
+
+use glam::{UVec2, Vec2};
+use wgpu::{include_wgsl, RenderPipeline, VertexStepMode};
+
+use crate::{components::color::Color, rect::Rect};
+
+use super::{camera::Camera, render_state::DEPTH_FORMAT, RenderContext, Renderer};
+
+/// Which layers a camera renders, any `i32` layer can be included or excluded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerMask {
+    layers: BTreeSet<i32>,
+    /// When set, `layers` lists the excluded layers instead of the included ones
+    inverted: bool,
+}
+
+impl LayerMask {
+    pub const ALL: LayerMask = LayerMask {
+        layers: BTreeSet::new(),
+        inverted: true,
+    };
+    pub const NONE: LayerMask = LayerMask {
+        layers: BTreeSet::new(),
+        inverted: false,
+    };
+
+    pub fn layer(layer: i32) -> Self {
+        Self::NONE.with(layer)
+    }
+
+    pub fn with(self, layer: i32) -> Self {
+        self.set(layer, true)
+    }
+
+    pub fn without(self, layer: i32) -> Self {
+        self.set(layer, false)
+    }
+
+    pub fn contains(&self, layer: i32) -> bool {
+        self.layers.contains(&layer) != self.inverted
+    }
+
+    fn set(mut self, layer: i32, included: bool) -> Self {
+        if included != self.inverted {
+            self.layers.insert(layer);
+        } else {
+            self.layers.remove(&layer);
+        }
+        self
+    }
+}
+
+impl Default for LayerMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// What a camera does to its viewport before drawing
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClearMode {
+    Color(Color),
+    /// Draws over what earlier cameras rendered, like a UI camera over the world
+    /// The depth buffer is still cleared
+    #[default]
+    None,
+}
+
+/// A camera rendering into part of the frame
+pub struct CameraView {
+    pub camera: Camera,
+    /// Normalized rect of the frame to draw into, (0, 0) is the top left and (1, 1) the bottom right
+    pub viewport: Rect,
+    pub clear_mode: ClearMode,
+    /// Cameras with a lower order render first
+    pub order: i32,
+    pub layer_mask: LayerMask,
+    /// Ambient level of the 2D lighting, `None` leaves this camera unlit
+    pub ambient_light_2d: Option<Color>,
+}
+
+impl CameraView {
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            camera,
+            viewport: Rect::new(Vec2::ONE),
+            clear_mode: ClearMode::default(),
+            order: 0,
+            layer_mask: LayerMask::ALL,
+            ambient_light_2d: None,
+        }
+    }
+
+    pub fn with_viewport(mut self, viewport: Rect) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_clear_mode(mut self, clear_mode: ClearMode) -> Self {
+        self.clear_mode = clear_mode;
+        self
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_layer_mask(mut self, layer_mask: LayerMask) -> Self {
+        self.layer_mask = layer_mask;
+        self
+    }
+
+    pub fn with_ambient_light_2d(mut self, ambient: Color) -> Self {
+        self.ambient_light_2d = Some(ambient);
+        self
+    }
+
+    /// Returns the offset and size in pixels, from the top left, of the viewport in a frame of `frame_size`
+    pub fn pixel_viewport(&self, frame_size: UVec2) -> (Vec2, Vec2) {
+        let frame_size = frame_size.as_vec2();
+        let min = (self.viewport.min.clamp(Vec2::ZERO, Vec2::ONE) * frame_size).round();
+        let max = (self.viewport.max.clamp(Vec2::ZERO, Vec2::ONE) * frame_size).round();
+
+        (min, (max - min).max(Vec2::ZERO))
+    }
+
+    /// Converts a window position (bottom left origin) to a world position, if it's inside this camera's viewport
+    pub fn viewport_to_world_position(
+        &self,
+        frame_position: Vec2,
+        frame_size: (u32, u32),
+    ) -> Option<glam::Vec3> {
+        let (offset, size) = self.pixel_viewport(UVec2::from(frame_size));
+        // The viewport offset is from the top left, flip it to match the position
+        let offset = Vec2::new(offset.x, frame_size.1 as f32 - offset.y - size.y);
+        let position = frame_position - offset;
+
+        if position.cmplt(Vec2::ZERO).any() || position.cmpge(size).any() {
+            return None;
+        }
+
+        self.camera
+            .viewport_to_world_position(position, (size.x as u32, size.y as u32))
+    }
+}
+
+/// A pass limited to a camera viewport
+pub(crate) struct ViewportPass {
+    pub(crate) offset: Vec2,
+    pub(crate) size: Vec2,
+    pub(crate) clear_mode: ClearMode,
+    /// Lights the viewport with the 2D lights after drawing
+    pub(crate) ambient_light_2d: Option<Color>,
+}
+
+impl Renderer {
+    /// Renders the queued meshes once for each camera, in [`CameraView::order`]
+    /// Every camera only draws the meshes on its layers, into its own viewport
+    /// The whole frame is cleared to `clear_color` first
+    pub fn render_cameras(
+        &mut self,
+        render_context: &mut RenderContext,
+        clear_color: Option<Color>,
+        cameras: &[CameraView],
+    ) {
+        let pixel_perfect_target = self.pixel_perfect.as_ref().map(|p| p.target);
+        let target_view;
+        let (view, depth_texture_handle, frame_size) = match pixel_perfect_target {
+            Some(target) => {
+                target_view = self
+                    .textures
+                    .get(target.texture)
+                    .unwrap()
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (&target_view, target.depth_texture, target.size)
+            }
+            None => (
+                &render_context.view,
+                self.depth_texture_handle,
+                UVec2::from(self.get_viewport_size()),
+            ),
+        };
+
+        let mut cameras: Vec<&CameraView> = cameras.iter().collect();
+        cameras.sort_by_key(|camera_view| camera_view.order);
+
+        let meshes = mem::take(&mut self.meshes);
+        let normal_meshes = mem::take(&mut self.normal_meshes_2d);
+        let mut clear_color = clear_color;
+
+        for camera_view in cameras {
+            let (offset, size) = camera_view.pixel_viewport(frame_size);
+            if size.min_element() < 1. {
+                continue;
+            }
+
+            self.meshes = meshes
+                .iter()
+                .filter(|mesh| camera_view.layer_mask.contains(mesh.layer))
+                .cloned()
+                .collect();
+            self.normal_meshes_2d = normal_meshes
+                .iter()
+                .filter(|mesh| camera_view.layer_mask.contains(mesh.layer))
+                .cloned()
+                .collect();
+
+            self.render_viewport(
+                &mut render_context.command_encoder,
+                view,
+                depth_texture_handle,
+                clear_color.take(),
+                &camera_view.camera,
+                Some(ViewportPass {
+                    offset,
+                    size,
+                    clear_mode: camera_view.clear_mode,
+                    ambient_light_2d: camera_view.ambient_light_2d,
+                }),
+            );
+        }
+
+        // Still clears the frame when no camera could draw
+        if clear_color.is_some() {
+            self.render_viewport(
+                &mut render_context.command_encoder,
+                view,
+                depth_texture_handle,
+                clear_color,
+                &Camera::orthographic(),
+                None,
+            );
+        }
+
+        if pixel_perfect_target.is_some() {
+            self.upscale_pixel_perfect(render_context);
+        }
+    }
+
+    /// Pipeline drawing a fullscreen triangle with the color from the vertex buffer
+    /// Keyed by whether it writes color, and whether the pass has a depth attachment
+    pub(crate) fn clear_pipeline(&mut self, write_color: bool, depth: bool) -> &RenderPipeline {
+        let format = self.surface_config.format;
+        let msaa_samples = self.msaa_samples;
+        let device = &self.device;
+
+        self.clear_pipelines
+            .entry((write_color, depth))
+            .or_insert_with(|| {
+                let shader =
+                    device.create_shader_module(include_wgsl!("./default_shaders/clear.wgsl"));
+
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Clear Pipeline Layout"),
+                    bind_group_layouts: &[],
+                    push_constant_ranges: &[],
+                });
+
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Clear Pipeline"),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vertex",
+                        buffers: &[wgpu::VertexBufferLayout {
+                            array_stride: mem::size_of::<[f32; 4]>() as u64,
+                            step_mode: VertexStepMode::Instance,
+                            attributes: &wgpu::vertex_attr_array![0 => Float32x4],
+                        }],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fragment",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: if write_color {
+                                wgpu::ColorWrites::ALL
+                            } else {
+                                wgpu::ColorWrites::empty()
+                            },
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: depth.then_some(wgpu::DepthStencilState {
+                        format: DEPTH_FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: msaa_samples,
+                        ..Default::default()
+                    },
+                    multiview: None,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, Vec2};
+
+    use super::{CameraView, ClearMode, LayerMask};
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        rect::Rect, sprite::Sprite, transform::Transform,
+    };
+
+    #[test]
+    fn layer_mask_contains() {
+        let mask = LayerMask::layer(2).with(5);
+        assert!(mask.contains(2) && mask.contains(5));
+        assert!(!mask.contains(0));
+        assert!(!mask.without(5).contains(5));
+        assert!(LayerMask::ALL.contains(-3));
+        assert!(!LayerMask::ALL.without(-3).contains(-3));
+    }
+
+    #[test]
+    fn layer_mask_outside_64_layers() {
+        let mask = LayerMask::layer(0);
+        assert!(mask.contains(0));
+        assert!(!mask.contains(64) && !mask.contains(128) && !mask.contains(-64));
+
+        let mask = LayerMask::ALL.without(64);
+        assert!(mask.contains(0) && !mask.contains(64));
+    }
+
+    #[test]
+    fn split_screen_viewports() {
+        let left = CameraView::new(Camera::orthographic())
+            .with_viewport(Rect::from_corners(Vec2::ZERO, Vec2::new(0.5, 1.)));
+        let right = CameraView::new(Camera::orthographic())
+            .with_viewport(Rect::from_corners(Vec2::new(0.5, 0.), Vec2::ONE));
+        let frame_size = UVec2::new(1281, 720);
+
+        let (left_offset, left_size) = left.pixel_viewport(frame_size);
+        let (right_offset, right_size) = right.pixel_viewport(frame_size);
+
+        assert_eq!(left_offset, Vec2::ZERO);
+        // No gap or overlap between the halves
+        assert_eq!(left_offset.x + left_size.x, right_offset.x);
+        assert_eq!(right_offset.x + right_size.x, 1281.);
+        assert_eq!(left_size.y, 720.);
+    }
+
+    #[test]
+    fn split_screen_cameras() {
+        let mut renderer = test_renderer((8, 8));
+
+        let cameras = [
+            CameraView::new(Camera::orthographic())
+                .with_viewport(Rect::from_corners(Vec2::ZERO, Vec2::new(0.5, 1.)))
+                .with_clear_mode(ClearMode::Color(Color::BLUE))
+                .with_layer_mask(LayerMask::NONE),
+            CameraView::new(Camera::orthographic())
+                .with_viewport(Rect::from_corners(Vec2::new(0.5, 0.), Vec2::ONE))
+                .with_layer_mask(LayerMask::layer(1)),
+        ];
+        let sprite = Sprite {
+            color: Color::GREEN.as_rgba_f32(),
+            custom_size: Some(Vec2::splat(8.)),
+            layer: 1,
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render_cameras(&mut ctx, Some(Color::RED), &cameras);
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [0, 0, 255, 255]
+            } else {
+                [0, 255, 0, 255]
+            };
+            assert_eq!(pixel, expected);
+        }
+    }
+}
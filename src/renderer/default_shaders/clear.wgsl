@@ -0,0 +1,24 @@
+// Clears a camera viewport, the render pass viewport limits the fullscreen triangle
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>
+};
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) color: vec4<f32>
+) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: VertexOutput;
+    // Z of 1 resets the depth to the far plane
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,120 @@
+struct Light {
+    // xy is the position, z the radius and w the falloff
+    position_radius: vec4<f32>,
+    color: vec4<f32>,
+    // xy is the spot direction, z the height and w the source radius
+    direction_height: vec4<f32>,
+    // Cosines of the inner and outer angle, point lights cover every direction
+    cone: vec4<f32>,
+};
+
+struct Lighting {
+    inverse_view_projection: mat4x4<f32>,
+    ambient: vec4<f32>,
+    light_count: u32,
+    occluder_count: u32,
+    lights: array<Light, 32>,
+    // Start and end of each occluder edge
+    occluders: array<vec4<f32>, 256>,
+};
+@group(0) @binding(0)
+var<uniform> lighting: Lighting;
+@group(0) @binding(1)
+var normal_map: texture_2d<f32>;
+
+const SHADOW_SAMPLES: i32 = 8;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec2<f32>,
+};
+
+// A triangle covering the whole viewport
+@vertex
+fn vertex(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let clip = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    let world = lighting.inverse_view_projection * clip;
+
+    var out: VertexOutput;
+    out.clip_position = clip;
+    out.world_position = world.xy / world.w;
+    return out;
+}
+
+fn cross_2d(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+// Whether the segment from `start` to `end` crosses the edge from `a` to `b`
+fn crosses(start: vec2<f32>, end: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> bool {
+    let ray = end - start;
+    let edge = b - a;
+    let denominator = cross_2d(ray, edge);
+    if abs(denominator) < 1e-6 {
+        return false;
+    }
+
+    let t = cross_2d(a - start, edge) / denominator;
+    let u = cross_2d(a - start, ray) / denominator;
+    return t > 0.0 && t < 1.0 && u >= 0.0 && u <= 1.0;
+}
+
+// How much of the light's source can be seen from the point, softening the shadow edges
+fn visibility(point: vec2<f32>, light: Light) -> f32 {
+    let center = light.position_radius.xy;
+    let to_point = point - center;
+    if lighting.occluder_count == 0u || dot(to_point, to_point) < 1e-6 {
+        return 1.0;
+    }
+
+    let side = normalize(vec2<f32>(-to_point.y, to_point.x)) * light.direction_height.w;
+    let samples = select(1, SHADOW_SAMPLES, light.direction_height.w > 0.0);
+    var visible = 0.0;
+    for (var i = 0; i < samples; i++) {
+        // Spread across the source's width as seen from the point
+        let spread = select(0.0, (f32(i) + 0.5) / f32(samples) * 2.0 - 1.0, samples > 1);
+        let source = center + side * spread;
+
+        var blocked = false;
+        for (var j = 0u; j < lighting.occluder_count; j++) {
+            let edge = lighting.occluders[j];
+            if crosses(point, source, edge.xy, edge.zw) {
+                blocked = true;
+                break;
+            }
+        }
+        visible += select(1.0, 0.0, blocked);
+    }
+
+    return visible / f32(samples);
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Alpha is how much of the pixel is covered by normal mapped sprites
+    let normal_sample = textureLoad(normal_map, vec2<i32>(in.clip_position.xy), 0);
+    let normal = normalize(normal_sample.xyz / max(normal_sample.a, 0.0001) * 2.0 - 1.0);
+
+    var light = lighting.ambient.rgb;
+    for (var i = 0u; i < lighting.light_count; i++) {
+        let point = lighting.lights[i];
+        let offset = point.position_radius.xy - in.world_position;
+        let distance = length(offset);
+        let radius = point.position_radius.z;
+        if distance >= radius {
+            continue;
+        }
+
+        let attenuation = pow(1.0 - distance / radius, point.position_radius.w);
+        let to_light = offset / max(distance, 0.0001);
+        let spot = smoothstep(point.cone.y, point.cone.x, dot(-to_light, point.direction_height.xy));
+        let surface_to_light = normalize(vec3<f32>(offset, point.direction_height.z));
+        let diffuse = mix(1.0, max(dot(normal, surface_to_light), 0.0), normal_sample.a);
+
+        light += point.color.rgb * attenuation * spot * diffuse * visibility(in.world_position, point);
+    }
+
+    // Multiplies what was drawn
+    return vec4<f32>(light, 0.0);
+}
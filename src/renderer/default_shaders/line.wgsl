@@ -1,8 +1,4 @@
-struct View {
-    view_proj: mat4x4<f32>
-};
-@group(0) @binding(0)
-var<uniform> view: View;
+#import nimbus::view
 
 
 struct VertexInput {
@@ -0,0 +1,42 @@
+#import nimbus::view
+#import nimbus::lighting
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) normal: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) normal: vec3<f32>,
+};
+
+@vertex
+fn vertex(
+    obj_vert: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    // Models are already transformed into world space
+    out.clip_position = view.view_proj * vec4<f32>(obj_vert.position, 1.0);
+    out.uv = obj_vert.uv;
+    out.color = obj_vert.color;
+    out.world_position = obj_vert.position;
+    out.normal = obj_vert.normal;
+    return out;
+}
+
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base = textureSample(obj_texture, obj_sampler, in.uv) * in.color;
+    return vec4<f32>(base.rgb * lighting_at(in.world_position, in.normal), base.a);
+}
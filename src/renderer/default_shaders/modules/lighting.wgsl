@@ -0,0 +1,107 @@
+struct Light {
+    // xyz is the position, w the range
+    position_range: vec4<f32>,
+    color: vec4<f32>,
+    direction: vec4<f32>,
+    // Cosines of the inner and outer angle, point lights cover every direction
+    cone: vec4<f32>,
+};
+
+struct Lighting {
+    light_view_projections: array<mat4x4<f32>, 4>,
+    cascade_splits: vec4<f32>,
+    camera_position: vec4<f32>,
+    camera_forward: vec4<f32>,
+    light_direction: vec4<f32>,
+    light_color: vec4<f32>,
+    ambient: vec4<f32>,
+    cascade_count: u32,
+    pcf_radius: u32,
+    receiver_bias: f32,
+    shadow_texel_size: f32,
+    light_count: u32,
+    lights: array<Light, 16>,
+};
+
+// Bound as the group after the texture, like `LitMaterial` does
+@group(2) @binding(0)
+var<uniform> lighting: Lighting;
+@group(2) @binding(1)
+var shadow_map: texture_depth_2d_array;
+@group(2) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+// 1 is fully lit, 0 fully in shadow
+fn shadow_factor(world_position: vec3<f32>) -> f32 {
+    let view_depth = dot(world_position - lighting.camera_position.xyz, lighting.camera_forward.xyz);
+
+    var cascade = 0u;
+    while cascade < lighting.cascade_count && view_depth > lighting.cascade_splits[cascade] {
+        cascade += 1u;
+    }
+    if cascade >= lighting.cascade_count {
+        return 1.0;
+    }
+
+    let light_clip = lighting.light_view_projections[cascade] * vec4<f32>(world_position, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    let uv = light_ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || light_ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let depth = light_ndc.z - lighting.receiver_bias;
+    let radius = i32(lighting.pcf_radius);
+    var lit = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        for (var y = -radius; y <= radius; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * lighting.shadow_texel_size;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, i32(cascade), depth);
+        }
+    }
+
+    let taps = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / taps;
+}
+
+const SPECULAR: f32 = 0.5;
+const SHININESS: f32 = 32.0;
+
+// Diffuse plus specular from one light, `to_light` and `to_camera` are normalized
+fn blinn_phong(normal: vec3<f32>, has_normal: bool, to_light: vec3<f32>, to_camera: vec3<f32>) -> f32 {
+    if !has_normal {
+        return 1.0;
+    }
+    let diffuse = max(dot(normal, to_light), 0.0);
+    let half_dir = normalize(to_light + to_camera);
+    let specular = select(0.0, pow(max(dot(normal, half_dir), 0.0), SHININESS) * SPECULAR, diffuse > 0.0);
+    return diffuse + specular;
+}
+
+// Light reaching a point from every light, `normal_in` is zero for meshes without normals
+fn lighting_at(world_position: vec3<f32>, normal_in: vec3<f32>) -> vec3<f32> {
+    // Meshes without normals get zero normals, and are lit evenly instead
+    let has_normal = dot(normal_in, normal_in) > 0.0;
+    let normal = select(vec3<f32>(0.0, 1.0, 0.0), normalize(normal_in), has_normal);
+    let to_camera = normalize(lighting.camera_position.xyz - world_position);
+
+    var light = lighting.ambient.rgb;
+    light += lighting.light_color.rgb
+        * blinn_phong(normal, has_normal, -lighting.light_direction.xyz, to_camera)
+        * shadow_factor(world_position);
+
+    for (var i = 0u; i < lighting.light_count; i++) {
+        let point = lighting.lights[i];
+        let offset = point.position_range.xyz - world_position;
+        let distance = length(offset);
+        let to_light = offset / max(distance, 0.0001);
+
+        let falloff = clamp(1.0 - pow(distance / point.position_range.w, 4.0), 0.0, 1.0);
+        let attenuation = falloff * falloff / (distance * distance + 1.0);
+        let spot = smoothstep(point.cone.y, point.cone.x, dot(-to_light, point.direction.xyz));
+
+        light += point.color.rgb * attenuation * spot * blinn_phong(normal, has_normal, to_light, to_camera);
+    }
+
+    return light;
+}
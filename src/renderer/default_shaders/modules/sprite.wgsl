@@ -0,0 +1,44 @@
+#import nimbus::view
+
+// Layout of materials with the default position, uv and color attributes
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+#ifdef VERTEX_COLOR
+    @location(2) color: vec4<f32>,
+#endif
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>
+};
+
+#ifdef HAS_TEXTURE
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+#endif
+
+fn sprite_vertex(vertex: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(vertex.position, 1.0);
+    out.uv = vertex.uv;
+#ifdef VERTEX_COLOR
+    out.color = vertex.color;
+#else
+    out.color = vec4<f32>(1.0);
+#endif
+    return out;
+}
+
+// Vertex color times the texture, when the material has one
+fn sprite_color(in: VertexOutput) -> vec4<f32> {
+#ifdef HAS_TEXTURE
+    return in.color * textureSample(obj_texture, obj_sampler, in.uv);
+#else
+    return in.color;
+#endif
+}
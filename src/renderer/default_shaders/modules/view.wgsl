@@ -0,0 +1,5 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
@@ -0,0 +1,44 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    // Columns of the matrix rotating and flipping the normals into world space
+    @location(2) color: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) rotation: vec4<f32>
+};
+
+@vertex
+fn vertex(
+    obj_vert: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(obj_vert.position, 1.0);
+    out.uv = obj_vert.uv;
+    out.rotation = obj_vert.color;
+    return out;
+}
+
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(obj_texture, obj_sampler, in.uv);
+    let normal = texel.xyz * 2.0 - 1.0;
+    let rotation = mat2x2<f32>(in.rotation.xy, in.rotation.zw);
+    let world_normal = normalize(vec3<f32>(rotation * normal.xy, normal.z));
+
+    return vec4<f32>(world_normal * 0.5 + 0.5, texel.a);
+}
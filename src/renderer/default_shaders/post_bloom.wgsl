@@ -0,0 +1,67 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>
+};
+
+@vertex
+fn vertex(
+    obj_vert: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(obj_vert.position, 1.0);
+    out.uv = obj_vert.uv;
+    out.color = obj_vert.color;
+    return out;
+}
+
+// Output of the previous pass
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+const THRESHOLD: f32 = 0.7;
+const INTENSITY: f32 = 0.8;
+const RADIUS: f32 = 6.0;
+const SAMPLES: i32 = 12;
+const TAU: f32 = 6.28318530718;
+
+fn bright_part(color: vec3<f32>) -> vec3<f32> {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return color * smoothstep(THRESHOLD, 1.0, luminance);
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(obj_texture));
+    let base = textureSample(obj_texture, obj_sampler, in.uv);
+
+    // Two rings of taps, the inner ring weighted more to fake a gaussian falloff
+    var bloom = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var i = 0; i < SAMPLES; i++) {
+        let angle = f32(i) / f32(SAMPLES) * TAU;
+        let direction = vec2<f32>(cos(angle), sin(angle)) * texel;
+
+        let inner = textureSample(obj_texture, obj_sampler, in.uv + direction * RADIUS * 0.5).rgb;
+        let outer = textureSample(obj_texture, obj_sampler, in.uv + direction * RADIUS).rgb;
+        bloom += bright_part(inner) * 2.0 + bright_part(outer);
+        total_weight += 3.0;
+    }
+    bloom = (bloom + bright_part(base.rgb) * 3.0) / (total_weight + 3.0);
+
+    return vec4<f32>(base.rgb + bloom * INTENSITY, base.a);
+}
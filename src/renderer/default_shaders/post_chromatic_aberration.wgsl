@@ -0,0 +1,48 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>
+};
+
+@vertex
+fn vertex(
+    obj_vert: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(obj_vert.position, 1.0);
+    out.uv = obj_vert.uv;
+    out.color = obj_vert.color;
+    return out;
+}
+
+// Output of the previous pass
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+const STRENGTH: f32 = 0.006;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Red and blue are pushed apart towards the edges of the screen
+    let offset = (in.uv - vec2<f32>(0.5)) * STRENGTH;
+
+    let red = textureSample(obj_texture, obj_sampler, in.uv + offset).r;
+    let green_alpha = textureSample(obj_texture, obj_sampler, in.uv).ga;
+    let blue = textureSample(obj_texture, obj_sampler, in.uv - offset).b;
+
+    return vec4<f32>(red, green_alpha.x, blue, green_alpha.y);
+}
@@ -0,0 +1,55 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>
+};
+
+@vertex
+fn vertex(
+    obj_vert: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(obj_vert.position, 1.0);
+    out.uv = obj_vert.uv;
+    out.color = obj_vert.color;
+    return out;
+}
+
+// Output of the previous pass
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+const CURVATURE: f32 = 0.15;
+const SCANLINE_DARKNESS: f32 = 0.25;
+const PI: f32 = 3.14159265359;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Barrel distortion, bulges the middle of the screen like a CRT tube
+    let centered = in.uv - vec2<f32>(0.5);
+    let uv = in.uv + centered * dot(centered, centered) * CURVATURE;
+
+    let color = textureSample(obj_texture, obj_sampler, uv);
+
+    let lines = f32(textureDimensions(obj_texture).y);
+    let scanline = sin(uv.y * lines * PI) * 0.5 + 0.5;
+    let shaded = color.rgb * mix(1.0 - SCANLINE_DARKNESS, 1.0, scanline);
+
+    // Everything bent outside the screen is black
+    let inside = all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
+    return select(vec4<f32>(0.0, 0.0, 0.0, 1.0), vec4<f32>(shaded, color.a), inside);
+}
@@ -0,0 +1,78 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>
+};
+
+@vertex
+fn vertex(
+    obj_vert: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(obj_vert.position, 1.0);
+    out.uv = obj_vert.uv;
+    out.color = obj_vert.color;
+    return out;
+}
+
+// Output of the previous pass
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+// 16x16x16 LUT stored as 16 slices side by side, blue picks the slice
+// Binding 0 is the empty params uniform
+@group(2) @binding(1)
+var lut_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var lut_sampler: sampler;
+
+const LUT_SIZE: f32 = 16.0;
+
+fn to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb <= vec3<f32>(0.04045));
+}
+
+fn lut_uv(color: vec3<f32>, slice: f32) -> vec2<f32> {
+    return vec2<f32>(
+        (slice * LUT_SIZE + color.r + 0.5) / (LUT_SIZE * LUT_SIZE),
+        (color.g + 0.5) / LUT_SIZE,
+    );
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(obj_texture, obj_sampler, in.uv);
+
+    // LUTs are authored in sRGB
+    let scaled = clamp(to_srgb(color.rgb), vec3<f32>(0.0), vec3<f32>(1.0)) * (LUT_SIZE - 1.0);
+    let slice = floor(scaled.b);
+    let next_slice = min(slice + 1.0, LUT_SIZE - 1.0);
+
+    let low = textureSample(lut_texture, lut_sampler, lut_uv(scaled, slice)).rgb;
+    let high = textureSample(lut_texture, lut_sampler, lut_uv(scaled, next_slice)).rgb;
+    let graded = mix(low, high, scaled.b - slice);
+
+    return vec4<f32>(to_linear(graded), color.a);
+}
@@ -0,0 +1,62 @@
+struct View {
+    view_proj: mat4x4<f32>
+};
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct InstanceInput {
+    @location(0) model_0: vec4<f32>,
+    @location(1) model_1: vec4<f32>,
+    @location(2) model_2: vec4<f32>,
+    @location(3) model_3: vec4<f32>,
+    // min xy, max xy
+    @location(4) uv_rect: vec4<f32>,
+    @location(5) color: vec4<f32>,
+    @location(6) size: vec2<f32>,
+    @location(7) anchor: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>
+};
+
+// Two counter clockwise triangles, matching QUAD_INDICES
+var<private> QUAD_CORNERS: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(1.0, 1.0),
+);
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) vertex_index: u32,
+    instance: InstanceInput
+) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let corner = QUAD_CORNERS[vertex_index];
+    let position = (corner - vec2<f32>(0.5, 0.5) - instance.anchor) * instance.size;
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * model * vec4<f32>(position, 0.0, 1.0);
+    // Texture v goes down while the quad goes up
+    out.uv = mix(instance.uv_rect.xy, instance.uv_rect.zw, vec2<f32>(corner.x, 1.0 - corner.y));
+    out.color = instance.color;
+    return out;
+}
+
+@group(1) @binding(0)
+var obj_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var obj_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(obj_texture, obj_sampler, in.uv);
+
+    return in.color * color;
+}
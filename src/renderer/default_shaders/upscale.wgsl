@@ -0,0 +1,43 @@
+struct Upscale {
+    source_size: vec2<f32>,
+    scale: vec2<f32>,
+    filter_mode: u32,
+};
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> upscale: Upscale;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>
+};
+
+// Fullscreen triangle, no vertex buffer needed
+@vertex
+fn vertex(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = in.uv * upscale.source_size;
+    var sample_texel = floor(texel) + 0.5;
+
+    // Sharp bilinear, only blend across the edge of each scaled pixel
+    if upscale.filter_mode == 1u {
+        let region_range = 0.5 - 0.5 / upscale.scale;
+        let center_dist = fract(texel) - 0.5;
+        let f = (center_dist - clamp(center_dist, -region_range, region_range)) * upscale.scale + 0.5;
+        sample_texel = floor(texel) + f;
+    }
+
+    return textureSample(source_texture, source_sampler, sample_texel / upscale.source_size);
+}
@@ -1,218 +1,474 @@
-use glam::Vec2;
-use wgpu::util::DeviceExt;
-use wgpu::{include_spirv_raw, Sampler};
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3, Vec3Swizzles};
+use wgpu::{
+    include_wgsl, CommandEncoder, RenderPipeline, Sampler, TextureFormat, TextureView,
+    VertexBufferLayout, VertexStepMode,
 };
 
-use crate::{texture, ArenaId, Texture};
+use crate::arena::ArenaId;
 
-pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+use super::{
+    batching::DrawCall,
+    camera::{Camera, Projection},
+    dynamic_buffer::DynamicBuffer,
+    lighting::LightingUniform,
+    mesh::{get_attribute_layout, MeshAttribute},
+    pipeline::Pipeline,
+    render_stats::PassStats,
+    Renderer,
+};
 
-pub fn create_depth_texture_non_comparison_sampler(
-    device: &wgpu::Device,
-    config: &wgpu::SurfaceConfiguration,
-    sampler: ArenaId<Sampler>,
-) -> Texture {
-    let size = wgpu::Extent3d {
-        width: config.width,
-        height: config.height,
-        depth_or_array_layers: 1,
-    };
-    let desc = wgpu::TextureDescriptor {
-        label: Some("test"),
-        size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: DEPTH_FORMAT,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-        view_formats: &[DEPTH_FORMAT],
-    };
-    let texture = device.create_texture(&desc);
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-    Texture {
-        dimensions: Vec2::new(config.width as f32, config.height as f3),
-        texture,
-        view,
-        sampler,
+pub const SHADOW_MAP_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+pub const MAX_CASCADES: usize = 4;
+
+/// Shadow quality settings, the cascades split the camera's view so nearby shadows get more texels
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Width and height of every cascade's shadow map
+    pub map_size: u32,
+    /// View distance where each cascade ends, closest first, at most [`MAX_CASCADES`]
+    /// Nothing past the last split is shadowed
+    pub cascade_splits: Vec<f32>,
+    /// Constant depth bias applied while rendering the shadow map
+    pub depth_bias: i32,
+    /// Depth bias scaled by the slope of the caster
+    pub slope_scale_bias: f32,
+    /// Subtracted from the receiver's depth before comparing, fights shadow acne
+    pub receiver_bias: f32,
+    /// PCF kernel radius in texels, 1 takes a 3x3 grid of filtered samples
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            map_size: 1024,
+            cascade_splits: vec![8., 25., 80.],
+            depth_bias: 2,
+            slope_scale_bias: 2.,
+            receiver_bias: 0.001,
+            pcf_radius: 1,
+        }
     }
 }
 
-struct DepthPass {
-    texture: texture::Texture,
-    layout: wgpu::BindGroupLayout,
-    bind_group: wgpu::BindGroup,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_depth_indices: u32,
-    render_pipeline: wgpu::RenderPipeline,
+/// Depth texture array with a layer per cascade, and what's needed to render and sample it
+pub(crate) struct ShadowMap {
+    pub(crate) settings: ShadowSettings,
+    texture: wgpu::Texture,
+    layer_views: Vec<TextureView>,
+    pub(crate) array_view: TextureView,
+    pub(crate) sampler: ArenaId<Sampler>,
+    pub(crate) lighting_buffer: wgpu::Buffer,
+    /// Lighting uniforms are copied into the lighting buffer from here inside the encoder,
+    /// so every camera rendered in a frame reads its own cascades
+    lighting_staging: DynamicBuffer,
+    /// Depth only pipelines, keyed by the material they read the vertex layout from
+    pipelines: HashMap<ArenaId<Pipeline>, Option<RenderPipeline>>,
 }
 
-impl DepthPass {
-    fn new(
+impl ShadowMap {
+    pub(crate) fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        settings: ShadowSettings,
         sampler: ArenaId<Sampler>,
     ) -> Self {
-        let texture = create_depth_texture_non_comparison_sampler(device, config, sampler);
-
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Depth Pass Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    count: None,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    count: None,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
-            label: Some("depth_pass.bind_group"),
-        });
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Depth Pass VB"),
-            contents: bytemuck::cast_slice(DEPTH_VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Depth Pass IB"),
-            contents: bytemuck::cast_slice(DEPTH_INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Depth Pass Pipeline Layout"),
-            bind_group_layouts: &[&layout],
-            push_constant_ranges: &[],
-        });
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shadow Display Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("challenge.wgsl").into()),
-        });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Depth Pass Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: wgpu::DepthStencilState {},
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-        });
+        let (texture, layer_views, array_view) = create_shadow_texture(device, &settings);
 
         Self {
+            settings,
             texture,
-            layout,
-            bind_group,
-            vertex_buffer,
-            index_buffer,
-            num_depth_indices: DEPTH_INDICES.len() as u32,
-            render_pipeline,
+            layer_views,
+            array_view,
+            sampler,
+            lighting_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Lighting Buffer"),
+                size: std::mem::size_of::<LightingUniform>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            lighting_staging: DynamicBuffer::new(
+                device,
+                "Lighting Staging Buffer",
+                wgpu::BufferUsages::COPY_SRC,
+                wgpu::COPY_BUFFER_ALIGNMENT,
+                4 * std::mem::size_of::<LightingUniform>() as u64,
+            ),
+            pipelines: HashMap::default(),
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.lighting_staging.reset();
+    }
+}
+
+fn create_shadow_texture(
+    device: &wgpu::Device,
+    settings: &ShadowSettings,
+) -> (wgpu::Texture, Vec<TextureView>, TextureView) {
+    // Always at least one layer so the array can be bound with shadows off
+    let layers = settings.cascade_splits.len().clamp(1, MAX_CASCADES) as u32;
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Shadow map"),
+        size: wgpu::Extent3d {
+            width: settings.map_size,
+            height: settings.map_size,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: SHADOW_MAP_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+
+    let layer_views = (0..layers)
+        .map(|layer| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Shadow cascade view"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Shadow map view"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+
+    (texture, layer_views, array_view)
+}
+
+/// Light view projection for every cascade, each fits a sphere around its slice of the camera frustum
+/// The spheres are snapped to shadow map texels so shadows don't shimmer when the camera moves
+pub(crate) fn cascade_view_projections(
+    camera: &Camera,
+    viewport_size: (u32, u32),
+    light_direction: Vec3,
+    cascade_splits: &[f32],
+    map_size: u32,
+) -> Vec<Mat4> {
+    let projection = camera.compute_projection_matrix(viewport_size);
+    let inverse_projection = projection.inverse();
+    let view = camera.view_matrix(viewport_size);
+    let light_direction = light_direction.normalize();
+
+    let ndc_depth = |distance: f32| {
+        let clip = projection * Vec3::new(0., 0., -distance).extend(1.);
+        clip.z / clip.w
+    };
+
+    let up = if light_direction.abs().dot(Vec3::Y) > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+
+    // Perspective projections can't project a point at the camera, so orthographic ones start just ahead
+    let mut slice_start = match camera.projection {
+        Projection::Perspective { near, .. } => near,
+        _ => 0.01,
+    };
+    cascade_splits
+        .iter()
+        .take(MAX_CASCADES)
+        .map(|&slice_end| {
+            let corners: Vec<Vec3> = [slice_start, slice_end]
+                .into_iter()
+                .flat_map(|distance| {
+                    let depth = ndc_depth(distance);
+                    [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)].map(|(x, y)| {
+                        view.transform_point3(
+                            inverse_projection.project_point3(Vec3::new(x, y, depth)),
+                        )
+                    })
+                })
+                .collect();
+            slice_start = slice_end;
+
+            let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0., f32::max)
+                .max(f32::EPSILON);
+
+            // Moves the center in whole texels, seen from the light
+            let texel_size = 2. * radius / map_size as f32;
+            let light_rotation = Mat4::look_at_rh(Vec3::ZERO, light_direction, up);
+            let light_center = light_rotation.transform_point3(center);
+            let snapped =
+                ((light_center.xy() / texel_size).floor() * texel_size).extend(light_center.z);
+            let center = light_rotation.inverse().transform_point3(snapped);
+
+            // Casters up to a radius behind the slice still land in the map
+            let eye = center - light_direction * radius * 2.;
+            let light_view = Mat4::look_at_rh(eye, center, up);
+            let light_projection =
+                Mat4::orthographic_rh(-radius, radius, -radius, radius, 0., radius * 3.);
+
+            light_projection * light_view
+        })
+        .collect()
+}
+
+impl Renderer {
+    pub fn shadow_settings(&self) -> &ShadowSettings {
+        &self.shadow_map.settings
+    }
+
+    /// Recreates the shadow map, and the shadow pipelines when the bias changed
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) {
+        let shadow_map = &mut self.shadow_map;
+        if settings.depth_bias != shadow_map.settings.depth_bias
+            || settings.slope_scale_bias != shadow_map.settings.slope_scale_bias
+        {
+            shadow_map.pipelines.clear();
+        }
+
+        let (texture, layer_views, array_view) = create_shadow_texture(&self.device, &settings);
+        shadow_map.texture = texture;
+        shadow_map.layer_views = layer_views;
+        shadow_map.array_view = array_view;
+        shadow_map.settings = settings;
+    }
+
+    /// Updates the lighting uniform for the camera and renders the shadow casters into
+    /// the cascades, before the pass that samples them
+    pub(crate) fn render_shadow_maps(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        draw_calls: &[DrawCall],
+        camera: &Camera,
+        viewport_size: (u32, u32),
+    ) {
+        let light = self.directional_light;
+        let settings = &self.shadow_map.settings;
+        let cascades = if settings.enabled {
+            cascade_view_projections(
+                camera,
+                viewport_size,
+                light.direction,
+                &settings.cascade_splits,
+                settings.map_size,
+            )
+        } else {
+            Vec::new()
+        };
+
+        let uniform = self.lighting_uniform(camera, viewport_size, &cascades);
+
+        let staging_offset = self.shadow_map.lighting_staging.write(
+            &self.device,
+            &self.queue,
+            bytemuck::cast_slice(&[uniform]),
+        );
+        command_encoder.copy_buffer_to_buffer(
+            self.shadow_map.lighting_staging.buffer(),
+            staging_offset,
+            &self.shadow_map.lighting_buffer,
+            0,
+            std::mem::size_of::<LightingUniform>() as u64,
+        );
+
+        if cascades.is_empty() {
+            return;
+        }
+
+        for draw_call in draw_calls.iter().filter(|draw_call| draw_call.cast_shadows) {
+            self.cache_shadow_pipeline(draw_call.material_handle);
+        }
+        let camera_offsets: Vec<u32> = cascades
+            .iter()
+            .map(|cascade| self.write_view_projection(*cascade))
+            .collect();
+
+        let timed_pass = self.begin_timed_pass(command_encoder, "Shadows");
+        let mut pass_stats = PassStats::default();
+        for (layer_view, camera_offset) in self.shadow_map.layer_views.iter().zip(camera_offsets) {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: layer_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[camera_offset]);
+
+            for draw_call in draw_calls.iter().filter(|draw_call| draw_call.cast_shadows) {
+                let Some(Some(pipeline)) =
+                    self.shadow_map.pipelines.get(&draw_call.material_handle)
+                else {
+                    continue;
+                };
+
+                render_pass.set_pipeline(pipeline);
+                render_pass
+                    .set_vertex_buffer(0, draw_call.vertex_slice(self.vertex_buffer.buffer()));
+                if draw_call.indices_len > 0 {
+                    render_pass.set_index_buffer(
+                        draw_call.index_slice(self.index_buffer.buffer()),
+                        draw_call.index_format,
+                    );
+                    render_pass.draw_indexed(0..draw_call.indices_len, 0, 0..1);
+                } else {
+                    render_pass.draw(0..draw_call.vert_len, 0..1);
+                }
+                pass_stats.pipeline_switches += 1;
+                pass_stats.draw_calls += 1;
+            }
         }
+        self.end_timed_pass(command_encoder, timed_pass);
+        self.frame_stats.add_pass(&pass_stats);
     }
 
-    fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
-        self.texture = texture::Texture::create_depth_texture_non_comparison_sampler(
-            device,
-            config,
-            "depth_texture",
+    /// Depth only pipeline reading the positions out of the material's vertex layout,
+    /// `None` for materials without positions, like instanced ones
+    fn cache_shadow_pipeline(&mut self, material_handle: ArenaId<Pipeline>) {
+        if self.shadow_map.pipelines.contains_key(&material_handle) {
+            return;
+        }
+
+        let material = &self
+            .materials
+            .get(material_handle)
+            .expect("Mesh was given invalid pipeline id")
+            .material;
+        let attributes = material.vertex_attributes();
+
+        let pipeline = (!material.instanced() && attributes.contains(&MeshAttribute::Position))
+            .then(|| {
+                // Position sorts first, so it's always at location 0 and offset 0
+                let (vertex_attributes, stride) = get_attribute_layout(attributes.iter());
+                let shader = self
+                    .device
+                    .create_shader_module(include_wgsl!("./default_shaders/shadow_depth.wgsl"));
+
+                let layout = self
+                    .device
+                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Shadow Pipeline Layout"),
+                        bind_group_layouts: &[&self.camera_bind_group_layout],
+                        push_constant_ranges: &[],
+                    });
+
+                self.device
+                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some("Shadow Pipeline"),
+                        layout: Some(&layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: "vertex",
+                            buffers: &[VertexBufferLayout {
+                                array_stride: stride,
+                                step_mode: VertexStepMode::Vertex,
+                                attributes: &vertex_attributes[..1],
+                            }],
+                        },
+                        fragment: None,
+                        primitive: wgpu::PrimitiveState {
+                            topology: material.topology(),
+                            cull_mode: material.cull_mode(),
+                            ..Default::default()
+                        },
+                        depth_stencil: Some(wgpu::DepthStencilState {
+                            format: SHADOW_MAP_FORMAT,
+                            depth_write_enabled: true,
+                            depth_compare: wgpu::CompareFunction::Less,
+                            stencil: wgpu::StencilState::default(),
+                            bias: wgpu::DepthBiasState {
+                                constant: self.shadow_map.settings.depth_bias,
+                                slope_scale: self.shadow_map.settings.slope_scale_bias,
+                                clamp: 0.,
+                            },
+                        }),
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview: None,
+                    })
+            });
+
+        self.shadow_map.pipelines.insert(material_handle, pipeline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use glam::{Quat, Vec3};
+
+    use super::cascade_view_projections;
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, lighting::lit_quad,
+        transform::Transform,
+    };
+
+    #[test]
+    fn cascades_cover_their_slice() {
+        let mut camera = Camera::perspective(60f32.to_radians(), 0.1, 16. / 9.);
+        camera.position = Vec3::new(3., 2., 10.);
+        camera.rotation = Quat::from_rotation_y(0.5);
+        let view = camera.view_matrix((1600, 900));
+
+        let splits = [5., 20.];
+        let cascades = cascade_view_projections(
+            &camera,
+            (1600, 900),
+            Vec3::new(-0.3, -1., -0.2),
+            &splits,
+            1024,
         );
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.texture.sampler),
-                },
-            ],
-            label: Some("depth_pass.bind_group"),
-        });
+        assert_eq!(cascades.len(), 2);
+
+        // A point in view at the far edge of each slice lands inside that cascade's map
+        for (cascade, distance) in cascades.iter().zip(splits) {
+            let point = view.transform_point3(Vec3::new(0.1, 0.1, -distance * 0.99));
+            let ndc = cascade.project_point3(point);
+            assert!(ndc.x.abs() <= 1. && ndc.y.abs() <= 1.);
+            assert!((0. ..=1.).contains(&ndc.z));
+        }
     }
 
-    fn render(&self, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Depth Visual Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: true,
-                },
-            })],
-            depth_stencil_attachment: None,
-        });
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..self.num_depth_indices, 0, 0..1);
+    #[test]
+    fn blocker_casts_shadow() {
+        let mut renderer = test_renderer((32, 32));
+        renderer.mode_3d = true;
+        renderer.directional_light.direction = Vec3::new(1., -1., 0.);
+
+        let mut camera = Camera::perspective(90f32.to_radians(), 0.1, 1.);
+        camera.position = Vec3::Y * 10.;
+        camera.rotation = Quat::from_rotation_x(-FRAC_PI_2);
+
+        let floor = lit_quad(&renderer, 10., 0.);
+        let blocker = lit_quad(&renderer, 1., 3.);
+
+        let mut ctx = renderer.begin();
+        renderer.draw_model(&floor, Transform::IDENTITY);
+        renderer.draw_model(&blocker, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::BLACK), &camera);
+        renderer.end_frame(ctx);
+        // Both quads are drawn into each of the 3 cascades as well as the scene
+        assert_eq!(renderer.render_stats().draw_calls, 2 + 3 * 2);
+
+        let image = renderer.read_frame();
+        let pixel = |x: usize, y: usize| image.data[(y * 32 + x) * 4];
+        // The light falls at 45 degrees, so the shadow lands 3 units along x
+        assert!(pixel(8, 16) > 200, "lit floor is {}", pixel(8, 16));
+        assert!(pixel(20, 16) < 120, "shadowed floor is {}", pixel(20, 16));
     }
 }
@@ -1,5 +1,3 @@
-use std::collections::BTreeMap;
-
 use glam::{Vec2, Vec3};
 
 use crate::{
@@ -9,14 +7,17 @@ use crate::{
     fonts::PositionedGlyph,
     line::LineMeshBuilder,
     mesh::{
-        AttributeValue, Indices, Mesh, MeshAttribute, MeshBuilder, Vertex, QUAD_INDICES, QUAD_UVS,
-        QUAD_VERTEX_POSITIONS,
+        AttributeValue, Indices, Mesh, MeshAttribute, MeshBuilder, VertexData, QUAD_INDICES,
+        QUAD_UVS, QUAD_VERTEX_POSITIONS,
     },
     model::Model,
     sprite::Anchor,
 };
 
-use super::{rect::Rect, sprite::Sprite, text::Text, transform::Transform, Renderer};
+use super::{
+    lighting_2d::normal_rotation, rect::Rect, sprite::Sprite, text::Text, transform::Transform,
+    Renderer,
+};
 
 impl Renderer {
     pub fn set_sorting_axis_2d(&mut self, sorting_axis: Vec3) {
@@ -32,30 +33,22 @@ impl Renderer {
                 .into()
         });
 
-        let vertices = positions
-            .iter()
-            .zip(QUAD_UVS)
-            .map(|(position, uv)| {
-                Vertex(BTreeMap::from([
-                    (MeshAttribute::Position, AttributeValue::Position(*position)),
-                    (MeshAttribute::UV, AttributeValue::UV(uv.into())),
-                    (
-                        MeshAttribute::Color,
-                        AttributeValue::Color(color.as_rgba_f32()),
-                    ),
-                ]))
-            })
-            .collect();
+        let vertices = VertexData {
+            positions: positions.to_vec(),
+            uvs: QUAD_UVS.map(Into::into).to_vec(),
+            colors: vec![color.as_rgba_f32(); 4],
+            normals: Vec::new(),
+        };
 
         let material_handle = self.material_map.default;
 
-        Mesh::new(
-            Some(ArenaId::first()),
-            material_handle,
-            vertices,
-            Indices::U16(QUAD_INDICES.to_vec()),
-            (transform.position * self.sorting_axis).length(),
-        )
+        MeshBuilder::new()
+            .with_texture(ArenaId::first())
+            .with_material(material_handle)
+            .with_vertices(vertices)
+            .with_indices(Indices::U16(QUAD_INDICES.to_vec()))
+            .with_sort_position(transform.position)
+            .build()
     }
 
     pub fn draw_rect(&mut self, rect: &Rect, color: Color) {
@@ -68,6 +61,8 @@ impl Renderer {
         self.draw_sprite(sprite, Transform::from_position(position));
     }
 
+    /// Draws the sprite as an instance, see [`Renderer::draw_sprite_instanced`],
+    /// sprites with a non instanced [`Sprite::material`] are drawn as a quad of vertices instead
     pub fn draw_sprite(&mut self, sprite: &Sprite, mut transform: Transform) {
         let texture = self
             .textures
@@ -102,7 +97,7 @@ impl Renderer {
             quad_size = custom_size;
         }
 
-        let sort_value = transform.position * self.sorting_axis;
+        let sort_position = transform.position;
 
         transform.position.z = 0.;
 
@@ -112,17 +107,47 @@ impl Renderer {
                 .into()
         });
 
-        let vertices = positions
-            .iter()
-            .zip(uvs)
-            .map(|(position, uv)| {
-                Vertex(BTreeMap::from([
-                    (MeshAttribute::Position, AttributeValue::Position(*position)),
-                    (MeshAttribute::UV, AttributeValue::UV(uv.into())),
-                    (MeshAttribute::Color, AttributeValue::Color(sprite.color)),
-                ]))
-            })
-            .collect();
+        if let Some(normal_map) = sprite.normal_map {
+            let rotation = normal_rotation(transform.rotation, sprite.flip_x, sprite.flip_y);
+            let vertices = VertexData {
+                positions: positions.to_vec(),
+                uvs: uvs.map(Into::into).to_vec(),
+                colors: vec![rotation; 4],
+                normals: Vec::new(),
+            };
+
+            self.normal_meshes_2d.push(
+                MeshBuilder::new()
+                    .with_indices(Indices::U16(QUAD_INDICES.to_vec()))
+                    .with_vertices(vertices)
+                    .with_material(self.normal_map_2d_material())
+                    .with_texture(normal_map)
+                    .with_layer(sprite.layer)
+                    .with_sort_position(sort_position)
+                    .build(),
+            );
+        }
+
+        // Sprites without a vertex shader of their own are drawn as instances
+        let instanced = match sprite.material {
+            Some(material) => self
+                .materials
+                .get(material)
+                .is_some_and(|pipeline| pipeline.material.instanced()),
+            None => true,
+        };
+        if instanced {
+            transform.position = sort_position;
+            self.draw_sprite_instanced(sprite, transform);
+            return;
+        }
+
+        let vertices = VertexData {
+            positions: positions.to_vec(),
+            uvs: uvs.map(Into::into).to_vec(),
+            colors: vec![sprite.color; 4],
+            normals: Vec::new(),
+        };
 
         let material_handle = sprite.material.unwrap_or(self.material_map.default);
 
@@ -131,9 +156,10 @@ impl Renderer {
             .with_vertices(vertices)
             .with_material(material_handle)
             .with_texture(sprite.handle)
+            .with_layer(sprite.layer)
+            .with_sort_position(sort_position)
             .build();
-
-        mesh.sort_value = sort_value.x + sort_value.y + sort_value.z;
+        mesh.params = sprite.params;
 
         self.push(mesh);
     }
@@ -168,8 +194,6 @@ impl Renderer {
                 // let scale_factor = 1f32;
                 let rect = text_glyph.rect;
 
-                let mut vertices = Vec::new();
-
                 let uvs = [
                     Vec2::new(rect.min.x, rect.max.y),
                     Vec2::new(rect.max.x, rect.max.y),
@@ -186,30 +210,23 @@ impl Renderer {
                         .into()
                 });
 
-                for i in 0..QUAD_VERTEX_POSITIONS.len() {
-                    vertices.push(Vertex(BTreeMap::from([
-                        (
-                            MeshAttribute::Position,
-                            AttributeValue::Position(positions[i]),
-                        ),
-                        (MeshAttribute::UV, AttributeValue::UV(uvs[i].into())),
-                        (
-                            MeshAttribute::Color,
-                            AttributeValue::Color(text.color.into()),
-                        ),
-                    ])));
-                }
+                let vertices = VertexData {
+                    positions: positions.to_vec(),
+                    uvs: uvs.map(Into::into).to_vec(),
+                    colors: vec![text.color.into(); 4],
+                    normals: Vec::new(),
+                };
 
                 let material_handle = text.material.unwrap_or(self.material_map.default);
 
-                Mesh {
-                    texture_handle: Some(text_glyph.atlas_info.texture_handle),
-                    material_handle,
-                    vertices,
-                    indices: crate::mesh::Indices::U16(QUAD_INDICES.to_vec()),
-                    sort_value: transform.position.z,
-                    batch: true,
-                }
+                MeshBuilder::new()
+                    .with_texture(text_glyph.atlas_info.texture_handle)
+                    .with_material(material_handle)
+                    .with_vertices(vertices)
+                    .with_indices(crate::mesh::Indices::U16(QUAD_INDICES.to_vec()))
+                    .with_layer(text.layer)
+                    .with_sort_position(transform.position)
+                    .build()
             })
             .collect();
 
@@ -234,6 +251,8 @@ impl Renderer {
             .with_indices(Indices::U32(model.indices.clone()))
             .with_material(model.material)
             .with_batch(false)
+            .with_cast_shadows(true)
+            .with_sort_position(transform.position)
             .with_attributes(
                 MeshAttribute::Position,
                 model
@@ -270,6 +289,17 @@ impl Renderer {
             );
         }
 
+        if !model.normals.is_empty() {
+            mesh_builder = mesh_builder.with_attributes(
+                MeshAttribute::Normal,
+                model
+                    .normals
+                    .iter()
+                    .map(|v| AttributeValue::Normal(transform.transform_normal(*v).into()))
+                    .collect(),
+            );
+        }
+
         self.push(mesh_builder.build());
     }
 
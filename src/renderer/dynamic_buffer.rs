@@ -0,0 +1,84 @@
+use wgpu::{util::align_to, Buffer, BufferUsages, Device, Queue};
+
+/// A GPU buffer that is kept between frames and written with [`Queue::write_buffer`]
+/// Every write is placed after the previous one, so several passes in a frame can share it
+/// Call [`DynamicBuffer::reset`] once the frame has been submitted
+pub(crate) struct DynamicBuffer {
+    buffer: Buffer,
+    label: &'static str,
+    usage: BufferUsages,
+    alignment: u64,
+    capacity: u64,
+    cursor: u64,
+}
+
+impl DynamicBuffer {
+    pub fn new(
+        device: &Device,
+        label: &'static str,
+        usage: BufferUsages,
+        alignment: u64,
+        capacity: u64,
+    ) -> Self {
+        let usage = usage | BufferUsages::COPY_DST;
+
+        Self {
+            buffer: create_buffer(device, label, usage, capacity),
+            label,
+            usage,
+            alignment,
+            capacity,
+            cursor: 0,
+        }
+    }
+
+    /// Writes the data after everything else written this frame, growing the buffer if it doesn't fit
+    /// Returns the byte offset the data was written to
+    pub fn write(&mut self, device: &Device, queue: &Queue, data: &[u8]) -> u64 {
+        let mut offset = align_to(self.cursor, self.alignment);
+        let size = align_to(data.len() as u64, wgpu::COPY_BUFFER_ALIGNMENT);
+
+        if offset + size > self.capacity {
+            // Passes recorded earlier in the frame keep the old buffer alive,
+            // so the new one can start from the beginning
+            self.capacity = size.max(self.capacity * 2).next_power_of_two();
+            self.buffer = create_buffer(device, self.label, self.usage, self.capacity);
+            offset = 0;
+        }
+
+        if size > 0 {
+            if size == data.len() as u64 {
+                queue.write_buffer(&self.buffer, offset, data);
+            } else {
+                let mut padded = data.to_vec();
+                padded.resize(size as usize, 0);
+                queue.write_buffer(&self.buffer, offset, &padded);
+            }
+        }
+
+        self.cursor = offset + size;
+
+        offset
+    }
+
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+}
+
+fn create_buffer(device: &Device, label: &str, usage: BufferUsages, size: u64) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage,
+        mapped_at_creation: false,
+    })
+}
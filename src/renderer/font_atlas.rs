@@ -25,6 +25,7 @@ impl FontAtlas {
             format: TextureFormat::Rgba8UnormSrgb,
             dimensions: (size.x as u32, size.y as u32),
             sampler: texture::TextureSamplerType::Nearest,
+            mipmaps: false,
         };
 
         let dynamic_texture_atlas_builder =
@@ -61,6 +61,7 @@ impl Font {
                 .collect::<Vec<u8>>(),
             format: TextureFormat::Rgba8UnormSrgb,
             sampler: super::texture::TextureSamplerType::Nearest,
+            mipmaps: false,
         };
 
         (metrics, glyph_image)
@@ -0,0 +1,211 @@
+use wgpu::{SurfaceConfiguration, TextureFormat};
+
+use super::{
+    errors::RenderError,
+    msaa::supported_sample_counts,
+    texture::{Image, Texture, TextureSamplerType},
+    Renderer,
+};
+
+/// The format headless frames are rendered in, matches the sRGB swapchain on most platforms
+pub const HEADLESS_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+impl Renderer {
+    /// Creates a [`Renderer`] without a window
+    /// Frames are rendered into an offscreen texture that can be read back with [`Renderer::read_frame`]
+    /// Prefers a fallback (software) adapter so it can run on machines without a GPU
+    pub async fn new_headless(viewport_size: (u32, u32)) -> Result<Self, RenderError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            dx12_shader_compiler: Default::default(),
+        });
+
+        let mut adapter = None;
+        for force_fallback_adapter in [true, false] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter,
+                })
+                .await;
+
+            if adapter.is_some() {
+                break;
+            }
+        }
+
+        let adapter = match adapter {
+            Some(it) => it,
+            None => return Err(RenderError::new("Unable to request adapter from wgpu")),
+        };
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                            | wgpu::Features::TIMESTAMP_QUERY),
+                    limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                    label: None,
+                },
+                None,
+            )
+            .await?;
+
+        // There is no surface, but the config still describes the size and format we render at
+        let surface_config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: HEADLESS_TEXTURE_FORMAT,
+            width: viewport_size.0,
+            height: viewport_size.1,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: Vec::default(),
+        };
+
+        let supported_msaa_samples =
+            supported_sample_counts(&adapter, device.features(), surface_config.format);
+        let mut renderer =
+            Self::from_device(device, queue, None, surface_config, supported_msaa_samples);
+
+        let target = Texture::create_render_target(
+            &renderer.device,
+            viewport_size,
+            HEADLESS_TEXTURE_FORMAT,
+            *renderer
+                .default_texture_samplers
+                .get(&TextureSamplerType::Nearest)
+                .unwrap(),
+        );
+        renderer.headless_target = Some(renderer.textures.insert(target));
+
+        Ok(renderer)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    /// Reads the last finished headless frame back from the GPU
+    /// Call after [`Renderer::end_frame`] so the frame has been submitted
+    pub fn read_frame(&self) -> Image {
+        let handle = self
+            .headless_target
+            .expect("read_frame is only supported by headless renderers");
+
+        self.read_texture(handle)
+    }
+}
+
+/// Headless renderer for the GPU tests
+/// Panics without an adapter so the tests fail instead of passing without running,
+/// a software adapter like llvmpipe or lavapipe is enough
+#[cfg(test)]
+pub(crate) fn test_renderer(size: (u32, u32)) -> Renderer {
+    pollster::block_on(Renderer::new_headless(size))
+        .unwrap_or_else(|error| panic!("GPU tests need a wgpu adapter: {}", error.message))
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3, Vec4};
+
+    use super::test_renderer;
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, model::Model, sprite::Sprite,
+        text::Text, transform::Transform,
+    };
+
+    #[test]
+    fn clear_color_reads_back() {
+        let mut renderer = test_renderer((4, 4));
+
+        let mut ctx = renderer.begin();
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert_eq!(image.dimensions, (4, 4));
+        assert_eq!(image.data.len(), 4 * 4 * 4);
+        assert!(image.data.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn draw_sprite_reads_back() {
+        let mut renderer = test_renderer((8, 8));
+
+        // The first texture is the blank white texture
+        let sprite = Sprite {
+            color: Color::BLUE.as_rgba_f32(),
+            custom_size: Some(Vec2::splat(8.)),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn draw_text_reads_back() {
+        let mut renderer = test_renderer((32, 32));
+
+        // Goes through the font atlas upload, the glyphs are drawn down from the top center
+        let text = Text::new("H", 24.).with_color(Color::WHITE);
+        let mut ctx = renderer.begin();
+        renderer.draw_text(&text, Transform::from_xyz(0., 12., 0.));
+        renderer.render(&mut ctx, Some(Color::BLACK), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        let pixel = |x: usize, y: usize| &image.data[(y * 32 + x) * 4..][..4];
+        let lit = image.data.chunks(4).filter(|pixel| pixel[0] > 200).count();
+        assert!(lit > 20, "only {lit} text pixels");
+        for (x, y) in [(0, 0), (31, 0), (0, 31), (31, 31)] {
+            assert_eq!(pixel(x, y), [0, 0, 0, 255], "corner {x}, {y}");
+        }
+    }
+
+    #[test]
+    fn draw_model_reads_back() {
+        let mut renderer = test_renderer((8, 8));
+
+        // An unlit quad with vertex colors, left half green and right half blue
+        let quad = |left: f32, color: Vec4| Model {
+            material: renderer.material_map.default,
+            positions: vec![
+                Vec3::new(left, -4., 0.),
+                Vec3::new(left + 4., -4., 0.),
+                Vec3::new(left + 4., 4., 0.),
+                Vec3::new(left, 4., 0.),
+            ],
+            tex_coords: vec![Vec2::ZERO; 4],
+            colors: vec![color; 4],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..Default::default()
+        };
+        let green = quad(-4., Vec4::new(0., 1., 0., 1.));
+        let blue = quad(0., Vec4::new(0., 0., 1., 1.));
+
+        let mut ctx = renderer.begin();
+        renderer.draw_model(&green, Transform::IDENTITY);
+        renderer.draw_model(&blue, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [0, 255, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            assert_eq!(pixel, expected, "pixel {index}");
+        }
+    }
+}
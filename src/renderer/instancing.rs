@@ -0,0 +1,171 @@
+use std::mem;
+
+use glam::{Vec2, Vec4};
+use wgpu::{include_wgsl, VertexAttribute, VertexBufferLayout, VertexStepMode};
+
+use super::{
+    material::Material, mesh::MeshBuilder, sprite::Sprite, transform::Transform, Renderer,
+};
+
+/// Number of vertices drawn for every instance, two triangles with no index buffer
+pub(crate) const INSTANCE_VERTEX_COUNT: u32 = 6;
+
+/// One sprite drawn by an instanced material
+/// The quad is built on the GPU, so a sprite is a single record instead of four vertices
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteInstance {
+    pub model: [[f32; 4]; 4],
+    /// Min and max UVs, swap them to flip the sprite
+    pub uv_rect: [f32; 4],
+    pub color: [f32; 4],
+    pub size: [f32; 2],
+    /// Offset from the center in quad units, see [`crate::sprite::Anchor::as_vec`]
+    pub anchor: [f32; 2],
+}
+
+impl SpriteInstance {
+    const ATTRIBUTES: [VertexAttribute; 8] = wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x2,
+        7 => Float32x2,
+    ];
+
+    /// Instances are read from vertex buffer 0, stepping once per instance
+    pub fn layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: mem::size_of::<SpriteInstance>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    /// Builds the instance the same way [`Renderer::draw_sprite`] builds its vertices
+    pub fn from_sprite(sprite: &Sprite, texture_size: Vec2, mut transform: Transform) -> Self {
+        let mut uv_rect = Vec4::new(0., 0., 1., 1.);
+        let mut size = texture_size;
+
+        if let Some(rect) = sprite.texture_rect {
+            uv_rect = Vec4::from((rect.min / texture_size, rect.max / texture_size));
+            size = rect.size();
+        }
+
+        if sprite.flip_x {
+            uv_rect = Vec4::new(uv_rect.z, uv_rect.y, uv_rect.x, uv_rect.w);
+        }
+        if sprite.flip_y {
+            uv_rect = Vec4::new(uv_rect.x, uv_rect.w, uv_rect.z, uv_rect.y);
+        }
+
+        if let Some(custom_size) = sprite.custom_size {
+            size = custom_size;
+        }
+
+        // Z is only used for sorting
+        transform.position.z = 0.;
+
+        Self {
+            model: transform.compute_matrix().to_cols_array_2d(),
+            uv_rect: uv_rect.into(),
+            color: sprite.color,
+            size: size.into(),
+            anchor: sprite.anchor.as_vec().into(),
+        }
+    }
+}
+
+/// The default instanced sprite material
+/// Custom sprite shaders can return true from [`Material::instanced`] and read the [`SpriteInstance`] attributes
+#[derive(Debug)]
+pub struct SpriteInstanceMaterial;
+
+impl Material for SpriteInstanceMaterial {
+    fn shader(&self) -> wgpu::ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/sprite_instanced.wgsl")
+    }
+
+    fn instanced(&self) -> bool {
+        true
+    }
+
+    fn label(&self) -> &str {
+        "Sprite Instance Material"
+    }
+}
+
+impl Renderer {
+    /// Draws the sprite as a GPU instance, neighbouring instances with the same texture
+    /// and material are drawn with a single call
+    /// [`Sprite::material`] has to be an instanced material if it's set
+    pub fn draw_sprite_instanced(&mut self, sprite: &Sprite, transform: Transform) {
+        let texture = self
+            .textures
+            .get(sprite.handle)
+            .expect("Mesh is missing texture");
+        let material_handle = sprite
+            .material
+            .unwrap_or(self.material_map.sprite_instanced);
+
+        debug_assert!(
+            self.materials
+                .get(material_handle)
+                .is_some_and(|pipeline| pipeline.material.instanced()),
+            "Instanced sprites need an instanced material"
+        );
+
+        let instance = SpriteInstance::from_sprite(sprite, texture.dimensions, transform);
+
+        let mut mesh = MeshBuilder::new()
+            .with_instances(vec![instance])
+            .with_material(material_handle)
+            .with_texture(sprite.handle)
+            .with_layer(sprite.layer)
+            .with_sort_position(transform.position)
+            .build();
+        mesh.params = sprite.params;
+
+        self.push(mesh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        sprite::Sprite, transform::Transform,
+    };
+
+    #[test]
+    fn draw_sprite_instanced_reads_back() {
+        let mut renderer = test_renderer((8, 8));
+
+        let half = |color: Color| Sprite {
+            color: color.as_rgba_f32(),
+            custom_size: Some(Vec2::new(4., 8.)),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite_instanced(&half(Color::BLUE), Transform::from_xyz(-2., 0., 0.));
+        renderer.draw_sprite_instanced(&half(Color::GREEN), Transform::from_xyz(2., 0., 0.));
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [0, 0, 255, 255]
+            } else {
+                [0, 255, 0, 255]
+            };
+            assert_eq!(pixel, expected);
+        }
+    }
+}
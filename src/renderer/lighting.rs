@@ -0,0 +1,377 @@
+use std::{collections::BTreeSet, mem};
+
+use glam::{Mat4, Vec3};
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupLayout, BindingResource, BindingType, BufferBindingType,
+    Device, RenderPipeline, SamplerBindingType, ShaderModuleDescriptor, ShaderStages,
+    TextureSampleType, TextureViewDimension,
+};
+
+use crate::components::color::Color;
+
+use super::{
+    bind_groups::{BindGroupBuilder, BindGroupLayoutBuilder},
+    camera::Camera,
+    depth_pass::MAX_CASCADES,
+    material::{Material, MaterialHandle},
+    mesh::{Mesh, MeshAttribute},
+    Renderer,
+};
+
+/// Most point and spot lights [`LitMaterial`] is lit by, the closest to the camera are kept
+pub const MAX_LIGHTS: usize = 16;
+
+/// Light coming from one direction, like the sun, the only light that casts shadows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction the light travels in
+    pub direction: Vec3,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::new(-0.4, -1., -0.3).normalize(),
+            color: Color::WHITE,
+            intensity: 1.,
+        }
+    }
+}
+
+/// Light shining in every direction from a point, fading out at `range`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Color,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            color: Color::WHITE,
+            intensity: 1.,
+            range: 10.,
+        }
+    }
+}
+
+/// Light shining in a cone from a point, fading out at `range`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    /// Direction the cone points in
+    pub direction: Vec3,
+    pub color: Color,
+    pub intensity: f32,
+    pub range: f32,
+    /// Angle from the direction in radians where the light starts fading
+    pub inner_angle: f32,
+    /// Angle from the direction in radians past which nothing is lit
+    pub outer_angle: f32,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            direction: Vec3::NEG_Y,
+            color: Color::WHITE,
+            intensity: 1.,
+            range: 10.,
+            inner_angle: 0.4,
+            outer_angle: 0.5,
+        }
+    }
+}
+
+/// Light added to [`Renderer::lights`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl Light {
+    pub fn position(&self) -> Vec3 {
+        match self {
+            Light::Point(light) => light.position,
+            Light::Spot(light) => light.position,
+        }
+    }
+
+    fn to_gpu(self) -> GpuLight {
+        match self {
+            Light::Point(light) => GpuLight {
+                position_range: light.position.extend(light.range).into(),
+                color: scaled_color(light.color, light.intensity),
+                direction: [0.; 4],
+                // Every direction is inside the cone
+                cone: [-1., -2., 0., 0.],
+            },
+            Light::Spot(light) => GpuLight {
+                position_range: light.position.extend(light.range).into(),
+                color: scaled_color(light.color, light.intensity),
+                direction: light.direction.normalize_or_zero().extend(0.).into(),
+                cone: [light.inner_angle.cos(), light.outer_angle.cos(), 0., 0.],
+            },
+        }
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+fn scaled_color(color: Color, intensity: f32) -> [f32; 4] {
+    color
+        .as_linear_rgba_f32()
+        .map(|channel| channel * intensity)
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct GpuLight {
+    pub(crate) position_range: [f32; 4],
+    pub(crate) color: [f32; 4],
+    pub(crate) direction: [f32; 4],
+    /// Cosines of the inner and outer angle
+    pub(crate) cone: [f32; 4],
+}
+
+/// Lights and shadow cascades read by [`LitMaterial`], laid out to match `lit.wgsl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LightingUniform {
+    pub(crate) light_view_projections: [[[f32; 4]; 4]; MAX_CASCADES],
+    /// View distance where each cascade ends
+    pub(crate) cascade_splits: [f32; 4],
+    pub(crate) camera_position: [f32; 4],
+    pub(crate) camera_forward: [f32; 4],
+    pub(crate) light_direction: [f32; 4],
+    pub(crate) light_color: [f32; 4],
+    pub(crate) ambient: [f32; 4],
+    pub(crate) cascade_count: u32,
+    pub(crate) pcf_radius: u32,
+    pub(crate) receiver_bias: f32,
+    pub(crate) shadow_texel_size: f32,
+    pub(crate) light_count: u32,
+    pub(crate) _padding: [u32; 3],
+    pub(crate) lights: [GpuLight; MAX_LIGHTS],
+}
+
+/// Textured Blinn-Phong material lit by [`Renderer::ambient_light`], [`Renderer::directional_light`]
+/// and [`Renderer::lights`], and shadowed by meshes drawn with
+/// [`crate::mesh::MeshBuilder::with_cast_shadows`] like models
+/// Meshes without normals are lit evenly from every side
+#[derive(Debug)]
+pub struct LitMaterial;
+
+impl Material for LitMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/lit.wgsl")
+    }
+
+    fn vertex_attributes(&self) -> BTreeSet<MeshAttribute> {
+        BTreeSet::from([
+            MeshAttribute::Position,
+            MeshAttribute::UV,
+            MeshAttribute::Color,
+            MeshAttribute::Normal,
+        ])
+    }
+
+    fn get_bind_group_layouts(&self, device: &Device) -> Vec<BindGroupLayout> {
+        vec![create_lighting_bind_group_layout(device)]
+    }
+
+    fn get_bind_groups(
+        &self,
+        _mesh: &Mesh,
+        renderer: &Renderer,
+        render_pipeline: &RenderPipeline,
+    ) -> Vec<BindGroup> {
+        vec![renderer.create_lighting_bind_group(&render_pipeline.get_bind_group_layout(2))]
+    }
+
+    fn use_depth_stencil(&self) -> bool {
+        true
+    }
+
+    fn label(&self) -> &str {
+        "Lit Material"
+    }
+}
+
+/// Lighting uniform, shadow map and its comparison sampler
+pub fn create_lighting_bind_group_layout(device: &Device) -> BindGroupLayout {
+    BindGroupLayoutBuilder::new()
+        .append_buffer(
+            ShaderStages::VERTEX_FRAGMENT,
+            BufferBindingType::Uniform,
+            false,
+            mem::size_of::<LightingUniform>() as u64,
+        )
+        .append(
+            ShaderStages::FRAGMENT,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            None,
+        )
+        .append(
+            ShaderStages::FRAGMENT,
+            BindingType::Sampler(SamplerBindingType::Comparison),
+            None,
+        )
+        .build(device, Some("lighting_bind_group_layout"))
+}
+
+impl Renderer {
+    pub fn lit_material(&self) -> MaterialHandle {
+        self.material_map.lit
+    }
+
+    /// Builds the lighting uniform for a camera, with the cascades the shadow map was rendered with
+    pub(crate) fn lighting_uniform(
+        &self,
+        camera: &Camera,
+        viewport_size: (u32, u32),
+        cascades: &[Mat4],
+    ) -> LightingUniform {
+        let light = self.directional_light;
+        let settings = &self.shadow_map.settings;
+        let view = camera.view_matrix(viewport_size);
+        let camera_position = view.w_axis.truncate();
+
+        let mut uniform = LightingUniform {
+            light_view_projections: [Mat4::IDENTITY.to_cols_array_2d(); MAX_CASCADES],
+            cascade_splits: [0.; 4],
+            camera_position: view.w_axis.into(),
+            camera_forward: view.transform_vector3(Vec3::NEG_Z).extend(0.).into(),
+            light_direction: light.direction.normalize().extend(0.).into(),
+            light_color: scaled_color(light.color, light.intensity),
+            ambient: self.ambient_light.as_linear_rgba_f32(),
+            cascade_count: cascades.len() as u32,
+            pcf_radius: settings.pcf_radius,
+            receiver_bias: settings.receiver_bias,
+            shadow_texel_size: 1. / settings.map_size as f32,
+            light_count: 0,
+            _padding: [0; 3],
+            lights: [GpuLight::default(); MAX_LIGHTS],
+        };
+        for (index, cascade) in cascades.iter().enumerate() {
+            uniform.light_view_projections[index] = cascade.to_cols_array_2d();
+            uniform.cascade_splits[index] = settings.cascade_splits[index];
+        }
+
+        let mut lights = self.lights.clone();
+        if lights.len() > MAX_LIGHTS {
+            lights.sort_by(|a, b| {
+                let a = a.position().distance_squared(camera_position);
+                let b = b.position().distance_squared(camera_position);
+                a.total_cmp(&b)
+            });
+            lights.truncate(MAX_LIGHTS);
+        }
+        uniform.light_count = lights.len() as u32;
+        for (index, light) in lights.into_iter().enumerate() {
+            uniform.lights[index] = light.to_gpu();
+        }
+
+        uniform
+    }
+
+    pub(crate) fn create_lighting_bind_group(&self, layout: &BindGroupLayout) -> BindGroup {
+        let sampler = self
+            .samplers
+            .get(self.shadow_map.sampler)
+            .expect("Shadow map sampler is missing");
+
+        BindGroupBuilder::new()
+            .append_buffer(&self.shadow_map.lighting_buffer)
+            .append_texture_view(&self.shadow_map.array_view)
+            .append(BindingResource::Sampler(sampler))
+            .build(&self.device, Some("Lighting bind group"), layout)
+    }
+}
+
+/// Flat lit square facing up, for the lighting and shadow tests
+#[cfg(test)]
+pub(crate) fn lit_quad(renderer: &Renderer, half_size: f32, height: f32) -> crate::model::Model {
+    crate::model::Model {
+        material: renderer.lit_material(),
+        positions: vec![
+            Vec3::new(-half_size, height, half_size),
+            Vec3::new(half_size, height, half_size),
+            Vec3::new(half_size, height, -half_size),
+            Vec3::new(-half_size, height, -half_size),
+        ],
+        tex_coords: vec![glam::Vec2::ZERO; 4],
+        indices: vec![0, 1, 2, 0, 2, 3],
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use glam::{Quat, Vec3};
+
+    use super::{lit_quad, PointLight};
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, transform::Transform,
+    };
+
+    #[test]
+    fn point_light_falls_off() {
+        let mut renderer = test_renderer((32, 32));
+        renderer.mode_3d = true;
+        renderer.ambient_light = Color::BLACK;
+        renderer.directional_light.intensity = 0.;
+        renderer.lights.push(
+            PointLight {
+                position: Vec3::Y,
+                range: 5.,
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        let mut camera = Camera::perspective(90f32.to_radians(), 0.1, 1.);
+        camera.position = Vec3::Y * 10.;
+        camera.rotation = Quat::from_rotation_x(-FRAC_PI_2);
+
+        let mut floor = lit_quad(&renderer, 10., 0.);
+        floor.normals = vec![Vec3::Y; 4];
+
+        let mut ctx = renderer.begin();
+        renderer.draw_model(&floor, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::BLACK), &camera);
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        let pixel = |x: usize, y: usize| image.data[(y * 32 + x) * 4];
+        assert!(
+            pixel(16, 16) > 150,
+            "floor under the light is {}",
+            pixel(16, 16)
+        );
+        assert!(pixel(1, 1) < 10, "floor out of range is {}", pixel(1, 1));
+    }
+}
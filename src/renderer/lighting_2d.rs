@@ -0,0 +1,566 @@
+use std::mem;
+
+use glam::{Mat3, Mat4, Quat, Vec2};
+use wgpu::{
+    include_wgsl, BindGroupLayout, BindingResource, BindingType, BufferBindingType, Device,
+    RenderPipeline, ShaderModuleDescriptor, ShaderStages, TextureSampleType, TextureView,
+    TextureViewDimension,
+};
+
+use crate::{
+    arena::ArenaId,
+    components::{color::Color, line::Line2D},
+};
+
+use super::{
+    bind_groups::{BindGroupBuilder, BindGroupLayoutBuilder},
+    camera::Camera,
+    camera_view::{ClearMode, ViewportPass},
+    dynamic_buffer::DynamicBuffer,
+    material::{Material, MaterialHandle},
+    rect::Rect,
+    render_state::BlendMode,
+    render_stats::PassStats,
+    render_target::RenderTarget,
+    texture::{Texture, TextureSamplerType},
+    Renderer,
+};
+
+/// Most 2D lights drawn per camera, the closest to the camera are kept
+pub const MAX_LIGHTS_2D: usize = 32;
+/// Most occluder edges casting shadows per camera, a [`Rect`] takes 4
+pub const MAX_OCCLUDER_SEGMENTS: usize = 256;
+
+/// Light shining in every direction from a point, reaching up to `radius`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight2D {
+    pub position: Vec2,
+    pub color: Color,
+    pub intensity: f32,
+    pub radius: f32,
+    /// Exponent of the fade towards the radius, 1 is linear
+    pub falloff: f32,
+    /// Distance above the sprites, lower lights graze normal maps at a flatter angle
+    pub height: f32,
+    /// Size of the light itself, bigger lights cast softer shadows and 0 gives hard edges
+    pub source_radius: f32,
+}
+
+impl Default for PointLight2D {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            color: Color::WHITE,
+            intensity: 1.,
+            radius: 200.,
+            falloff: 2.,
+            height: 50.,
+            source_radius: 8.,
+        }
+    }
+}
+
+/// Light shining in a cone from a point, reaching up to `radius`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotLight2D {
+    pub position: Vec2,
+    /// Direction the cone points in
+    pub direction: Vec2,
+    pub color: Color,
+    pub intensity: f32,
+    pub radius: f32,
+    /// Exponent of the fade towards the radius, 1 is linear
+    pub falloff: f32,
+    /// Distance above the sprites, lower lights graze normal maps at a flatter angle
+    pub height: f32,
+    /// Size of the light itself, bigger lights cast softer shadows and 0 gives hard edges
+    pub source_radius: f32,
+    /// Angle from the direction in radians where the light starts fading
+    pub inner_angle: f32,
+    /// Angle from the direction in radians past which nothing is lit
+    pub outer_angle: f32,
+}
+
+impl Default for SpotLight2D {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            direction: Vec2::X,
+            color: Color::WHITE,
+            intensity: 1.,
+            radius: 200.,
+            falloff: 2.,
+            height: 50.,
+            source_radius: 8.,
+            inner_angle: 0.4,
+            outer_angle: 0.5,
+        }
+    }
+}
+
+/// Light added to [`Renderer::lights_2d`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light2D {
+    Point(PointLight2D),
+    Spot(SpotLight2D),
+}
+
+impl Light2D {
+    pub fn position(&self) -> Vec2 {
+        match self {
+            Light2D::Point(light) => light.position,
+            Light2D::Spot(light) => light.position,
+        }
+    }
+
+    fn to_gpu(self) -> GpuLight2D {
+        match self {
+            Light2D::Point(light) => GpuLight2D {
+                position_radius: [
+                    light.position.x,
+                    light.position.y,
+                    light.radius,
+                    light.falloff,
+                ],
+                color: scaled_color(light.color, light.intensity),
+                direction_height: [0., 0., light.height, light.source_radius],
+                // Every direction is inside the cone
+                cone: [-1., -2., 0., 0.],
+            },
+            Light2D::Spot(light) => {
+                let direction = light.direction.normalize_or_zero();
+                GpuLight2D {
+                    position_radius: [
+                        light.position.x,
+                        light.position.y,
+                        light.radius,
+                        light.falloff,
+                    ],
+                    color: scaled_color(light.color, light.intensity),
+                    direction_height: [direction.x, direction.y, light.height, light.source_radius],
+                    cone: [light.inner_angle.cos(), light.outer_angle.cos(), 0., 0.],
+                }
+            }
+        }
+    }
+}
+
+impl From<PointLight2D> for Light2D {
+    fn from(light: PointLight2D) -> Self {
+        Light2D::Point(light)
+    }
+}
+
+impl From<SpotLight2D> for Light2D {
+    fn from(light: SpotLight2D) -> Self {
+        Light2D::Spot(light)
+    }
+}
+
+/// Shape blocking 2D lights, added to [`Renderer::occluders_2d`]
+/// The inside of a [`Rect`] occluder is in its own shadow
+#[derive(Debug, Clone, Copy)]
+pub enum Occluder2D {
+    Line(Line2D),
+    Rect(Rect),
+}
+
+impl Occluder2D {
+    /// Edges of the occluder
+    pub fn segments(&self) -> Vec<Line2D> {
+        match self {
+            Occluder2D::Line(line) => vec![*line],
+            Occluder2D::Rect(rect) => {
+                let corners = [
+                    rect.min,
+                    Vec2::new(rect.max.x, rect.min.y),
+                    rect.max,
+                    Vec2::new(rect.min.x, rect.max.y),
+                ];
+                (0..4)
+                    .map(|index| Line2D(corners[index], corners[(index + 1) % 4]))
+                    .collect()
+            }
+        }
+    }
+}
+
+impl From<Line2D> for Occluder2D {
+    fn from(line: Line2D) -> Self {
+        Occluder2D::Line(line)
+    }
+}
+
+impl From<Rect> for Occluder2D {
+    fn from(rect: Rect) -> Self {
+        Occluder2D::Rect(rect)
+    }
+}
+
+fn scaled_color(color: Color, intensity: f32) -> [f32; 4] {
+    color
+        .as_linear_rgba_f32()
+        .map(|channel| channel * intensity)
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuLight2D {
+    /// Position, radius and falloff
+    position_radius: [f32; 4],
+    color: [f32; 4],
+    /// Spot direction, height and source radius
+    direction_height: [f32; 4],
+    /// Cosines of the inner and outer angle
+    cone: [f32; 4],
+}
+
+/// Lights and occluders of one camera, laid out to match `lighting_2d.wgsl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Lighting2DUniform {
+    inverse_view_projection: [[f32; 4]; 4],
+    ambient: [f32; 4],
+    light_count: u32,
+    occluder_count: u32,
+    _padding: [u32; 2],
+    lights: [GpuLight2D; MAX_LIGHTS_2D],
+    /// Start and end of each occluder edge
+    occluders: [[f32; 4]; MAX_OCCLUDER_SEGMENTS],
+}
+
+/// Renderer state for the 2D light pass
+pub(crate) struct Lighting2D {
+    uniform_buffer: DynamicBuffer,
+    bind_group_layout: BindGroupLayout,
+    /// Recreated when the MSAA sample count changes
+    pub(crate) pipeline: Option<RenderPipeline>,
+    /// Normals of the normal mapped sprites, the size of the frame
+    normal_target: Option<RenderTarget>,
+}
+
+impl Lighting2D {
+    pub(crate) fn new(device: &Device) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .append_buffer(
+                ShaderStages::VERTEX_FRAGMENT,
+                BufferBindingType::Uniform,
+                true,
+                mem::size_of::<Lighting2DUniform>() as u64,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .build(device, Some("lighting_2d_bind_group_layout"));
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        Self {
+            uniform_buffer: DynamicBuffer::new(
+                device,
+                "Lighting 2D Buffer",
+                wgpu::BufferUsages::UNIFORM,
+                alignment,
+                mem::size_of::<Lighting2DUniform>() as u64 * 2,
+            ),
+            bind_group_layout,
+            pipeline: None,
+            normal_target: None,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.uniform_buffer.reset();
+    }
+}
+
+/// Writes the normal map of a sprite, the vertex color holds the 2x2 matrix
+/// that rotates and flips its normals into world space
+#[derive(Debug)]
+pub struct NormalMap2DMaterial;
+
+impl Material for NormalMap2DMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/normal_map_2d.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        "Normal Map 2D Material"
+    }
+}
+
+/// Vertex color for a normal mapped sprite, see [`NormalMap2DMaterial`]
+pub(crate) fn normal_rotation(rotation: Quat, flip_x: bool, flip_y: bool) -> [f32; 4] {
+    let rotation = Mat3::from_quat(rotation);
+    let x_axis = rotation.x_axis.truncate() * if flip_x { -1. } else { 1. };
+    let y_axis = rotation.y_axis.truncate() * if flip_y { -1. } else { 1. };
+
+    [x_axis.x, x_axis.y, y_axis.x, y_axis.y]
+}
+
+impl Renderer {
+    pub(crate) fn normal_map_2d_material(&self) -> MaterialHandle {
+        self.material_map.normal_map_2d
+    }
+
+    /// Lights the viewport that was just drawn: renders the normal mapped sprites,
+    /// then multiplies the view by the ambient plus every light
+    /// `depth_texture_handle` is the target's, it keys its MSAA textures
+    pub(crate) fn render_lighting_2d(
+        &mut self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view: &TextureView,
+        depth_texture_handle: ArenaId<Texture>,
+        camera: &Camera,
+        viewport: &ViewportPass,
+        ambient: Color,
+    ) {
+        let target_size = self
+            .textures
+            .get(depth_texture_handle)
+            .unwrap()
+            .dimensions
+            .as_uvec2();
+
+        let normal_target = match self.lighting_2d.normal_target {
+            Some(normal_target) if normal_target.size == target_size => normal_target,
+            previous => {
+                if let Some(previous) = previous {
+                    self.remove_render_target(previous);
+                }
+                let normal_target =
+                    self.create_render_target(target_size, TextureSamplerType::Nearest);
+                self.lighting_2d.normal_target = Some(normal_target);
+                normal_target
+            }
+        };
+
+        // Nothing normal mapped leaves the alpha at 0, and lights skip the normal term there
+        let normal_meshes = mem::take(&mut self.normal_meshes_2d);
+        let queued_meshes = mem::replace(&mut self.meshes, normal_meshes);
+        let normal_view = self
+            .textures
+            .get(normal_target.texture)
+            .unwrap()
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_viewport(
+            command_encoder,
+            &normal_view,
+            normal_target.depth_texture,
+            Some(Color::NONE),
+            camera,
+            Some(ViewportPass {
+                offset: viewport.offset,
+                size: viewport.size,
+                clear_mode: ClearMode::None,
+                ambient_light_2d: None,
+            }),
+        );
+        self.meshes = queued_meshes;
+
+        let viewport_size = (viewport.size.x as u32, viewport.size.y as u32);
+        let uniform = self.lighting_2d_uniform(camera, viewport_size, ambient);
+        let uniform_offset = self.lighting_2d.uniform_buffer.write(
+            &self.device,
+            &self.queue,
+            bytemuck::cast_slice(&[uniform]),
+        );
+        let bind_group = BindGroupBuilder::new()
+            .append(BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: self.lighting_2d.uniform_buffer.buffer(),
+                offset: 0,
+                size: wgpu::BufferSize::new(mem::size_of::<Lighting2DUniform>() as u64),
+            }))
+            .append_texture_view(&normal_view)
+            .build(
+                &self.device,
+                Some("Lighting 2D bind group"),
+                &self.lighting_2d.bind_group_layout,
+            );
+
+        self.cache_lighting_2d_pipeline();
+        let msaa_views = self.msaa_views(depth_texture_handle, target_size);
+
+        let timed_pass = self.begin_timed_pass(command_encoder, "Lighting 2D");
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Lighting 2D Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: msaa_views.as_ref().map_or(view, |(color, _)| color),
+                    resolve_target: msaa_views.as_ref().map(|_| view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_viewport(
+                viewport.offset.x,
+                viewport.offset.y,
+                viewport.size.x,
+                viewport.size.y,
+                0.,
+                1.,
+            );
+            render_pass.set_pipeline(self.lighting_2d.pipeline.as_ref().unwrap());
+            render_pass.set_bind_group(0, &bind_group, &[uniform_offset as u32]);
+            render_pass.draw(0..3, 0..1);
+        }
+        self.end_timed_pass(command_encoder, timed_pass);
+        self.frame_stats.add_pass(&PassStats::single_draw());
+    }
+
+    fn lighting_2d_uniform(
+        &self,
+        camera: &Camera,
+        viewport_size: (u32, u32),
+        ambient: Color,
+    ) -> Lighting2DUniform {
+        let view_projection =
+            Mat4::from_cols_array_2d(&camera.create_uniform(viewport_size).view_proj);
+        let camera_position = camera.position.truncate();
+        let distance = |position: Vec2| position.distance_squared(camera_position);
+
+        let mut lights = self.lights_2d.clone();
+        if lights.len() > MAX_LIGHTS_2D {
+            lights.sort_by(|a, b| distance(a.position()).total_cmp(&distance(b.position())));
+            lights.truncate(MAX_LIGHTS_2D);
+        }
+
+        let mut segments: Vec<Line2D> = self
+            .occluders_2d
+            .iter()
+            .flat_map(Occluder2D::segments)
+            .collect();
+        if segments.len() > MAX_OCCLUDER_SEGMENTS {
+            segments
+                .sort_by(|a, b| distance((a.0 + a.1) / 2.).total_cmp(&distance((b.0 + b.1) / 2.)));
+            segments.truncate(MAX_OCCLUDER_SEGMENTS);
+        }
+
+        let mut uniform = Lighting2DUniform {
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            ambient: ambient.as_linear_rgba_f32(),
+            light_count: lights.len() as u32,
+            occluder_count: segments.len() as u32,
+            _padding: [0; 2],
+            lights: [GpuLight2D::default(); MAX_LIGHTS_2D],
+            occluders: [[0.; 4]; MAX_OCCLUDER_SEGMENTS],
+        };
+        for (index, light) in lights.into_iter().enumerate() {
+            uniform.lights[index] = light.to_gpu();
+        }
+        for (index, segment) in segments.into_iter().enumerate() {
+            uniform.occluders[index] = [segment.0.x, segment.0.y, segment.1.x, segment.1.y];
+        }
+
+        uniform
+    }
+
+    fn cache_lighting_2d_pipeline(&mut self) {
+        if self.lighting_2d.pipeline.is_some() {
+            return;
+        }
+
+        let shader = self
+            .device
+            .create_shader_module(include_wgsl!("./default_shaders/lighting_2d.wgsl"));
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Lighting 2D Pipeline Layout"),
+                bind_group_layouts: &[&self.lighting_2d.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Lighting 2D Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vertex",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.surface_config.format,
+                        blend: Some(BlendMode::Multiply.blend_state()),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: self.msaa_samples,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+
+        self.lighting_2d.pipeline = Some(pipeline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::PointLight2D;
+    use crate::{
+        arena::ArenaId,
+        camera::Camera,
+        camera_view::CameraView,
+        components::{color::Color, line::Line2D},
+        headless::test_renderer,
+        sprite::Sprite,
+        transform::Transform,
+    };
+
+    #[test]
+    fn occluder_blocks_2d_light() {
+        let mut renderer = test_renderer((32, 32));
+        renderer.lights_2d.push(
+            PointLight2D {
+                position: Vec2::new(-12., 0.),
+                radius: 40.,
+                falloff: 1.,
+                source_radius: 0.,
+                ..Default::default()
+            }
+            .into(),
+        );
+        renderer
+            .occluders_2d
+            .push(Line2D(Vec2::new(0., -4.), Vec2::new(0., 4.)).into());
+
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(32.)),
+            ..Sprite::new(ArenaId::first())
+        };
+        let cameras = [CameraView::new(Camera::orthographic()).with_ambient_light_2d(Color::BLACK)];
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render_cameras(&mut ctx, Some(Color::BLACK), &cameras);
+        renderer.end_frame(ctx);
+        // The sprite and the light composited over it
+        assert_eq!(renderer.render_stats().draw_calls, 2);
+
+        let image = renderer.read_frame();
+        let pixel = |x: usize, y: usize| image.data[(y * 32 + x) * 4];
+        assert!(pixel(8, 16) > 200, "next to the light is {}", pixel(8, 16));
+        assert!(pixel(26, 4) > 100, "past the occluder is {}", pixel(26, 4));
+        assert_eq!(pixel(26, 16), 0, "behind the occluder is lit");
+    }
+}
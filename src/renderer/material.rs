@@ -1,15 +1,17 @@
 use std::{collections::BTreeSet, fmt::Debug};
 
 use wgpu::{
-    include_wgsl, BindGroup, BindGroupLayout, Device, PrimitiveTopology, RenderPipeline,
-    ShaderModuleDescriptor,
+    include_wgsl, BindGroup, BindGroupLayout, Device, Face, PrimitiveTopology, RenderPipeline,
+    ShaderModuleDescriptor, StencilState,
 };
 
 use crate::arena::ArenaId;
 
 use super::{
+    errors::RenderError,
     mesh::{Mesh, MeshAttribute},
     pipeline::Pipeline,
+    render_state::{BlendMode, DepthState},
     Renderer,
 };
 
@@ -24,6 +26,12 @@ pub trait Material: Debug {
         include_wgsl!("./default_shaders/default.wgsl")
     }
 
+    /// Defs the shader's `#ifdef`s see, on top of the ones derived from the material,
+    /// see [`crate::shader_preprocessor::ShaderPreprocessor`]
+    fn shader_defs(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     fn vertex_attributes(&self) -> BTreeSet<MeshAttribute> {
         BTreeSet::from([
             MeshAttribute::Position,
@@ -61,6 +69,41 @@ pub trait Material: Debug {
         true
     }
 
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Alpha
+    }
+
+    /// `None` draws both sides, for double sided meshes like foliage
+    fn cull_mode(&self) -> Option<Face> {
+        Some(Face::Back)
+    }
+
+    fn depth_state(&self) -> DepthState {
+        DepthState::default()
+    }
+
+    /// Stencil test and ops, only used with [`Material::use_depth_stencil`]
+    fn stencil_state(&self) -> StencilState {
+        StencilState::default()
+    }
+
+    /// Value the stencil ops compare against and write
+    fn stencil_reference(&self) -> u32 {
+        0
+    }
+
+    /// Opaque meshes are drawn first, front to back, so the depth test can skip hidden fragments
+    /// Everything else is drawn after, sorted by layer
+    fn is_opaque(&self) -> bool {
+        self.use_depth_stencil()
+    }
+
+    /// Reads a [`crate::instancing::SpriteInstance`] per instance instead of mesh vertices,
+    /// [`Material::vertex_attributes`] is ignored
+    fn instanced(&self) -> bool {
+        false
+    }
+
     fn label(&self) -> &str {
         "Default Material"
     }
@@ -69,10 +112,105 @@ pub trait Material: Debug {
 impl Renderer {
     pub fn push_material(&mut self, material: impl Material + 'static) -> ArenaId<Pipeline> {
         let pipeline = Pipeline {
-            render_pipeline: self.create_pipeline_from_material(&material),
+            render_pipeline: self.create_pipeline_from_material(&material, None, false),
             material: Box::from(material),
+            params_layout: None,
+            default_params: None,
+            shader_source: None,
+            depth_pass_pipeline: None,
         };
 
         self.materials.insert(pipeline)
     }
+
+    /// Rebuilds the pipeline of `material` from WGSL `source`, which replaces [`Material::shader`]
+    /// On a compile error the last pipeline that compiled keeps being drawn with, and the error
+    /// stays in [`Renderer::shader_errors`] until a source compiles
+    pub fn reload_material_shader(
+        &mut self,
+        material: MaterialHandle,
+        source: String,
+    ) -> Result<(), RenderError> {
+        let pipeline = self.materials.get(material).expect("Material is missing");
+        match self.create_pipeline_from_source(
+            pipeline.material.as_ref(),
+            pipeline.params_layout.as_ref(),
+            &source,
+            false,
+        ) {
+            Ok(render_pipeline) => {
+                let pipeline = self.materials.get_mut(material).unwrap();
+                pipeline.render_pipeline = render_pipeline;
+                pipeline.depth_pass_pipeline = None;
+                pipeline.shader_source = Some(source);
+                self.shader_errors.remove(&material);
+                Ok(())
+            }
+            Err(error) => {
+                self.shader_errors.insert(material, error.message.clone());
+                Err(error)
+            }
+        }
+    }
+
+    /// Compile errors of the latest source given to [`Renderer::reload_material_shader`], per material
+    pub fn shader_errors(&self) -> impl Iterator<Item = (MaterialHandle, &str)> {
+        self.shader_errors
+            .iter()
+            .map(|(material, error)| (*material, error.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::{DefaultMat, MaterialHandle};
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        sprite::Sprite, texture::Image, transform::Transform, Renderer,
+    };
+
+    fn draw_green_shader_sprite(renderer: &mut Renderer, material: MaterialHandle) -> Image {
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(8.)),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::default());
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        renderer.read_frame()
+    }
+
+    #[test]
+    fn broken_shader_keeps_last_pipeline() {
+        let mut renderer = test_renderer((8, 8));
+        let material = renderer.push_material(DefaultMat {});
+        let green = include_str!("./default_shaders/default.wgsl").replace(
+            "return in.color * color;",
+            "return vec4<f32>(0.0, 1.0, 0.0, 1.0);",
+        );
+
+        renderer
+            .reload_material_shader(material, green.clone())
+            .unwrap();
+        let broken = green.replace("fn fragment", "fn fragment oops");
+        assert!(renderer.reload_material_shader(material, broken).is_err());
+        assert_eq!(renderer.shader_errors().count(), 1);
+
+        let image = draw_green_shader_sprite(&mut renderer, material);
+        assert_eq!(&image.data[..4], [0, 255, 0, 255]);
+
+        // The pipeline is recreated from the file source, not the material's own shader
+        renderer.set_msaa_samples(4).unwrap();
+        let image = draw_green_shader_sprite(&mut renderer, material);
+        assert_eq!(&image.data[..4], [0, 255, 0, 255]);
+
+        renderer.reload_material_shader(material, green).unwrap();
+        assert_eq!(renderer.shader_errors().count(), 0);
+    }
 }
@@ -0,0 +1,397 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use bytemuck::Pod;
+use wgpu::{
+    util::{align_to, BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupLayout, BindingResource, BindingType, BufferBindingType, Device,
+    SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension,
+};
+
+use crate::arena::ArenaId;
+
+use super::{
+    bind_groups::{BindGroupBuilder, BindGroupLayoutBuilder},
+    material::{Material, MaterialHandle},
+    pipeline::Pipeline,
+    texture::Texture,
+    Renderer,
+};
+
+/// Uniform values and textures read by a material pushed with [`Renderer::push_material_with_params`]
+/// The uniform is binding 0 of the group after the texture, each texture and its sampler follow it
+/// Groups from [`Material::get_bind_group_layouts`] come after
+#[derive(Debug)]
+pub struct MaterialParams {
+    material: MaterialHandle,
+    type_id: TypeId,
+    data: Vec<u8>,
+    textures: Vec<ArenaId<Texture>>,
+    buffer: wgpu::Buffer,
+    /// Rebuilt when one of the textures changes
+    pub(crate) bind_group: Option<BindGroup>,
+}
+
+/// Typed handle to [`MaterialParams`] holding a `T`
+///
+/// The params live in a single uniform buffer, so a frame draws with the last value given to
+/// [`Renderer::set_params`] before [`Renderer::end_frame`], including the meshes queued before it was set
+/// Use separate instances from [`Renderer::create_params_instance`] for meshes that need different values
+pub struct ParamsHandle<T> {
+    id: ArenaId<MaterialParams>,
+    marker: PhantomData<T>,
+}
+
+impl<T> ParamsHandle<T> {
+    pub fn id(&self) -> ArenaId<MaterialParams> {
+        self.id
+    }
+}
+
+impl<T> Clone for ParamsHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ParamsHandle<T> {}
+
+impl<T> std::fmt::Debug for ParamsHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ParamsHandle").field(&self.id).finish()
+    }
+}
+
+/// Uniform buffers are padded so the shader struct's size is always covered
+fn uniform_size<T>() -> u64 {
+    align_to(std::mem::size_of::<T>().max(1) as u64, 16)
+}
+
+pub(crate) fn create_params_bind_group_layout(
+    device: &Device,
+    uniform_size: u64,
+    texture_count: usize,
+) -> BindGroupLayout {
+    let mut builder = BindGroupLayoutBuilder::new();
+    builder.append_buffer(
+        ShaderStages::VERTEX_FRAGMENT,
+        BufferBindingType::Uniform,
+        false,
+        uniform_size,
+    );
+    for _ in 0..texture_count {
+        builder
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Sampler(SamplerBindingType::Filtering),
+                None,
+            );
+    }
+
+    builder.build(device, Some("material_params_bind_group_layout"))
+}
+
+impl Renderer {
+    /// Pushes a material that reads `params` as a uniform, followed by `textures`, see [`MaterialParams`]
+    /// Every mesh drawn with the material uses these params unless it picks an instance
+    /// from [`Renderer::create_params_instance`]
+    pub fn push_material_with_params<T: Pod>(
+        &mut self,
+        material: impl Material + 'static,
+        params: T,
+        textures: Vec<ArenaId<Texture>>,
+    ) -> MaterialHandle {
+        let params_layout =
+            create_params_bind_group_layout(&self.device, uniform_size::<T>(), textures.len());
+        let render_pipeline =
+            self.create_pipeline_from_material(&material, Some(&params_layout), false);
+
+        let handle = self.materials.insert(Pipeline {
+            render_pipeline,
+            material: Box::from(material),
+            params_layout: Some(params_layout),
+            default_params: None,
+            shader_source: None,
+            depth_pass_pipeline: None,
+        });
+        let params = self.insert_params(handle, params, textures);
+        self.materials.get_mut(handle).unwrap().default_params = Some(params.id);
+
+        handle
+    }
+
+    /// The params meshes drawn with `material` use by default
+    /// `None` if the material has no params, or they aren't a `T`
+    pub fn material_params<T: Pod>(&self, material: MaterialHandle) -> Option<ParamsHandle<T>> {
+        let id = self.materials.get(material)?.default_params?;
+        (self.params.get(id)?.type_id == TypeId::of::<T>()).then_some(ParamsHandle {
+            id,
+            marker: PhantomData,
+        })
+    }
+
+    /// Creates params of `material` a mesh can pick with [`crate::mesh::MeshBuilder::with_params`],
+    /// sharing the pipeline and textures of the material's default params
+    pub fn create_params_instance<T: Pod>(
+        &mut self,
+        material: MaterialHandle,
+        params: T,
+    ) -> ParamsHandle<T> {
+        let default = self
+            .material_params::<T>(material)
+            .expect("Material has no params of this type");
+        let textures = self.params.get(default.id).unwrap().textures.clone();
+
+        self.insert_params(material, params, textures)
+    }
+
+    /// Removes an instance at the end of the frame, so meshes already queued with it still draw
+    /// Returns false for a material's default params, they live as long as the material
+    pub fn remove_params<T>(&mut self, handle: ParamsHandle<T>) -> bool {
+        let Some(params) = self.params.get(handle.id) else {
+            return false;
+        };
+        let is_default = self
+            .materials
+            .get(params.material)
+            .is_some_and(|pipeline| pipeline.default_params == Some(handle.id));
+        if is_default {
+            return false;
+        }
+
+        if !self.removed_params.contains(&handle.id) {
+            self.removed_params.push(handle.id);
+        }
+        true
+    }
+
+    /// Current value of the params
+    pub fn get_params<T: Pod>(&self, handle: ParamsHandle<T>) -> T {
+        let params = self.params.get(handle.id).expect("Params were removed");
+        bytemuck::pod_read_unaligned(&params.data[..std::mem::size_of::<T>()])
+    }
+
+    /// Updates the uniform, it's only written to the GPU when the value changed
+    /// Draws already queued this frame see the new value too, see [`ParamsHandle`]
+    pub fn set_params<T: Pod>(&mut self, handle: ParamsHandle<T>, value: T) {
+        let params = self.params.get_mut(handle.id).expect("Params were removed");
+        let bytes = bytemuck::bytes_of(&value);
+        if params.data[..bytes.len()] == *bytes {
+            return;
+        }
+
+        params.data[..bytes.len()].copy_from_slice(bytes);
+        self.queue.write_buffer(&params.buffer, 0, &params.data);
+    }
+
+    /// Replaces one of the textures the params were created with
+    pub fn set_params_texture<T>(
+        &mut self,
+        handle: ParamsHandle<T>,
+        index: usize,
+        texture: ArenaId<Texture>,
+    ) {
+        let params = self.params.get_mut(handle.id).expect("Params were removed");
+        params.textures[index] = texture;
+        params.bind_group = None;
+    }
+
+    fn insert_params<T: Pod>(
+        &mut self,
+        material: MaterialHandle,
+        value: T,
+        textures: Vec<ArenaId<Texture>>,
+    ) -> ParamsHandle<T> {
+        let mut data = bytemuck::bytes_of(&value).to_vec();
+        data.resize(uniform_size::<T>() as usize, 0);
+
+        let buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Material Params Buffer"),
+            contents: &data,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let id = self.params.insert(MaterialParams {
+            material,
+            type_id: TypeId::of::<T>(),
+            data,
+            textures,
+            buffer,
+            bind_group: None,
+        });
+
+        ParamsHandle {
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    /// Builds the bind group of the params the first time they're drawn, and after a texture changed
+    pub(crate) fn cache_params_bind_group(&mut self, id: ArenaId<MaterialParams>) {
+        let params = self.params.get(id).expect("Mesh was given removed params");
+        if params.bind_group.is_some() {
+            return;
+        }
+
+        let layout = self
+            .materials
+            .get(params.material)
+            .and_then(|pipeline| pipeline.params_layout.as_ref())
+            .expect("Params material is missing");
+
+        let mut builder = BindGroupBuilder::new();
+        builder.append_buffer(&params.buffer);
+        for handle in &params.textures {
+            let texture = self
+                .textures
+                .get(*handle)
+                .expect("Params texture is missing");
+            builder
+                .append_texture_view(&texture.view)
+                .append(BindingResource::Sampler(
+                    self.samplers.get(texture.sampler).unwrap(),
+                ));
+        }
+        let bind_group = builder.build(&self.device, Some("Material params bind group"), layout);
+
+        self.params.get_mut(id).unwrap().bind_group = Some(bind_group);
+    }
+
+    /// Drops the bind groups of params reading a texture that has been replaced or removed
+    pub(crate) fn invalidate_params_bind_groups(&mut self, texture_handle: ArenaId<Texture>) {
+        for params in self.params.iter_mut() {
+            if params.textures.contains(&texture_handle) {
+                params.bind_group = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        material::Material, sprite::Sprite, transform::Transform,
+    };
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+    struct Tint {
+        color: [f32; 4],
+    }
+
+    #[derive(Debug)]
+    struct TintMaterial;
+
+    impl Material for TintMaterial {
+        fn shader(&self) -> wgpu::ShaderModuleDescriptor<'_> {
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Tint shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    include_str!("./default_shaders/default.wgsl")
+                        .replace("return in.color * color;", "return tint.color;")
+                        .replace(
+                            "@fragment",
+                            "struct Tint { color: vec4<f32> };\n\
+                             @group(2) @binding(0) var<uniform> tint: Tint;\n@fragment",
+                        )
+                        .into(),
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn params_instance_shares_pipeline() {
+        let mut renderer = test_renderer((8, 8));
+        let material = renderer.push_material_with_params(
+            TintMaterial,
+            Tint {
+                color: [1., 0., 0., 1.],
+            },
+            Vec::new(),
+        );
+        let blue = renderer.create_params_instance(
+            material,
+            Tint {
+                color: [0., 0., 1., 1.],
+            },
+        );
+        let default = renderer.material_params::<Tint>(material).unwrap();
+        renderer.set_params(
+            default,
+            Tint {
+                color: [0., 1., 0., 1.],
+            },
+        );
+
+        let half = Sprite {
+            custom_size: Some(Vec2::new(4., 8.)),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&half, Transform::from_xyz(-2., 0., 0.));
+        renderer.draw_sprite(&half.with_params(blue), Transform::from_xyz(2., 0., 0.));
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [0, 255, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            assert_eq!(pixel, expected);
+        }
+    }
+
+    #[test]
+    fn removed_params_draw_until_the_frame_ends() {
+        let mut renderer = test_renderer((4, 4));
+        let material = renderer.push_material_with_params(
+            TintMaterial,
+            Tint {
+                color: [1., 0., 0., 1.],
+            },
+            Vec::new(),
+        );
+        let default = renderer.material_params::<Tint>(material).unwrap();
+        let blue = renderer.create_params_instance(
+            material,
+            Tint {
+                color: [0., 0., 1., 1.],
+            },
+        );
+        assert!(!renderer.remove_params(default));
+
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(4.)),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite.with_params(blue), Transform::IDENTITY);
+        assert!(renderer.remove_params(blue));
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 0, 255, 255]));
+        assert!(renderer.params.get(blue.id()).is_none());
+        assert!(renderer.params.get(default.id()).is_some());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, mem};
+use std::{collections::BTreeMap, mem, sync::Arc};
 
 use bytemuck::{cast_slice, AnyBitPattern};
 use egui::Id;
@@ -7,7 +7,15 @@ use guillotiere::euclid::default;
 use tobj::LoadOptions;
 use wgpu::{VertexAttribute, VertexFormat};
 
-use crate::{arena::ArenaId, pipeline::Pipeline, texture::Texture, transform::Transform};
+use crate::{
+    arena::ArenaId,
+    batching::MeshBuffers,
+    instancing::SpriteInstance,
+    material_params::{MaterialParams, ParamsHandle},
+    pipeline::Pipeline,
+    texture::Texture,
+    transform::Transform,
+};
 
 pub const QUAD_INDICES: [u16; 6] = [0, 2, 3, 0, 1, 2];
 
@@ -39,24 +47,35 @@ impl Indices {
         }
     }
 
+    /// Indices of `quad_count` quads laid out like [`QUAD_VERTEX_POSITIONS`], four vertices each
+    /// Uses u32 indices only when the vertices don't fit in a u16
+    pub fn quads(quad_count: usize) -> Indices {
+        let indices =
+            (0..quad_count).flat_map(|quad| QUAD_INDICES.map(|index| quad * 4 + index as usize));
+        if quad_count * 4 <= u16::MAX as usize + 1 {
+            Indices::U16(indices.map(|index| index as u16).collect())
+        } else {
+            Indices::U32(indices.map(|index| index as u32).collect())
+        }
+    }
+
+    /// Offsets every index by `value`, switching to u32 indices if they no longer fit in a u16
     pub fn add(&self, value: usize) -> Indices {
         match self {
-            Indices::U16(indices) => {
-                return Indices::U16(
-                    indices
-                        .iter()
-                        .map(|index| index + value as u16)
-                        .collect::<Vec<u16>>(),
-                )
-            }
-            Indices::U32(indices) => {
-                return Indices::U32(
-                    indices
-                        .iter()
-                        .map(|index| index + value as u32)
-                        .collect::<Vec<u32>>(),
-                )
+            Indices::U16(indices)
+                if indices.iter().max().map_or(0, |max| *max as usize) + value
+                    <= u16::MAX as usize =>
+            {
+                Indices::U16(indices.iter().map(|index| index + value as u16).collect())
             }
+            _ => Indices::U32(self.iter().map(|index| (index + value) as u32).collect()),
+        }
+    }
+
+    /// Converts to u32 indices, doing nothing if they already are
+    pub fn promote_to_u32(&mut self) {
+        if let Indices::U16(indices) = self {
+            *self = Indices::U32(indices.iter().map(|index| *index as u32).collect());
         }
     }
 
@@ -67,16 +86,16 @@ impl Indices {
         }
     }
 
+    /// Appends the other indices, switching both to u32 if only one side is
     pub fn append(&mut self, other: &mut Self) {
-        match self {
-            Indices::U16(v) => match other {
-                Indices::U16(v2) => v.append(v2),
-                Indices::U32(_) => panic!("Trying to append a u32 to u16"),
-            },
-            Indices::U32(v) => match other {
-                Indices::U32(v2) => v.append(v2),
-                Indices::U16(_) => panic!("Trying to append a u16 to u32"),
-            },
+        match (&mut *self, &mut *other) {
+            (Indices::U16(v), Indices::U16(v2)) => v.append(v2),
+            (Indices::U32(v), Indices::U32(v2)) => v.append(v2),
+            _ => {
+                self.promote_to_u32();
+                other.promote_to_u32();
+                self.append(other);
+            }
         }
     }
 
@@ -138,9 +157,15 @@ impl Default for Indices {
 pub struct MeshBuilder {
     pub(crate) texture_handle: Option<ArenaId<Texture>>,
     pub(crate) material_handle: ArenaId<Pipeline>,
-    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) vertices: VertexData,
     pub(crate) indices: Indices,
     pub(crate) batch: bool,
+    pub(crate) instances: Vec<SpriteInstance>,
+    pub(crate) layer: i32,
+    pub(crate) sort_position: Vec3,
+    pub(crate) sort_value: f32,
+    pub(crate) cast_shadows: bool,
+    pub(crate) params: Option<ArenaId<MaterialParams>>,
 }
 
 impl MeshBuilder {
@@ -148,9 +173,15 @@ impl MeshBuilder {
         Self {
             texture_handle: None,
             material_handle: ArenaId::first(), // TODO(loui): we should really ref the default material but its hard without a ref to rb
-            vertices: Vec::default(),
+            vertices: VertexData::default(),
             indices: Indices::default(),
             batch: true,
+            instances: Vec::new(),
+            layer: 0,
+            sort_position: Vec3::ZERO,
+            sort_value: 0.,
+            cast_shadows: false,
+            params: None,
         }
     }
 
@@ -176,8 +207,8 @@ impl MeshBuilder {
         self
     }
 
-    pub fn with_vertices(mut self, vertices: Vec<Vertex>) -> Self {
-        self.vertices = vertices;
+    pub fn with_vertices(mut self, vertices: impl Into<VertexData>) -> Self {
+        self.vertices = vertices.into();
 
         self
     }
@@ -187,25 +218,18 @@ impl MeshBuilder {
         attribute: MeshAttribute,
         values: Vec<AttributeValue>,
     ) -> Self {
-        if self.vertices.is_empty() {
-            self.vertices = values
-                .iter()
-                .map(|v| Vertex::new().with_attribute(attribute, *v))
-                .collect();
-        } else {
-            for i in 0..values.len() {
-                let vert = &mut self.vertices[i];
-                vert.set_attribute(attribute, values[i]);
-            }
+        for (index, value) in values.into_iter().enumerate() {
+            debug_assert_eq!(value.into_mesh_attr(), attribute);
+            self.vertices.set_attribute(index, value);
         }
 
         self
     }
 
     pub fn with_attribute(mut self, attribute: MeshAttribute, value: AttributeValue) -> Self {
-        for i in 0..self.vertices.len() {
-            let vert = &mut self.vertices[i];
-            vert.set_attribute(attribute, value);
+        debug_assert_eq!(value.into_mesh_attr(), attribute);
+        for index in 0..self.vertices.len() {
+            self.vertices.set_attribute(index, value);
         }
 
         self
@@ -229,14 +253,63 @@ impl MeshBuilder {
         self
     }
 
+    /// Instances for a material that is [`crate::material::Material::instanced`], the mesh has no vertices
+    pub fn with_instances(mut self, instances: Vec<SpriteInstance>) -> Self {
+        self.instances = instances;
+
+        self
+    }
+
+    /// Meshes on higher layers are drawn on top of lower layers
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+
+        self
+    }
+
+    /// World position used by [`crate::sorting::SortKey`] to order the mesh within its layer
+    pub fn with_sort_position(mut self, sort_position: Vec3) -> Self {
+        self.sort_position = sort_position;
+
+        self
+    }
+
+    /// Value used when the layer sorts with [`crate::sorting::SortKey::Custom`]
+    pub fn with_sort_value(mut self, sort_value: f32) -> Self {
+        self.sort_value = sort_value;
+
+        self
+    }
+
+    /// Draws the mesh into the shadow map in 3D mode
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+
+        self
+    }
+
+    /// Draws the mesh with params from [`crate::Renderer::create_params_instance`]
+    /// instead of the material's default ones
+    pub fn with_params<T>(mut self, params: ParamsHandle<T>) -> Self {
+        self.params = Some(params.id());
+
+        self
+    }
+
     pub fn build(self) -> Mesh {
         Mesh {
             texture_handle: self.texture_handle,
             material_handle: self.material_handle,
             vertices: self.vertices,
             indices: self.indices,
-            sort_value: 0.,
+            instances: self.instances,
+            layer: self.layer,
+            sort_position: self.sort_position,
+            sort_value: self.sort_value,
             batch: self.batch,
+            cast_shadows: self.cast_shadows,
+            params: self.params,
+            buffers: None,
         }
     }
 }
@@ -245,32 +318,47 @@ impl MeshBuilder {
 pub struct Mesh {
     pub(crate) texture_handle: Option<ArenaId<Texture>>,
     pub material_handle: ArenaId<Pipeline>,
-    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) vertices: VertexData,
     pub(crate) indices: Indices,
+    pub(crate) instances: Vec<SpriteInstance>,
     // used for sorting
+    pub(crate) layer: i32,
+    pub(crate) sort_position: Vec3,
     pub(crate) sort_value: f32,
     pub(crate) batch: bool,
+    pub(crate) cast_shadows: bool,
+    pub(crate) params: Option<ArenaId<MaterialParams>>,
+    /// Set by [`crate::Renderer::upload_mesh`], the vertices and indices are empty then
+    pub(crate) buffers: Option<Arc<MeshBuffers>>,
 }
 impl Mesh {
+    /// `sort_value` is also used as the z of the sort position, so the mesh keeps its order
+    /// under the default [`crate::sorting::SortKey`] as well as [`crate::sorting::SortKey::Custom`]
     pub fn new(
         texture_handle: Option<ArenaId<Texture>>,
         material_handle: ArenaId<Pipeline>,
-        vertices: Vec<Vertex>,
+        vertices: impl Into<VertexData>,
         indices: Indices,
         sort_value: f32,
     ) -> Self {
         Self {
             material_handle,
             texture_handle,
-            vertices,
+            vertices: vertices.into(),
             indices,
+            instances: Vec::new(),
+            layer: 0,
+            sort_position: Vec3::Z * sort_value,
             sort_value,
             batch: true,
+            cast_shadows: false,
+            params: None,
+            buffers: None,
         }
     }
 
-    pub fn concat(&mut self, mut vertices: Vec<Vertex>, mut indices: Indices) {
-        self.vertices.append(&mut vertices);
+    pub fn concat(&mut self, vertices: VertexData, mut indices: Indices) {
+        self.vertices.append(vertices);
         self.indices.append(&mut indices);
     }
 }
@@ -293,7 +381,7 @@ impl MeshAttribute {
         }
     }
 
-    fn format(&self) -> wgpu::VertexFormat {
+    pub fn format(&self) -> wgpu::VertexFormat {
         match self {
             MeshAttribute::Position => VertexFormat::Float32x3,
             MeshAttribute::UV => VertexFormat::Float32x2,
@@ -312,7 +400,7 @@ pub enum AttributeValue {
 }
 
 impl AttributeValue {
-    fn into_mesh_attr(&self) -> MeshAttribute {
+    pub fn into_mesh_attr(&self) -> MeshAttribute {
         match self {
             AttributeValue::Position(_) => MeshAttribute::Position,
             AttributeValue::UV(_) => MeshAttribute::UV,
@@ -349,7 +437,140 @@ pub fn get_attribute_layout<'a>(
     (vertex_attribute, offset as u64)
 }
 
-#[repr(C)]
+/// The vertices of a [`Mesh`], each attribute is stored in its own contiguous array
+/// An attribute array is either empty or has one value per vertex
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexData {
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+    pub normals: Vec<[f32; 3]>,
+}
+
+/// Value used for an attribute the material needs but the mesh doesn't have
+const DEFAULT_COLOR: [f32; 4] = [1., 1., 1., 1.];
+
+impl VertexData {
+    /// Number of vertices
+    pub fn len(&self) -> usize {
+        self.positions
+            .len()
+            .max(self.uvs.len())
+            .max(self.colors.len())
+            .max(self.normals.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sets one attribute of the vertex at `index`, adding vertices if needed
+    pub fn set_attribute(&mut self, index: usize, value: AttributeValue) {
+        fn set<T: Copy>(values: &mut Vec<T>, index: usize, value: T, default: T) {
+            if values.len() <= index {
+                values.resize(index + 1, default);
+            }
+            values[index] = value;
+        }
+
+        match value {
+            AttributeValue::Position(v) => set(&mut self.positions, index, v, [0.; 3]),
+            AttributeValue::UV(v) => set(&mut self.uvs, index, v, [0.; 2]),
+            AttributeValue::Color(v) => set(&mut self.colors, index, v, DEFAULT_COLOR),
+            AttributeValue::Normal(v) => set(&mut self.normals, index, v, [0.; 3]),
+        }
+    }
+
+    /// Adds the other vertices after these ones, filling in any attribute only one side has
+    pub fn append(&mut self, mut other: VertexData) {
+        let len = self.len();
+        let other_len = other.len();
+
+        if self.positions.is_empty() != other.positions.is_empty() {
+            self.positions.resize(len, [0.; 3]);
+            other.positions.resize(other_len, [0.; 3]);
+        }
+        if self.uvs.is_empty() != other.uvs.is_empty() {
+            self.uvs.resize(len, [0.; 2]);
+            other.uvs.resize(other_len, [0.; 2]);
+        }
+        if self.colors.is_empty() != other.colors.is_empty() {
+            self.colors.resize(len, DEFAULT_COLOR);
+            other.colors.resize(other_len, DEFAULT_COLOR);
+        }
+        if self.normals.is_empty() != other.normals.is_empty() {
+            self.normals.resize(len, [0.; 3]);
+            other.normals.resize(other_len, [0.; 3]);
+        }
+
+        self.positions.append(&mut other.positions);
+        self.uvs.append(&mut other.uvs);
+        self.colors.append(&mut other.colors);
+        self.normals.append(&mut other.normals);
+    }
+
+    /// Resizes every attribute that has values to `len`
+    fn pad(&mut self, len: usize) {
+        if !self.positions.is_empty() {
+            self.positions.resize(len, [0.; 3]);
+        }
+        if !self.uvs.is_empty() {
+            self.uvs.resize(len, [0.; 2]);
+        }
+        if !self.colors.is_empty() {
+            self.colors.resize(len, DEFAULT_COLOR);
+        }
+        if !self.normals.is_empty() {
+            self.normals.resize(len, [0.; 3]);
+        }
+    }
+
+    /// Interleaves the vertices into `bytes` in the order of `attributes`,
+    /// matching the layout from [`get_attribute_layout`]
+    pub fn write_bytes(&self, attributes: &[MeshAttribute], bytes: &mut Vec<u8>) {
+        let len = self.len();
+        let stride: usize = attributes.iter().map(|attribute| attribute.size()).sum();
+        bytes.reserve(len * stride);
+
+        for index in 0..len {
+            for attribute in attributes {
+                match attribute {
+                    MeshAttribute::Position => {
+                        write_value(bytes, self.positions.get(index), [0.; 3])
+                    }
+                    MeshAttribute::UV => write_value(bytes, self.uvs.get(index), [0.; 2]),
+                    MeshAttribute::Color => {
+                        write_value(bytes, self.colors.get(index), DEFAULT_COLOR)
+                    }
+                    MeshAttribute::Normal => write_value(bytes, self.normals.get(index), [0.; 3]),
+                }
+            }
+        }
+    }
+}
+
+fn write_value<const N: usize>(bytes: &mut Vec<u8>, value: Option<&[f32; N]>, default: [f32; N]) {
+    bytes.extend_from_slice(cast_slice(value.unwrap_or(&default)));
+}
+
+impl From<Vec<Vertex>> for VertexData {
+    fn from(vertices: Vec<Vertex>) -> Self {
+        let mut data = VertexData::default();
+        for (index, vertex) in vertices.iter().enumerate() {
+            for value in vertex.0.values() {
+                data.set_attribute(index, *value);
+            }
+        }
+
+        // Pads attributes that were missing from the last vertices
+        data.pad(vertices.len());
+
+        data
+    }
+}
+
+/// A single vertex with any set of attributes
+/// Meshes store their vertices as [`VertexData`], this is kept for building meshes one vertex at a time
 #[derive(Clone, Debug)]
 pub struct Vertex(pub BTreeMap<MeshAttribute, AttributeValue>);
 
@@ -380,3 +601,67 @@ impl Vertex {
         base
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::cast_slice;
+
+    use super::{AttributeValue, Indices, MeshAttribute, Vertex, VertexData};
+
+    #[test]
+    fn add_promotes_overflowing_indices() {
+        let indices = Indices::U16(vec![0, 1, 2]).add(u16::MAX as usize - 2);
+        assert!(matches!(indices, Indices::U16(_)));
+
+        let indices = Indices::U16(vec![0, 1, 2]).add(u16::MAX as usize);
+        let Indices::U32(indices) = indices else {
+            panic!("Expected u32 indices");
+        };
+        assert_eq!(indices, vec![65535, 65536, 65537]);
+    }
+
+    #[test]
+    fn append_fills_missing_attributes() {
+        let mut data = VertexData {
+            positions: vec![[1., 2., 3.]],
+            ..Default::default()
+        };
+        data.append(VertexData {
+            positions: vec![[4., 5., 6.]],
+            colors: vec![[0.5; 4]],
+            ..Default::default()
+        });
+
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.colors, vec![[1.; 4], [0.5; 4]]);
+        assert!(data.uvs.is_empty());
+    }
+
+    #[test]
+    fn write_bytes_matches_vertex_bytes() {
+        let vertices = vec![
+            Vertex::new()
+                .with_attribute(MeshAttribute::Position, AttributeValue::Position([1.; 3]))
+                .with_attribute(MeshAttribute::UV, AttributeValue::UV([0.25, 0.75]))
+                .with_attribute(MeshAttribute::Color, AttributeValue::Color([0.5; 4])),
+            Vertex::new()
+                .with_attribute(MeshAttribute::Position, AttributeValue::Position([2.; 3]))
+                .with_attribute(MeshAttribute::UV, AttributeValue::UV([1., 0.]))
+                .with_attribute(MeshAttribute::Color, AttributeValue::Color([1.; 4])),
+        ];
+        let expected: Vec<u8> = vertices.iter().flat_map(Vertex::get_bytes).collect();
+
+        let mut bytes = Vec::new();
+        VertexData::from(vertices).write_bytes(
+            &[
+                MeshAttribute::Position,
+                MeshAttribute::UV,
+                MeshAttribute::Color,
+            ],
+            &mut bytes,
+        );
+
+        assert_eq!(bytes, expected);
+        assert_eq!(cast_slice::<u8, f32>(&bytes).len(), 2 * 9);
+    }
+}
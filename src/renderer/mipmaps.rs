@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    include_wgsl, BindGroupLayout, BindingResource, BindingType, Device, RenderPipeline, Sampler,
+    ShaderStages, TextureFormat, TextureSampleType, TextureUsages, TextureViewDimension,
+};
+
+use super::{
+    bind_groups::{BindGroupBuilder, BindGroupLayoutBuilder},
+    Renderer,
+};
+
+/// Levels in a full mip chain, down to 1x1
+pub(crate) fn mip_level_count(size: (u32, u32)) -> u32 {
+    32 - size.0.max(size.1).max(1).leading_zeros()
+}
+
+/// Whether mips of the format can be generated by rendering each level from the one above
+pub(crate) fn can_generate_mipmaps(device: &Device, format: TextureFormat) -> bool {
+    let features = format.guaranteed_format_features(device.features());
+    features
+        .allowed_usages
+        .contains(TextureUsages::RENDER_ATTACHMENT)
+        && features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+}
+
+/// Downsamples each mip level from the one above with a linear blit pass
+pub(crate) struct MipmapGenerator {
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub(crate) fn new(device: &Device) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                None,
+            )
+            .build(device, Some("mipmap_bind_group_layout"));
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipelines: HashMap::default(),
+        }
+    }
+
+    fn pipeline(&mut self, device: &Device, format: TextureFormat) -> &RenderPipeline {
+        let bind_group_layout = &self.bind_group_layout;
+        self.pipelines.entry(format).or_insert_with(|| {
+            let shader =
+                device.create_shader_module(include_wgsl!("./default_shaders/mipmap.wgsl"));
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Mipmap Pipeline Layout"),
+                bind_group_layouts: &[bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Mipmap Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vertex",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        })
+    }
+}
+
+impl Renderer {
+    /// Fills every mip level below the first from the level above
+    /// The texture needs `RENDER_ATTACHMENT` usage, see [`can_generate_mipmaps`]
+    pub(crate) fn generate_mipmaps(&mut self, texture: &wgpu::Texture) {
+        let format = texture.format();
+        let views: Vec<_> = (0..texture.mip_level_count())
+            .map(|mip_level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mip level view"),
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Mipmap encoder"),
+            });
+
+        let generator = &mut self.mipmap_generator;
+        generator.pipeline(&self.device, format);
+        let pipeline = &generator.pipelines[&format];
+
+        for pair in views.windows(2) {
+            let bind_group = BindGroupBuilder::new()
+                .append_texture_view(&pair[0])
+                .append(BindingResource::Sampler(&generator.sampler))
+                .build(
+                    &self.device,
+                    Some("Mipmap bind group"),
+                    &generator.bind_group_layout,
+                );
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &pair[1],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::mip_level_count;
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, sprite::Sprite,
+        texture::Image, transform::Transform,
+    };
+
+    #[test]
+    fn full_chain_reaches_one_texel() {
+        assert_eq!(mip_level_count((1, 1)), 1);
+        assert_eq!(mip_level_count((256, 256)), 9);
+        assert_eq!(mip_level_count((300, 20)), 9);
+        assert_eq!(mip_level_count((0, 0)), 1);
+    }
+
+    #[test]
+    fn mipmaps_average_when_minified() {
+        let mut renderer = test_renderer((2, 2));
+        // Red and blue checkerboard, every mip past the first is an even mix of both
+        // Nearest sampling without mips would pick one of the two
+        let data = (0..16 * 16)
+            .flat_map(|index| {
+                if (index % 16 + index / 16) % 2 == 0 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 255]
+                }
+            })
+            .collect();
+        let texture = renderer.add_texture(
+            Image {
+                data,
+                dimensions: (16, 16),
+                ..Default::default()
+            }
+            .with_mipmaps(true),
+        );
+        assert_eq!(
+            renderer
+                .textures
+                .get(texture)
+                .unwrap()
+                .texture
+                .mip_level_count(),
+            5
+        );
+
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(2.)),
+            ..Sprite::new(texture)
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::GREEN), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for pixel in image.data.chunks(4) {
+            assert!(
+                (150..220).contains(&pixel[0]) && (150..220).contains(&pixel[2]),
+                "pixel {pixel:?} isn't an even mix"
+            );
+        }
+    }
+}
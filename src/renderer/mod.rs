@@ -1,10 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem, num::NonZeroU64};
 
 use egui_wgpu_backend::RenderPass;
-use glam::Vec3;
+use glam::{Mat4, UVec2, Vec2, Vec3};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use wgpu::{
-    BindGroup, BindGroupLayout, BindingType, CommandEncoder, Sampler, ShaderStages,
+    BindGroup, BindGroupLayout, BindingType, CommandEncoder, RenderPipeline, Sampler, ShaderStages,
     SurfaceConfiguration, SurfaceTexture, TextureView,
 };
 
@@ -14,75 +14,170 @@ use crate::{
 };
 
 use self::{
-    batching::DrawCall,
+    batching::{DrawCall, TextureBindGroupKey},
     bind_groups::BindGroupLayoutBuilder,
-    camera::Camera,
+    camera::{Camera, CameraUniform},
+    camera_view::{ClearMode, ViewportPass},
+    depth_pass::{ShadowMap, ShadowSettings},
+    dynamic_buffer::DynamicBuffer,
     errors::RenderError,
     font_atlas::FontAtlas,
     fonts::{Font, FontSizeKey},
+    instancing::{SpriteInstanceMaterial, INSTANCE_VERTEX_COUNT},
+    lighting::{DirectionalLight, Light, LitMaterial},
+    lighting_2d::{Light2D, Lighting2D, NormalMap2DMaterial, Occluder2D},
     line::LineMaterial,
     material::DefaultMat,
+    material_params::MaterialParams,
     mesh::Mesh,
+    mipmaps::MipmapGenerator,
+    msaa::{supported_sample_counts, MsaaTargets},
     pipeline::Pipeline,
-    texture::{Texture, TextureSamplerType},
+    pixel_perfect::PixelPerfect,
+    post_processing::PostProcessing,
+    render_stats::{GpuTimer, PassStats, RenderStats},
+    shader_preprocessor::ShaderPreprocessor,
+    sorting::SortKey,
+    texture::{SamplerConfig, Texture, TextureSamplerType},
     ui::Layout,
 };
 
 pub mod batching;
 pub mod bind_groups;
 pub mod camera;
+pub mod camera_view;
 pub mod cube;
-// pub mod depth_pass;
+pub mod depth_pass;
 pub mod drawing;
+mod dynamic_buffer;
 mod dynamic_texture_atlas_builder;
 pub mod errors;
 mod font_atlas;
 pub mod fonts;
+pub mod headless;
+pub mod instancing;
+pub mod lighting;
+pub mod lighting_2d;
 pub mod line;
 pub mod material;
+pub mod material_params;
 pub mod mesh;
+mod mipmaps;
 pub mod model;
+mod msaa;
+pub mod nine_slice;
 pub mod pipeline;
+pub mod pixel_perfect;
+pub mod post_processing;
 pub mod rect;
+pub mod render_state;
+pub mod render_stats;
+pub mod render_target;
+pub mod screenshot;
+pub mod shader_preprocessor;
+pub mod sorting;
 pub mod sprite;
 pub mod text;
 pub mod texture;
 pub mod texture_atlas;
+pub mod tilemap;
 pub mod transform;
 pub mod ui;
 
+const INITIAL_VERTEX_BUFFER_SIZE: u64 = 1 << 20;
+const INITIAL_INDEX_BUFFER_SIZE: u64 = 1 << 18;
+
 pub struct MaterialMap {
     default: ArenaId<Pipeline>,
     line: ArenaId<Pipeline>,
+    sprite_instanced: ArenaId<Pipeline>,
+    lit: ArenaId<Pipeline>,
+    normal_map_2d: ArenaId<Pipeline>,
 }
 
 pub struct RenderContext {
-    pub(crate) output: SurfaceTexture,
+    /// Missing when rendering headless, there is no swapchain to present to
+    pub(crate) output: Option<SurfaceTexture>,
     pub(crate) view: TextureView,
     pub(crate) command_encoder: CommandEncoder,
+    /// The frame view while the scene renders into a post processing target
+    pub(crate) post_process_output: Option<TextureView>,
+    /// The surface view while the frame renders into the capture target, see [`Renderer::request_frame_capture`]
+    pub(crate) surface_view: Option<TextureView>,
 }
 
 pub struct Renderer {
+    /// Sort key for layers that don't have one set with [`Renderer::set_layer_sort_key`]
     pub sorting_axis: Vec3,
+    pub(crate) layer_sort_keys: HashMap<i32, SortKey>,
     pub(crate) fonts: Arena<Font>,
     pub(crate) font_atlases: HashMap<(FontSizeKey, ArenaId<Font>), FontAtlas>,
     pub textures: Arena<Texture>,
     pub device: wgpu::Device,
     pub(crate) meshes: Vec<Mesh>,
     pub(crate) default_texture_samplers: HashMap<TextureSamplerType, ArenaId<Sampler>>,
+    /// Samplers created by [`Renderer::get_sampler`], so textures with the same config share one
+    pub(crate) sampler_cache: HashMap<SamplerConfig, ArenaId<Sampler>>,
     pub samplers: Arena<Sampler>,
     camera_bind_group_layout: BindGroupLayout,
+    camera_buffer: DynamicBuffer,
+    camera_bind_group: BindGroup,
+    vertex_buffer: DynamicBuffer,
+    index_buffer: DynamicBuffer,
+    /// Colors for the camera viewport clears, kept apart so a growing vertex buffer can't move them
+    clear_color_buffer: DynamicBuffer,
+    /// Indexed by whether the texture is filterable
+    texture_bind_group_layouts: [BindGroupLayout; 2],
+    pub(crate) texture_bind_groups: HashMap<TextureBindGroupKey, BindGroup>,
+    pub(crate) clear_pipelines: HashMap<(bool, bool), RenderPipeline>,
     queue: wgpu::Queue,
-    surface: wgpu::Surface,
+    surface: Option<wgpu::Surface>,
     surface_config: SurfaceConfiguration,
     materials: Arena<Pipeline>,
+    pub(crate) shader_errors: HashMap<ArenaId<Pipeline>, String>,
+    pub(crate) shader_preprocessor: ShaderPreprocessor,
+    pub(crate) mipmap_generator: MipmapGenerator,
+    pub(crate) params: Arena<MaterialParams>,
+    /// Removed by [`Renderer::end_frame`], once no queued mesh can still read them
+    pub(crate) removed_params: Vec<ArenaId<MaterialParams>>,
     pub(crate) material_map: MaterialMap,
     #[cfg(feature = "egui")]
     egui_render_pass: egui_wgpu_backend::RenderPass,
-    pub(crate) ui_render_data: Vec<Mesh>,
     pub(crate) current_layout: Vec<Layout>,
     pub(crate) depth_texture_handle: ArenaId<Texture>,
     pub mode_3d: bool,
+    /// Lights [`LitMaterial`] and casts the 3D shadows
+    pub directional_light: DirectionalLight,
+    pub ambient_light: Color,
+    /// Point and spot lights [`LitMaterial`] is lit by, kept until removed
+    pub lights: Vec<Light>,
+    pub(crate) shadow_map: ShadowMap,
+    /// Lights the 2D scene drawn by cameras with an ambient light, kept until removed
+    pub lights_2d: Vec<Light2D>,
+    /// Shapes casting 2D shadows, kept until removed
+    pub occluders_2d: Vec<Occluder2D>,
+    /// Ambient level of the 2D lighting for [`Renderer::render`], `None` leaves it unlit
+    /// Cameras set their own with [`camera_view::CameraView::ambient_light_2d`]
+    pub ambient_light_2d: Option<Color>,
+    /// Normal maps of the queued sprites, drawn when their camera is lit
+    pub(crate) normal_meshes_2d: Vec<Mesh>,
+    pub(crate) lighting_2d: Lighting2D,
+    pub(crate) headless_target: Option<ArenaId<Texture>>,
+    /// Whether the next frame renders into `capture_target`
+    pub(crate) capture_requested: bool,
+    /// Copyable stand-in for the surface texture, created for the first capture
+    pub(crate) capture_target: Option<ArenaId<Texture>>,
+    pub(crate) pixel_perfect: Option<PixelPerfect>,
+    pub(crate) post_processing: PostProcessing,
+    msaa_samples: u32,
+    supported_msaa_samples: Vec<u32>,
+    /// Keyed by the depth texture of the target they resolve into
+    pub(crate) msaa_targets: HashMap<ArenaId<Texture>, MsaaTargets>,
+    /// Stats of the frame being recorded
+    pub(crate) frame_stats: RenderStats,
+    pub(crate) render_stats: RenderStats,
+    /// None when the adapter can't time passes
+    pub(crate) gpu_timer: Option<GpuTimer>,
 }
 
 impl Renderer {
@@ -121,6 +216,8 @@ impl Renderer {
             .find(|f| f.is_srgb())
             .unwrap();
         let surface_config = wgpu::SurfaceConfiguration {
+            // Not every backend can copy out of the surface,
+            // captured frames render into their own texture, see Renderer::request_frame_capture
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: viewport_size.0,
@@ -133,7 +230,9 @@ impl Renderer {
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                            | wgpu::Features::TIMESTAMP_QUERY),
                     // Webgl 2 for web until WGPU is fully supported
                     limits: if cfg!(target_arch = "wasm32") {
                         wgpu::Limits::downlevel_webgl2_defaults()
@@ -148,23 +247,31 @@ impl Renderer {
 
         surface.configure(&device, &surface_config);
 
-        let default_sampler_nearest = {
-            device.create_sampler(&wgpu::SamplerDescriptor {
-                mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
-            })
-        };
+        let supported_msaa_samples =
+            supported_sample_counts(&adapter, device.features(), surface_format);
 
-        let default_sampler_linear = {
-            device.create_sampler(&wgpu::SamplerDescriptor {
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Linear,
-                mipmap_filter: wgpu::FilterMode::Linear,
-                ..Default::default()
-            })
-        };
+        Ok(Self::from_device(
+            device,
+            queue,
+            Some(surface),
+            surface_config,
+            supported_msaa_samples,
+        ))
+    }
+
+    /// Shared setup for windowed and headless renderers
+    /// Creates the default samplers, textures, materials and font
+    pub(crate) fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface: Option<wgpu::Surface>,
+        surface_config: SurfaceConfiguration,
+        supported_msaa_samples: Vec<u32>,
+    ) -> Self {
+        let surface_format = surface_config.format;
+
+        let default_sampler_nearest = SamplerConfig::nearest().create_sampler(&device);
+        let default_sampler_linear = SamplerConfig::linear().create_sampler(&device);
 
         let depth_texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -185,36 +292,94 @@ impl Renderer {
         let default_sampler_nearest = samplers.insert(default_sampler_nearest);
         let depth_texture_sampler_handle = samplers.insert(depth_texture_sampler);
 
+        // Every render call writes its camera at a new dynamic offset, so passes recorded
+        // in the same frame don't overwrite each other
         let camera_bind_group_layout = BindGroupLayoutBuilder::new()
             .append(
                 ShaderStages::VERTEX,
                 BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(mem::size_of::<CameraUniform>() as u64),
                 },
                 None,
             )
             .build(&device, Some("camera_bind_group_layout"));
 
+        let camera_buffer = DynamicBuffer::new(
+            &device,
+            "Camera Buffer",
+            wgpu::BufferUsages::UNIFORM,
+            device.limits().min_uniform_buffer_offset_alignment as u64,
+            4 * device.limits().min_uniform_buffer_offset_alignment as u64,
+        );
+        let camera_bind_group =
+            create_camera_bind_group(&device, &camera_bind_group_layout, &camera_buffer);
+
+        let vertex_buffer = DynamicBuffer::new(
+            &device,
+            "Vertex Buffer",
+            wgpu::BufferUsages::VERTEX,
+            wgpu::COPY_BUFFER_ALIGNMENT,
+            INITIAL_VERTEX_BUFFER_SIZE,
+        );
+        let index_buffer = DynamicBuffer::new(
+            &device,
+            "Index Buffer",
+            wgpu::BufferUsages::INDEX,
+            wgpu::COPY_BUFFER_ALIGNMENT,
+            INITIAL_INDEX_BUFFER_SIZE,
+        );
+
+        let clear_color_buffer = DynamicBuffer::new(
+            &device,
+            "Clear Color Buffer",
+            wgpu::BufferUsages::VERTEX,
+            wgpu::COPY_BUFFER_ALIGNMENT,
+            256,
+        );
+
+        let texture_bind_group_layouts = [
+            Texture::create_bind_group_layout(&device, false),
+            Texture::create_bind_group_layout(&device, true),
+        ];
+
         let blank_texture = Texture::create_blank_texture(&device, &queue, default_sampler_nearest);
 
         let mut textures = Arena::new();
 
         let depth_texture = Texture::create_depth_texture(
             &device,
-            &surface_config,
+            (surface_config.width, surface_config.height),
             depth_texture_sampler_handle.clone(),
         );
 
         textures.insert(blank_texture);
         let depth_texture_handle = textures.insert(depth_texture);
 
+        let shadow_map = ShadowMap::new(
+            &device,
+            ShadowSettings::default(),
+            depth_texture_sampler_handle,
+        );
+
+        let lighting_2d = Lighting2D::new(&device);
+        let mipmap_generator = MipmapGenerator::new(&device);
+
         let mut render_buddy = Self {
             sorting_axis: Vec3::Z,
+            layer_sort_keys: HashMap::default(),
             #[cfg(feature = "egui")]
             egui_render_pass: RenderPass::new(&device, surface_format, 1),
             camera_bind_group_layout,
+            camera_buffer,
+            camera_bind_group,
+            vertex_buffer,
+            index_buffer,
+            clear_color_buffer,
+            texture_bind_group_layouts,
+            texture_bind_groups: HashMap::default(),
+            clear_pipelines: HashMap::default(),
             font_atlases: HashMap::default(),
             fonts: Arena::new(),
             device,
@@ -229,52 +394,95 @@ impl Renderer {
                 (TextureSamplerType::Nearest, default_sampler_nearest),
                 (TextureSamplerType::Depth, depth_texture_sampler_handle),
             ]),
+            sampler_cache: HashMap::from([
+                (SamplerConfig::linear(), default_sampler_linear),
+                (SamplerConfig::nearest(), default_sampler_nearest),
+            ]),
             materials: Arena::new(),
+            shader_errors: HashMap::default(),
+            shader_preprocessor: ShaderPreprocessor::default(),
+            mipmap_generator,
+            params: Arena::new(),
+            removed_params: Vec::new(),
             material_map: MaterialMap {
                 default: ArenaId::default(),
                 line: ArenaId::default(),
+                sprite_instanced: ArenaId::default(),
+                lit: ArenaId::default(),
+                normal_map_2d: ArenaId::default(),
             },
-            ui_render_data: Vec::default(),
             current_layout: Vec::default(),
             depth_texture_handle,
             mode_3d: false,
+            directional_light: DirectionalLight::default(),
+            ambient_light: Color::rgb(0.2, 0.2, 0.2),
+            lights: Vec::new(),
+            shadow_map,
+            lights_2d: Vec::new(),
+            occluders_2d: Vec::new(),
+            ambient_light_2d: None,
+            normal_meshes_2d: Vec::new(),
+            lighting_2d,
+            headless_target: None,
+            capture_requested: false,
+            capture_target: None,
+            pixel_perfect: None,
+            post_processing: PostProcessing::default(),
+            msaa_samples: 1,
+            supported_msaa_samples,
+            msaa_targets: HashMap::default(),
+            frame_stats: RenderStats::default(),
+            render_stats: RenderStats::default(),
+            gpu_timer: None,
         };
 
-        let default_mat = DefaultMat {};
-        let render_pipeline = render_buddy.create_pipeline_from_material(&default_mat);
-        let material_handle = render_buddy.materials.insert(Pipeline {
-            render_pipeline,
-            material: Box::from(default_mat),
-        });
-        render_buddy.material_map.default = material_handle;
+        render_buddy.gpu_timer = GpuTimer::new(&render_buddy.device, &render_buddy.queue);
 
-        let line_mat = LineMaterial;
+        render_buddy.material_map.default = render_buddy.push_material(DefaultMat {});
+        render_buddy.material_map.line = render_buddy.push_material(LineMaterial);
 
-        let render_pipeline = render_buddy.create_pipeline_from_material(&line_mat);
-
-        let line_material_handle = render_buddy.materials.insert(Pipeline {
-            render_pipeline,
-            material: Box::from(line_mat),
-        });
-
-        render_buddy.material_map.line = line_material_handle;
+        render_buddy.material_map.sprite_instanced =
+            render_buddy.push_material(SpriteInstanceMaterial);
+        render_buddy.material_map.lit = render_buddy.push_material(LitMaterial);
+        render_buddy.material_map.normal_map_2d = render_buddy.push_material(NormalMap2DMaterial);
+        render_buddy.add_builtin_post_effects();
 
         render_buddy.fonts.insert(
             Font::try_from_bytes(include_bytes!("./default_font/Roboto-Regular.ttf")).unwrap(),
         );
 
-        Ok(render_buddy)
+        render_buddy
     }
     /// Begin the render process by prepping the [`RenderContext`]
-    pub fn begin(&self) -> RenderContext {
-        let output = self
-            .surface
-            .get_current_texture()
-            .expect("Missing current texture in surface");
+    pub fn begin(&mut self) -> RenderContext {
+        let (output, view) = match &self.surface {
+            Some(surface) => {
+                let output = surface
+                    .get_current_texture()
+                    .expect("Missing current texture in surface");
+
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                (Some(output), view)
+            }
+            None => {
+                let target = self
+                    .headless_target
+                    .and_then(|handle| self.textures.get(handle))
+                    .expect("Headless renderer is missing its target texture");
+
+                let view = target
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                (None, view)
+            }
+        };
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (view, surface_view) = self.begin_frame_capture(view);
+        let (view, post_process_output) = self.begin_post_processing(view);
 
         let command_encoder = self
             .device
@@ -286,6 +494,8 @@ impl Renderer {
             output,
             view,
             command_encoder,
+            post_process_output,
+            surface_view,
         }
     }
 
@@ -314,6 +524,7 @@ impl Renderer {
             &screen_descriptor,
         );
 
+        let timed_pass = self.begin_timed_pass(&mut render_context.command_encoder, "Egui");
         self.egui_render_pass
             .execute(
                 &mut render_context.command_encoder,
@@ -323,6 +534,7 @@ impl Renderer {
                 None,
             )
             .unwrap();
+        self.end_timed_pass(&mut render_context.command_encoder, timed_pass);
     }
 
     #[cfg(feature = "egui")]
@@ -338,12 +550,119 @@ impl Renderer {
         clear_color: Option<Color>,
         camera: &Camera,
     ) {
-        let mesh_prepared_batch = self.prepare_mesh_batch();
-        let camera_bind_group = camera.create_bind_group(
-            &self.device,
-            (self.surface_config.width, self.surface_config.height),
-            &self.camera_bind_group_layout,
+        if let Some(target) = self.pixel_perfect.as_ref().map(|p| p.target) {
+            let view = self
+                .textures
+                .get(target.texture)
+                .unwrap()
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
+            self.render_to_view(
+                &mut render_context.command_encoder,
+                &view,
+                target.depth_texture,
+                (target.size.x, target.size.y),
+                clear_color,
+                camera,
+            );
+            self.upscale_pixel_perfect(render_context);
+
+            return;
+        }
+
+        let viewport_size = self.get_viewport_size();
+        self.render_to_view(
+            &mut render_context.command_encoder,
+            &render_context.view,
+            self.depth_texture_handle,
+            viewport_size,
+            clear_color,
+            camera,
+        );
+    }
+
+    /// Renders and drains the mesh queue into the given color view
+    pub(crate) fn render_to_view(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        view: &TextureView,
+        depth_texture_handle: ArenaId<Texture>,
+        viewport_size: (u32, u32),
+        clear_color: Option<Color>,
+        camera: &Camera,
+    ) {
+        self.render_viewport(
+            command_encoder,
+            view,
+            depth_texture_handle,
+            clear_color,
+            camera,
+            Some(ViewportPass {
+                offset: Vec2::ZERO,
+                size: UVec2::from(viewport_size).as_vec2(),
+                clear_mode: ClearMode::None,
+                ambient_light_2d: self.ambient_light_2d,
+            }),
         );
+    }
+
+    /// Renders and drains the mesh queue, `clear_color` clears the whole view
+    /// while the [`ViewportPass`] only covers part of it
+    pub(crate) fn render_viewport(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        view: &TextureView,
+        depth_texture_handle: ArenaId<Texture>,
+        clear_color: Option<Color>,
+        camera: &Camera,
+        viewport: Option<ViewportPass>,
+    ) {
+        let mesh_prepared_batch = self.prepare_mesh_batch(camera.position);
+        // 2D passes only get a depth-stencil attachment when a queued material tests or writes it
+        let use_depth_stencil = self.mode_3d
+            || mesh_prepared_batch.iter().any(|draw_call| {
+                self.materials
+                    .get(draw_call.material_handle)
+                    .is_some_and(|pipeline| pipeline.material.use_depth_stencil())
+            });
+        if use_depth_stencil {
+            self.cache_depth_pass_pipelines(&mesh_prepared_batch);
+        }
+
+        let viewport_size = viewport.as_ref().map_or((1, 1), |viewport| {
+            (viewport.size.x as u32, viewport.size.y as u32)
+        });
+
+        if self.mode_3d {
+            self.render_shadow_maps(command_encoder, &mesh_prepared_batch, camera, viewport_size);
+        }
+
+        let camera_offset = self.write_camera_uniform(camera, viewport_size);
+
+        // Only the viewport is cleared for cameras that don't cover the whole view,
+        // and the depth has to be reset for every camera
+        let viewport_clear = viewport.as_ref().and_then(|viewport| {
+            let clear_color = match viewport.clear_mode {
+                ClearMode::Color(color) => Some(color),
+                ClearMode::None if use_depth_stencil => None,
+                ClearMode::None => return None,
+            };
+            let color: [f32; 4] = clear_color.map_or([0.; 4], Color::as_linear_rgba_f32);
+            let offset = self.clear_color_buffer.write(
+                &self.device,
+                &self.queue,
+                bytemuck::cast_slice(&color),
+            );
+
+            Some((clear_color.is_some(), offset))
+        });
+        if let Some((write_color, _)) = viewport_clear {
+            self.clear_pipeline(write_color, use_depth_stencil);
+        }
+
+        let target_size = self.textures.get(depth_texture_handle).unwrap().dimensions;
+        let msaa_views = self.msaa_views(depth_texture_handle, target_size.as_uvec2());
 
         let load = if let Some(clear_color) = clear_color {
             wgpu::LoadOp::Clear(clear_color.into())
@@ -351,66 +670,187 @@ impl Renderer {
             wgpu::LoadOp::Load
         };
 
-        {
-            let mut render_pass =
-                render_context
-                    .command_encoder
-                    .begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: Some("Render Pass"),
-                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &render_context.view,
-                            resolve_target: None,
-                            ops: wgpu::Operations { load, store: true },
-                        })],
-                        depth_stencil_attachment: if self.mode_3d {
-                            dbg!("FIRES depth stencil");
-                            Some(wgpu::RenderPassDepthStencilAttachment {
-                                view: &self.textures.get(self.depth_texture_handle).unwrap().view,
-                                depth_ops: Some(wgpu::Operations {
-                                    load: wgpu::LoadOp::Clear(1.0),
-                                    store: true,
-                                }),
-                                stencil_ops: None,
-                            })
-                        } else {
-                            None
-                        },
-                    });
+        let timed_pass = self.begin_timed_pass(command_encoder, "Scene");
+        let pass_stats = {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: msaa_views.as_ref().map_or(view, |(color, _)| color),
+                    resolve_target: msaa_views.as_ref().map(|_| view),
+                    ops: wgpu::Operations { load, store: true },
+                })],
+                depth_stencil_attachment: if use_depth_stencil {
+                    Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: msaa_views.as_ref().map_or(
+                            &self.textures.get(depth_texture_handle).unwrap().view,
+                            |(_, depth)| depth,
+                        ),
+                        depth_ops: Some(wgpu::Operations {
+                            load: if clear_color.is_some() {
+                                wgpu::LoadOp::Clear(1.0)
+                            } else {
+                                wgpu::LoadOp::Load
+                            },
+                            store: true,
+                        }),
+                        stencil_ops: Some(wgpu::Operations {
+                            load: if clear_color.is_some() {
+                                wgpu::LoadOp::Clear(0)
+                            } else {
+                                wgpu::LoadOp::Load
+                            },
+                            store: true,
+                        }),
+                    })
+                } else {
+                    None
+                },
+            });
+
+            if let Some(viewport) = &viewport {
+                render_pass.set_viewport(
+                    viewport.offset.x,
+                    viewport.offset.y,
+                    viewport.size.x,
+                    viewport.size.y,
+                    0.,
+                    1.,
+                );
+            }
+
+            if let Some((write_color, offset)) = viewport_clear {
+                render_pass.set_pipeline(&self.clear_pipelines[&(write_color, use_depth_stencil)]);
+                render_pass.set_vertex_buffer(0, self.clear_color_buffer.buffer().slice(offset..));
+                render_pass.draw(0..3, 0..1);
+            }
+
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[camera_offset]);
 
             render_queued_draw_calls(
                 &mesh_prepared_batch,
                 &mut render_pass,
+                use_depth_stencil,
                 &self.materials,
-                &camera_bind_group,
+                &self.params,
+                &self.texture_bind_groups,
+                self.vertex_buffer.buffer(),
+                self.index_buffer.buffer(),
+            )
+        };
+        self.end_timed_pass(command_encoder, timed_pass);
+        self.frame_stats.add_pass(&pass_stats);
+
+        let ambient_light_2d = viewport
+            .as_ref()
+            .and_then(|viewport| viewport.ambient_light_2d);
+        match ambient_light_2d {
+            Some(ambient) if !self.mode_3d => self.render_lighting_2d(
+                command_encoder,
+                view,
+                depth_texture_handle,
+                camera,
+                viewport.as_ref().unwrap(),
+                ambient,
+            ),
+            _ => self.normal_meshes_2d.clear(),
+        }
+    }
+
+    /// Writes the camera at a new offset in the camera buffer, returning the dynamic offset
+    pub(crate) fn write_camera_uniform(
+        &mut self,
+        camera: &Camera,
+        viewport_size: (u32, u32),
+    ) -> u32 {
+        self.write_camera_data(camera.create_uniform(viewport_size))
+    }
+
+    /// Same as [`Renderer::write_camera_uniform`] for a view projection that isn't from a [`Camera`]
+    pub(crate) fn write_view_projection(&mut self, view_projection: Mat4) -> u32 {
+        self.write_camera_data(CameraUniform {
+            view_proj: view_projection.to_cols_array_2d(),
+        })
+    }
+
+    fn write_camera_data(&mut self, uniform: CameraUniform) -> u32 {
+        let camera_capacity = self.camera_buffer.capacity();
+        let camera_offset =
+            self.camera_buffer
+                .write(&self.device, &self.queue, bytemuck::cast_slice(&[uniform]));
+        if camera_capacity != self.camera_buffer.capacity() {
+            self.camera_bind_group = create_camera_bind_group(
+                &self.device,
+                &self.camera_bind_group_layout,
+                &self.camera_buffer,
             );
         }
+
+        camera_offset as u32
     }
 
     /// Presents the frame to WGPU for rendering
     /// Drops the [`RenderContext`]
-    pub fn end_frame(&mut self, render_context: RenderContext) {
+    pub fn end_frame(&mut self, mut render_context: RenderContext) {
+        self.finish_frame_capture(&mut render_context);
+        if let Some(timer) = &self.gpu_timer {
+            timer.resolve(&mut render_context.command_encoder);
+        }
         self.queue
             .submit(std::iter::once(render_context.command_encoder.finish()));
-        render_context.output.present();
+        if let Some(output) = render_context.output {
+            output.present();
+        }
+        self.finish_frame_stats();
+
+        self.camera_buffer.reset();
+        self.vertex_buffer.reset();
+        self.index_buffer.reset();
+        self.clear_color_buffer.reset();
+        self.shadow_map.reset();
+        self.lighting_2d.reset();
+        for id in self.removed_params.drain(..) {
+            self.params.remove(id);
+        }
     }
 
     /// Should be called when the window has been resized
     pub fn resize(&mut self, new_surface_size: (u32, u32)) {
         self.surface_config.width = new_surface_size.0;
         self.surface_config.height = new_surface_size.1;
-        self.surface.configure(&self.device, &self.surface_config);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
+        }
+
+        if let Some(handle) = self.headless_target {
+            self.replace_texture(
+                handle,
+                Texture::create_render_target(
+                    &self.device,
+                    new_surface_size,
+                    self.surface_config.format,
+                    *self
+                        .default_texture_samplers
+                        .get(&TextureSamplerType::Nearest)
+                        .unwrap(),
+                ),
+            );
+        }
 
         self.replace_texture(
             self.depth_texture_handle,
             Texture::create_depth_texture(
                 &self.device,
-                &self.surface_config,
+                new_surface_size,
                 *self
                     .default_texture_samplers
                     .get(&TextureSamplerType::Depth)
                     .unwrap(),
             ),
         );
+
+        self.drop_post_process_targets();
+        self.drop_capture_target();
+        self.msaa_targets.clear();
     }
 
     pub fn set_sorting_axis(&mut self, sorting_axis: Vec3) {
@@ -425,39 +865,95 @@ impl Renderer {
         self.meshes.push(mesh);
     }
 
+    pub(crate) fn texture_bind_group_layout(&self, filterable: bool) -> &BindGroupLayout {
+        &self.texture_bind_group_layouts[filterable as usize]
+    }
+
     pub(crate) fn get_viewport_size(&self) -> (u32, u32) {
         (self.surface_config.width, self.surface_config.height)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_queued_draw_calls<'a>(
     draw_calls: &'a Vec<DrawCall>,
     render_pass: &mut wgpu::RenderPass<'a>,
+    depth_stencil: bool,
     materials: &'a Arena<Pipeline>,
-    camera_bind_group: &'a BindGroup,
-) {
-    let last_material = ArenaId::default();
+    params: &'a Arena<MaterialParams>,
+    texture_bind_groups: &'a HashMap<TextureBindGroupKey, BindGroup>,
+    vertex_buffer: &'a wgpu::Buffer,
+    index_buffer: &'a wgpu::Buffer,
+) -> PassStats {
+    let mut last_material = None;
+    // Groups bound after the camera since the pipeline was set, rebinding one of them is skipped
+    let mut bound_groups: Vec<&BindGroup> = Vec::new();
+    let mut stats = PassStats::default();
 
     for draw_call in draw_calls {
-        if draw_call.material_handle != last_material {
+        if last_material != Some(draw_call.material_handle) {
             let pipeline: &Pipeline = materials
                 .get(draw_call.material_handle)
                 .expect("Mesh was given invalid pipeline id");
-            render_pass.set_pipeline(&pipeline.render_pipeline);
-
-            render_pass.set_bind_group(0, &camera_bind_group, &[]); // can probably do this once before the loop
+            render_pass.set_pipeline(pipeline.render_pipeline(depth_stencil));
+            render_pass.set_stencil_reference(pipeline.material.stencil_reference());
+            last_material = Some(draw_call.material_handle);
+            bound_groups.clear();
+            stats.pipeline_switches += 1;
         }
 
-        for (i, bind_group) in draw_call.bind_groups.iter().enumerate() {
-            render_pass.set_bind_group(i as u32 + 1, &bind_group, &[]);
+        let texture_group = draw_call
+            .texture_bind_group
+            .as_ref()
+            .map(|key| &texture_bind_groups[key]);
+        let params_group = draw_call
+            .params
+            .map(|id| params[id].bind_group.as_ref().unwrap());
+        let groups = texture_group
+            .into_iter()
+            .chain(params_group)
+            .chain(draw_call.bind_groups.iter());
+        for (index, bind_group) in groups.enumerate() {
+            match bound_groups.get_mut(index) {
+                Some(bound) if std::ptr::eq(*bound, bind_group) => continue,
+                Some(bound) => *bound = bind_group,
+                None => bound_groups.push(bind_group),
+            }
+            render_pass.set_bind_group(index as u32 + 1, bind_group, &[]);
+            stats.bind_group_switches += 1;
         }
 
-        render_pass.set_vertex_buffer(0, draw_call.vertex_buffer.slice(..));
-        if draw_call.indices_len > 0 {
-            render_pass.set_index_buffer(draw_call.index_buffer.slice(..), draw_call.index_format);
+        render_pass.set_vertex_buffer(0, draw_call.vertex_slice(vertex_buffer));
+        if draw_call.instance_count > 0 {
+            render_pass.draw(0..INSTANCE_VERTEX_COUNT, 0..draw_call.instance_count);
+        } else if draw_call.indices_len > 0 {
+            render_pass
+                .set_index_buffer(draw_call.index_slice(index_buffer), draw_call.index_format);
             render_pass.draw_indexed(0..draw_call.indices_len, 0, 0..1);
         } else {
             render_pass.draw(0..draw_call.vert_len, 0..1);
         }
+        stats.draw_calls += 1;
     }
+
+    stats
+}
+
+fn create_camera_bind_group(
+    device: &wgpu::Device,
+    layout: &BindGroupLayout,
+    camera_buffer: &DynamicBuffer,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: camera_buffer.buffer(),
+                offset: 0,
+                size: NonZeroU64::new(mem::size_of::<CameraUniform>() as u64),
+            }),
+        }],
+        label: Some("Camera bind group"),
+        layout,
+    })
 }
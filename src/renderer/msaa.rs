@@ -0,0 +1,182 @@
+use glam::UVec2;
+use wgpu::{TextureFormat, TextureView};
+
+use crate::arena::ArenaId;
+
+use super::{errors::RenderError, render_state::DEPTH_FORMAT, texture::Texture, Renderer};
+
+/// Multisampled color and depth textures a target is drawn into before resolving
+pub(crate) struct MsaaTargets {
+    color: wgpu::Texture,
+    depth: wgpu::Texture,
+    size: UVec2,
+}
+
+/// Sample counts both the color and depth formats support on the adapter
+/// Counts other than 1 and 4 need `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` on the device
+pub(crate) fn supported_sample_counts(
+    adapter: &wgpu::Adapter,
+    features: wgpu::Features,
+    color_format: TextureFormat,
+) -> Vec<u32> {
+    let adapter_specific =
+        features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+    [1, 2, 4, 8, 16]
+        .into_iter()
+        .filter(|count| adapter_specific || *count == 1 || *count == 4)
+        .filter(|count| {
+            [color_format, DEPTH_FORMAT].iter().all(|format| {
+                adapter
+                    .get_texture_format_features(*format)
+                    .flags
+                    .sample_count_supported(*count)
+            })
+        })
+        .collect()
+}
+
+impl Renderer {
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// Sample counts [`Renderer::set_msaa_samples`] accepts, always includes 1
+    pub fn supported_msaa_samples(&self) -> &[u32] {
+        &self.supported_msaa_samples
+    }
+
+    /// Sets how many samples every mesh pass uses, 1 turns MSAA off
+    /// Recreates the pipeline of every material
+    pub fn set_msaa_samples(&mut self, samples: u32) -> Result<(), RenderError> {
+        if !self.supported_msaa_samples.contains(&samples) {
+            return Err(RenderError::new(format!(
+                "{samples}x MSAA isn't supported, supported sample counts are {:?}",
+                self.supported_msaa_samples
+            )));
+        }
+        if samples == self.msaa_samples {
+            return Ok(());
+        }
+
+        self.msaa_samples = samples;
+        self.msaa_targets.clear();
+        self.clear_pipelines.clear();
+        self.lighting_2d.pipeline = None;
+
+        let material_ids: Vec<_> = self.materials.ids().collect();
+        for id in material_ids {
+            let render_pipeline = self.rebuild_pipeline(self.materials.get(id).unwrap(), false);
+            let pipeline = self.materials.get_mut(id).unwrap();
+            pipeline.render_pipeline = render_pipeline;
+            pipeline.depth_pass_pipeline = None;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the multisampled color and depth views to draw into instead of the target,
+    /// or `None` without MSAA
+    /// `key` is a texture only this target uses, so targets of the same size don't share contents
+    pub(crate) fn msaa_views(
+        &mut self,
+        key: ArenaId<Texture>,
+        size: UVec2,
+    ) -> Option<(TextureView, TextureView)> {
+        if self.msaa_samples == 1 {
+            return None;
+        }
+
+        if self
+            .msaa_targets
+            .get(&key)
+            .filter(|targets| targets.size == size)
+            .is_none()
+        {
+            let targets = MsaaTargets {
+                color: create_multisampled_texture(
+                    &self.device,
+                    size,
+                    self.surface_config.format,
+                    self.msaa_samples,
+                ),
+                depth: create_multisampled_texture(
+                    &self.device,
+                    size,
+                    DEPTH_FORMAT,
+                    self.msaa_samples,
+                ),
+                size,
+            };
+            self.msaa_targets.insert(key, targets);
+        }
+        let targets = &self.msaa_targets[&key];
+
+        Some((
+            targets
+                .color
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            targets
+                .depth
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        ))
+    }
+}
+
+fn create_multisampled_texture(
+    device: &wgpu::Device,
+    size: UVec2,
+    format: TextureFormat,
+    samples: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisampled texture"),
+        size: wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: samples,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        sprite::Sprite, transform::Transform,
+    };
+
+    #[test]
+    fn msaa_smooths_edges() {
+        let mut renderer = test_renderer((8, 8));
+        renderer.set_msaa_samples(4).unwrap();
+
+        // The left edge of the sprite falls halfway through a pixel
+        let sprite = Sprite {
+            color: Color::BLUE.as_rgba_f32(),
+            custom_size: Some(Vec2::splat(4.)),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::from_position(Vec3::new(0.5, 0., 0.)));
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        let edge = &image.data[(4 * 8 + 2) * 4..][..4];
+        assert!(
+            edge[0] > 0 && edge[2] > 0,
+            "edge pixel {edge:?} isn't blended"
+        );
+        assert!(renderer.set_msaa_samples(3).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use glam::Vec2;
+
+use crate::mesh::{Indices, Mesh, MeshBuilder, VertexData};
+
+use super::{rect::Rect, sprite::Sprite, transform::Transform, Renderer};
+
+/// Border widths in texture pixels, they're drawn at their pixel size while the rest scales
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SliceBorder {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl SliceBorder {
+    pub fn new(left: f32, right: f32, top: f32, bottom: f32) -> Self {
+        Self {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    /// The same width on every side
+    pub fn all(width: f32) -> Self {
+        Self::new(width, width, width, width)
+    }
+}
+
+/// How the parts between the corners fill their size
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SliceScaleMode {
+    #[default]
+    Stretch,
+    /// Repeats the part at its pixel size, the last repeat is cut off
+    Tile,
+}
+
+/// A sprite cut into a 3x3 grid, the corners keep their size and the edges and center
+/// scale to fit [`Sprite::custom_size`], for UI frames, panels and buttons
+/// Flips and normal maps of the sprite aren't applied
+#[derive(Clone, Copy, Debug)]
+pub struct NineSlice {
+    /// Cut from [`Sprite::texture_rect`], or the whole texture without one
+    pub sprite: Sprite,
+    pub border: SliceBorder,
+    pub edges: SliceScaleMode,
+    pub center: SliceScaleMode,
+}
+
+impl NineSlice {
+    pub fn new(sprite: Sprite, border: SliceBorder) -> Self {
+        Self {
+            sprite,
+            border,
+            edges: SliceScaleMode::default(),
+            center: SliceScaleMode::default(),
+        }
+    }
+
+    pub fn with_size(mut self, size: Vec2) -> Self {
+        self.sprite.custom_size = Some(size);
+        self
+    }
+
+    pub fn with_edges(mut self, edges: SliceScaleMode) -> Self {
+        self.edges = edges;
+        self
+    }
+
+    pub fn with_center(mut self, center: SliceScaleMode) -> Self {
+        self.center = center;
+        self
+    }
+}
+
+/// Splits `target` into the three spans of one axis, borders shrink evenly when they don't fit
+fn target_spans(target: f32, start: f32, end: f32) -> [(f32, f32); 3] {
+    let scale = if start + end > target {
+        target / (start + end)
+    } else {
+        1.
+    };
+    let (start, end) = (start * scale, end * scale);
+
+    [(0., start), (start, target - end), (target - end, target)]
+}
+
+/// Cuts a span into repeats of `source` pixels, with the part of the source each repeat shows
+fn tile_span(target: (f32, f32), source: (f32, f32), mode: SliceScaleMode) -> Vec<[(f32, f32); 2]> {
+    let source_size = source.1 - source.0;
+    if mode == SliceScaleMode::Stretch || source_size <= 0. {
+        return vec![[target, source]];
+    }
+
+    let mut tiles = Vec::new();
+    let mut start = target.0;
+    while start < target.1 {
+        let end = (start + source_size).min(target.1);
+        tiles.push([(start, end), (source.0, source.0 + end - start)]);
+        start = end;
+    }
+
+    tiles
+}
+
+impl Renderer {
+    /// Builds the mesh of every slice, see [`Renderer::draw_nine_slice`]
+    pub fn nine_slice_to_mesh(&self, nine_slice: &NineSlice, mut transform: Transform) -> Mesh {
+        let sprite = &nine_slice.sprite;
+        let image_size = self
+            .textures
+            .get(sprite.handle)
+            .expect("Mesh is missing texture")
+            .dimensions;
+        let source = sprite.texture_rect.unwrap_or_else(|| Rect::new(image_size));
+        let size = sprite.custom_size.unwrap_or(source.size());
+        let border = nine_slice.border;
+
+        let columns = target_spans(size.x, border.left, border.right);
+        let rows = target_spans(size.y, border.top, border.bottom);
+        let source_columns = [
+            (source.min.x, source.min.x + border.left),
+            (source.min.x + border.left, source.max.x - border.right),
+            (source.max.x - border.right, source.max.x),
+        ];
+        let source_rows = [
+            (source.min.y, source.min.y + border.top),
+            (source.min.y + border.top, source.max.y - border.bottom),
+            (source.max.y - border.bottom, source.max.y),
+        ];
+
+        // Target positions are from the top left, y down like texture pixels
+        let offset = Vec2::new(-0.5, 0.5) - sprite.anchor.as_vec();
+        let sort_position = transform.position;
+        transform.position.z = 0.;
+
+        let mut vertices = VertexData::default();
+        let mut quad_count = 0;
+        for (row, (target_row, source_row)) in rows.into_iter().zip(source_rows).enumerate() {
+            for (column, (target_column, source_column)) in
+                columns.into_iter().zip(source_columns).enumerate()
+            {
+                if target_row.1 <= target_row.0 || target_column.1 <= target_column.0 {
+                    continue;
+                }
+
+                let (mode_x, mode_y) = match (column, row) {
+                    (1, 1) => (nine_slice.center, nine_slice.center),
+                    (1, _) => (nine_slice.edges, SliceScaleMode::Stretch),
+                    (_, 1) => (SliceScaleMode::Stretch, nine_slice.edges),
+                    _ => (SliceScaleMode::Stretch, SliceScaleMode::Stretch),
+                };
+
+                for [(y0, y1), (v0, v1)] in tile_span(target_row, source_row, mode_y) {
+                    for [(x0, x1), (u0, u1)] in tile_span(target_column, source_column, mode_x) {
+                        let corners = [(x0, y1), (x1, y1), (x1, y0), (x0, y0)];
+                        for (x, y) in corners {
+                            let position = Vec2::new(x, -y) + offset * size;
+                            vertices
+                                .positions
+                                .push(transform.transform_point(position.extend(0.)).into());
+                        }
+                        for uv in [(u0, v1), (u1, v1), (u1, v0), (u0, v0)] {
+                            vertices
+                                .uvs
+                                .push((Vec2::new(uv.0, uv.1) / image_size).into());
+                        }
+                        quad_count += 1;
+                    }
+                }
+            }
+        }
+        vertices.colors = vec![sprite.color; vertices.positions.len()];
+
+        let mut mesh = MeshBuilder::new()
+            .with_indices(Indices::quads(quad_count))
+            .with_vertices(vertices)
+            .with_material(sprite.material.unwrap_or(self.material_map.default))
+            .with_texture(sprite.handle)
+            .with_layer(sprite.layer)
+            .with_sort_position(sort_position)
+            .build();
+        mesh.params = sprite.params;
+
+        mesh
+    }
+
+    /// Draws the nine slice as one mesh, batched like sprites
+    pub fn draw_nine_slice(&mut self, nine_slice: &NineSlice, transform: Transform) {
+        let mesh = self.nine_slice_to_mesh(nine_slice, transform);
+        self.push(mesh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::{target_spans, tile_span, NineSlice, SliceBorder, SliceScaleMode};
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, sprite::Sprite,
+        texture::Image, transform::Transform,
+    };
+
+    #[test]
+    fn borders_shrink_to_fit() {
+        assert_eq!(target_spans(10., 2., 3.), [(0., 2.), (2., 7.), (7., 10.)]);
+        assert_eq!(target_spans(4., 4., 4.), [(0., 2.), (2., 2.), (2., 4.)]);
+    }
+
+    #[test]
+    fn tiles_cut_off_last_repeat() {
+        assert_eq!(
+            tile_span((0., 5.), (8., 10.), SliceScaleMode::Tile),
+            vec![
+                [(0., 2.), (8., 10.)],
+                [(2., 4.), (8., 10.)],
+                [(4., 5.), (8., 9.)]
+            ]
+        );
+        assert_eq!(
+            tile_span((0., 5.), (8., 10.), SliceScaleMode::Stretch),
+            vec![[(0., 5.), (8., 10.)]]
+        );
+    }
+
+    #[test]
+    fn nine_slice_keeps_corner_size() {
+        let mut renderer = test_renderer((8, 8));
+        // Red corners, blue edges and a white center, one pixel each
+        let (red, blue, white) = ([255, 0, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]);
+        let texture = renderer.add_texture(Image {
+            data: [red, blue, red, blue, white, blue, red, blue, red].concat(),
+            dimensions: (3, 3),
+            ..Default::default()
+        });
+        let nine_slice =
+            NineSlice::new(Sprite::new(texture), SliceBorder::all(1.)).with_size(Vec2::splat(8.));
+
+        // Tiling repeats each one pixel edge and center part once per pixel
+        let tiled = nine_slice
+            .with_edges(SliceScaleMode::Tile)
+            .with_center(SliceScaleMode::Tile);
+        let mesh = renderer.nine_slice_to_mesh(&tiled, Transform::IDENTITY);
+        assert_eq!(mesh.vertices.positions.len(), 64 * 4);
+
+        let mut ctx = renderer.begin();
+        renderer.draw_nine_slice(&nine_slice, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::BLACK), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let on_edge = |position: usize| position == 0 || position == 7;
+            let expected = match (on_edge(index % 8), on_edge(index / 8)) {
+                (true, true) => red,
+                (false, false) => white,
+                _ => blue,
+            };
+            assert_eq!(pixel, expected, "pixel {index}");
+        }
+    }
+}
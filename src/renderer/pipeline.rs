@@ -1,18 +1,39 @@
 use std::fmt::Debug;
 
 use wgpu::{
-    BindGroupLayout, BindingType, BlendState, FragmentState, FrontFace, PolygonMode,
-    PrimitiveState, RenderPipeline, RenderPipelineDescriptor, ShaderStages, TextureFormat,
-    VertexBufferLayout, VertexState, VertexStepMode,
+    BindGroupLayout, FragmentState, FrontFace, PolygonMode, PrimitiveState, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, VertexBufferLayout,
+    VertexState, VertexStepMode,
 };
 
-use crate::{bind_groups::BindGroupLayoutBuilder, material::Material, mesh::get_attribute_layout};
+use crate::{
+    arena::ArenaId, batching::DrawCall, instancing::SpriteInstance, material::Material,
+    material_params::MaterialParams, mesh::get_attribute_layout, render_state::DEPTH_FORMAT,
+};
 
-use super::Renderer;
+use super::{errors::RenderError, Renderer};
 
 pub struct Pipeline {
     pub(crate) render_pipeline: RenderPipeline,
     pub(crate) material: Box<dyn Material>,
+    /// Layout of the [`MaterialParams`] group, for materials pushed with params
+    pub(crate) params_layout: Option<BindGroupLayout>,
+    /// Params used by meshes that didn't pick their own
+    pub(crate) default_params: Option<ArenaId<MaterialParams>>,
+    /// WGSL set with [`Renderer::reload_material_shader`], used instead of [`Material::shader`]
+    pub(crate) shader_source: Option<String>,
+    /// Variant for passes with a depth-stencil attachment, when the material doesn't use one itself
+    pub(crate) depth_pass_pipeline: Option<RenderPipeline>,
+}
+
+impl Pipeline {
+    /// The render pipeline matching whether the pass has a depth-stencil attachment
+    pub(crate) fn render_pipeline(&self, depth_stencil: bool) -> &RenderPipeline {
+        match &self.depth_pass_pipeline {
+            Some(depth_pass_pipeline) if depth_stencil => depth_pass_pipeline,
+            _ => &self.render_pipeline,
+        }
+    }
 }
 
 impl Debug for Pipeline {
@@ -25,53 +46,120 @@ impl Debug for Pipeline {
 }
 
 impl Renderer {
+    /// `params_layout` is bound right after the texture, before the material's own groups
+    /// `depth_pass` builds a material that doesn't use the depth-stencil for a pass that has it
     pub(crate) fn create_pipeline_from_material(
-        &mut self,
-        material: &impl Material,
+        &self,
+        material: &(impl Material + ?Sized),
+        params_layout: Option<&BindGroupLayout>,
+        depth_pass: bool,
     ) -> RenderPipeline {
-        let shader = self.device.create_shader_module(material.shader());
+        let shader = self
+            .preprocess_shader(material, material.shader())
+            .unwrap_or_else(|error| panic!("{}", error.message));
+
+        self.create_pipeline_with_shader(material, params_layout, shader, depth_pass)
+    }
+
+    /// Compiles `source` in place of the material's shader
+    /// Shader and pipeline validation errors are returned instead of panicking
+    pub(crate) fn create_pipeline_from_source(
+        &self,
+        material: &(impl Material + ?Sized),
+        params_layout: Option<&BindGroupLayout>,
+        source: &str,
+        depth_pass: bool,
+    ) -> Result<RenderPipeline, RenderError> {
+        let shader = self.preprocess_shader(
+            material,
+            ShaderModuleDescriptor {
+                label: Some(material.label()),
+                source: ShaderSource::Wgsl(source.into()),
+            },
+        )?;
+
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let render_pipeline =
+            self.create_pipeline_with_shader(material, params_layout, shader, depth_pass);
+
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => Err(RenderError::new(format!(
+                "Shader of {} failed to compile: {error}",
+                material.label()
+            ))),
+            None => Ok(render_pipeline),
+        }
+    }
+
+    /// Recreates the render pipeline of a material, from its shader source if it has one
+    pub(crate) fn rebuild_pipeline(&self, pipeline: &Pipeline, depth_pass: bool) -> RenderPipeline {
+        let material = pipeline.material.as_ref();
+        let params_layout = pipeline.params_layout.as_ref();
+
+        pipeline
+            .shader_source
+            .as_ref()
+            .and_then(|source| {
+                self.create_pipeline_from_source(material, params_layout, source, depth_pass)
+                    .ok()
+            })
+            .unwrap_or_else(|| {
+                self.create_pipeline_from_material(material, params_layout, depth_pass)
+            })
+    }
+
+    /// Builds the depth pass variant of the materials drawn without depth-stencil,
+    /// for a pass that has the attachment because another material needs it
+    pub(crate) fn cache_depth_pass_pipelines(&mut self, draw_calls: &[DrawCall]) {
+        for draw_call in draw_calls {
+            let pipeline = self.materials.get(draw_call.material_handle).unwrap();
+            if pipeline.material.use_depth_stencil() || pipeline.depth_pass_pipeline.is_some() {
+                continue;
+            }
+
+            let depth_pass_pipeline = self.rebuild_pipeline(pipeline, true);
+            self.materials
+                .get_mut(draw_call.material_handle)
+                .unwrap()
+                .depth_pass_pipeline = Some(depth_pass_pipeline);
+        }
+    }
+
+    fn create_pipeline_with_shader(
+        &self,
+        material: &(impl Material + ?Sized),
+        params_layout: Option<&BindGroupLayout>,
+        shader: ShaderModuleDescriptor<'_>,
+        depth_pass: bool,
+    ) -> RenderPipeline {
+        let shader = self.device.create_shader_module(shader);
 
         let vertex_attributes = material.vertex_attributes();
 
         let (vertex_attribute, offset) = get_attribute_layout(vertex_attributes.iter());
 
-        let vertex_buffer_layout = VertexBufferLayout {
-            array_stride: offset as u64,
-            step_mode: VertexStepMode::Vertex,
-            attributes: vertex_attribute.as_slice(),
+        let vertex_buffer_layout = if material.instanced() {
+            SpriteInstance::layout()
+        } else {
+            VertexBufferLayout {
+                array_stride: offset as u64,
+                step_mode: VertexStepMode::Vertex,
+                attributes: vertex_attribute.as_slice(),
+            }
         };
 
         let bind_group_layouts: Vec<BindGroupLayout> =
             material.get_bind_group_layouts(&self.device);
 
-        let texture_bind_group_layout = BindGroupLayoutBuilder::new()
-            .append(
-                ShaderStages::FRAGMENT,
-                BindingType::Texture {
-                    multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    sample_type: wgpu::TextureSampleType::Float {
-                        filterable: material.filterable_texture(),
-                    },
-                },
-                None,
-            )
-            .append(
-                ShaderStages::FRAGMENT,
-                BindingType::Sampler(if material.filterable_texture() {
-                    wgpu::SamplerBindingType::Filtering
-                } else {
-                    wgpu::SamplerBindingType::NonFiltering
-                }),
-                None,
-            )
-            .build(&self.device, None);
-
         let mut predefined_bind_group_layouts = if material.has_texture() {
-            vec![&self.camera_bind_group_layout, &texture_bind_group_layout]
+            vec![
+                &self.camera_bind_group_layout,
+                self.texture_bind_group_layout(material.filterable_texture()),
+            ]
         } else {
             vec![&self.camera_bind_group_layout]
         };
+        predefined_bind_group_layouts.extend(params_layout);
         predefined_bind_group_layouts.append(&mut bind_group_layouts.iter().map(|x| &*x).collect());
 
         let render_pipeline_layout =
@@ -84,7 +172,7 @@ impl Renderer {
 
         let binding = [Some(wgpu::ColorTargetState {
             format: self.surface_config.format,
-            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            blend: Some(material.blend_mode().blend_state()),
             write_mask: wgpu::ColorWrites::ALL,
         })];
 
@@ -102,7 +190,7 @@ impl Renderer {
             layout: Some(&render_pipeline_layout),
             primitive: PrimitiveState {
                 front_face: FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode: material.cull_mode(),
                 unclipped_depth: false,
                 polygon_mode: PolygonMode::Fill,
                 conservative: false,
@@ -110,11 +198,20 @@ impl Renderer {
                 strip_index_format: None,
             },
             depth_stencil: if material.use_depth_stencil() {
-                dbg!("FIRES");
+                let depth_state = material.depth_state();
+                Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: depth_state.write,
+                    depth_compare: depth_state.compare,
+                    stencil: material.stencil_state(),
+                    bias: depth_state.bias,
+                })
+            } else if depth_pass {
+                // Draws as if there was no attachment, leaving the depth and stencil untouched
                 Some(wgpu::DepthStencilState {
-                    format: TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 })
@@ -122,7 +219,7 @@ impl Renderer {
                 None
             },
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: self.msaa_samples,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
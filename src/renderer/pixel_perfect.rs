@@ -0,0 +1,339 @@
+use glam::{UVec2, Vec2};
+use wgpu::{
+    include_wgsl, BindGroup, BindingResource, BindingType, Buffer, RenderPipeline, ShaderStages,
+};
+
+use super::{
+    bind_groups::{BindGroupBuilder, BindGroupLayoutBuilder},
+    render_stats::PassStats,
+    render_target::RenderTarget,
+    texture::TextureSamplerType,
+    RenderContext, Renderer,
+};
+
+/// How the low resolution frame is scaled up to the window
+/// Any space left over is filled with black bars (letterboxing or pillarboxing)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalingMode {
+    /// Only scale by whole numbers, every game pixel is the same size on screen
+    #[default]
+    Integer,
+    /// Scale as large as possible while keeping the aspect ratio
+    Fit,
+    /// Fill the whole window, ignoring the aspect ratio
+    Stretch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpscaleFilter {
+    #[default]
+    Nearest,
+    /// Nearest neighbour with a one pixel blend on the edges, avoids uneven pixels with non integer scales
+    SharpBilinear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PixelPerfectSettings {
+    pub scaling_mode: ScalingMode,
+    pub upscale_filter: UpscaleFilter,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniform {
+    source_size: [f32; 2],
+    scale: [f32; 2],
+    filter_mode: u32,
+    _padding: u32,
+}
+
+pub(crate) struct PixelPerfect {
+    pub(crate) settings: PixelPerfectSettings,
+    pub(crate) target: RenderTarget,
+    pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    uniform_buffer: Buffer,
+}
+
+/// Returns the offset and size, in window pixels from the top left, the low resolution frame is drawn at
+pub fn compute_upscale_viewport(
+    resolution: UVec2,
+    window_size: UVec2,
+    scaling_mode: ScalingMode,
+) -> (Vec2, Vec2) {
+    let resolution = resolution.as_vec2();
+    let window_size = window_size.as_vec2();
+    let fit_scale = (window_size / resolution).min_element();
+
+    let size = match scaling_mode {
+        ScalingMode::Integer => resolution * fit_scale.floor().max(1.),
+        ScalingMode::Fit => resolution * fit_scale,
+        ScalingMode::Stretch => window_size,
+    };
+
+    let offset = ((window_size - size) / 2.).floor();
+
+    (offset, size)
+}
+
+impl Renderer {
+    /// Renders everything into a `resolution` sized texture that is then scaled up to the window
+    /// Stops sprites shimmering when they move by less than a screen pixel
+    pub fn set_pixel_perfect(&mut self, resolution: UVec2, settings: PixelPerfectSettings) {
+        if let Some(pixel_perfect) = self.pixel_perfect.take() {
+            self.remove_render_target(pixel_perfect.target);
+        }
+
+        let target = self.create_render_target(resolution, TextureSamplerType::Nearest);
+
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                None,
+            )
+            .build(&self.device, Some("upscale_bind_group_layout"));
+
+        let uniform_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Upscale Buffer"),
+            size: std::mem::size_of::<UpscaleUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture = self.textures.get(target.texture).unwrap();
+        let sampler = self
+            .samplers
+            .get(
+                *self
+                    .default_texture_samplers
+                    .get(&TextureSamplerType::Linear)
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let bind_group = BindGroupBuilder::new()
+            .append_texture_view(&texture.view)
+            .append(BindingResource::Sampler(sampler))
+            .append_buffer(&uniform_buffer)
+            .build(&self.device, Some("Upscale bind group"), &bind_group_layout);
+
+        let shader = self
+            .device
+            .create_shader_module(include_wgsl!("./default_shaders/upscale.wgsl"));
+
+        let pipeline_layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Upscale Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Upscale Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vertex",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.surface_config.format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        self.pixel_perfect = Some(PixelPerfect {
+            settings,
+            target,
+            pipeline,
+            bind_group,
+            uniform_buffer,
+        });
+    }
+
+    /// Goes back to rendering straight to the window
+    pub fn disable_pixel_perfect(&mut self) {
+        if let Some(pixel_perfect) = self.pixel_perfect.take() {
+            self.remove_render_target(pixel_perfect.target);
+        }
+    }
+
+    /// The size everything is rendered at, the low resolution when pixel perfect otherwise the window size
+    pub fn get_render_size(&self) -> (u32, u32) {
+        match &self.pixel_perfect {
+            Some(pixel_perfect) => (pixel_perfect.target.size.x, pixel_perfect.target.size.y),
+            None => self.get_viewport_size(),
+        }
+    }
+
+    /// Maps a position in the window (bottom left origin, like [`crate::input::InputManager::mouse_position`])
+    /// to the same position in the rendered frame, undoing any pixel perfect scaling
+    pub fn viewport_to_render_position(&self, viewport_position: Vec2) -> Vec2 {
+        let Some(pixel_perfect) = &self.pixel_perfect else {
+            return viewport_position;
+        };
+
+        let window_size = UVec2::from(self.get_viewport_size());
+        let (offset, size) = compute_upscale_viewport(
+            pixel_perfect.target.size,
+            window_size,
+            pixel_perfect.settings.scaling_mode,
+        );
+        // The viewport offset is from the top left, flip it to match the position
+        let offset = Vec2::new(offset.x, window_size.y as f32 - offset.y - size.y);
+
+        (viewport_position - offset) / size * pixel_perfect.target.size.as_vec2()
+    }
+
+    /// Draws the low resolution frame onto the window view
+    pub(crate) fn upscale_pixel_perfect(&mut self, render_context: &mut RenderContext) {
+        let Some(pixel_perfect) = &self.pixel_perfect else {
+            return;
+        };
+
+        let (offset, size) = compute_upscale_viewport(
+            pixel_perfect.target.size,
+            UVec2::from(self.get_viewport_size()),
+            pixel_perfect.settings.scaling_mode,
+        );
+
+        // Minimised windows have nothing to draw into
+        if size.min_element() <= 0. {
+            return;
+        }
+
+        let uniform = UpscaleUniform {
+            source_size: pixel_perfect.target.size.as_vec2().into(),
+            scale: (size / pixel_perfect.target.size.as_vec2()).into(),
+            filter_mode: match pixel_perfect.settings.upscale_filter {
+                UpscaleFilter::Nearest => 0,
+                UpscaleFilter::SharpBilinear => 1,
+            },
+            _padding: 0,
+        };
+        self.queue.write_buffer(
+            &pixel_perfect.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
+
+        let timed_pass =
+            self.begin_timed_pass(&mut render_context.command_encoder, "Pixel perfect upscale");
+        {
+            let pixel_perfect = self.pixel_perfect.as_ref().unwrap();
+            let mut render_pass =
+                render_context
+                    .command_encoder
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Upscale Pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &render_context.view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                // Clears the bars around the frame
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+
+            render_pass.set_viewport(offset.x, offset.y, size.x, size.y, 0., 1.);
+            render_pass.set_pipeline(&pixel_perfect.pipeline);
+            render_pass.set_bind_group(0, &pixel_perfect.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        self.end_timed_pass(&mut render_context.command_encoder, timed_pass);
+        self.frame_stats.add_pass(&PassStats::single_draw());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, Vec2};
+
+    use super::{compute_upscale_viewport, ScalingMode};
+
+    #[test]
+    fn integer_scaling_letterboxes() {
+        let (offset, size) = compute_upscale_viewport(
+            UVec2::new(320, 180),
+            UVec2::new(1280, 800),
+            ScalingMode::Integer,
+        );
+        assert_eq!(size, Vec2::new(1280., 720.));
+        assert_eq!(offset, Vec2::new(0., 40.));
+    }
+
+    #[test]
+    fn integer_scaling_never_scales_fractionally() {
+        let (offset, size) = compute_upscale_viewport(
+            UVec2::new(320, 180),
+            UVec2::new(1000, 600),
+            ScalingMode::Integer,
+        );
+        assert_eq!(size, Vec2::new(960., 540.));
+        assert_eq!(offset, Vec2::new(20., 30.));
+
+        // Windows smaller than the resolution still draw at 1x
+        let (_, size) = compute_upscale_viewport(
+            UVec2::new(320, 180),
+            UVec2::new(200, 100),
+            ScalingMode::Integer,
+        );
+        assert_eq!(size, Vec2::new(320., 180.));
+    }
+
+    #[test]
+    fn fit_scaling_pillarboxes() {
+        let (offset, size) = compute_upscale_viewport(
+            UVec2::new(320, 180),
+            UVec2::new(1000, 360),
+            ScalingMode::Fit,
+        );
+        assert_eq!(size, Vec2::new(640., 360.));
+        assert_eq!(offset, Vec2::new(180., 0.));
+    }
+
+    #[test]
+    fn stretch_fills_window() {
+        let (offset, size) = compute_upscale_viewport(
+            UVec2::new(320, 180),
+            UVec2::new(1000, 360),
+            ScalingMode::Stretch,
+        );
+        assert_eq!(size, Vec2::new(1000., 360.));
+        assert_eq!(offset, Vec2::ZERO);
+    }
+}
@@ -0,0 +1,496 @@
+use glam::{Mat4, Quat, UVec2, Vec3};
+use wgpu::{include_wgsl, ShaderModuleDescriptor, TextureFormat};
+
+use crate::arena::ArenaId;
+
+use super::{
+    camera::{Camera, Projection},
+    material::Material,
+    mesh::{Indices, Mesh, MeshBuilder, VertexData, QUAD_INDICES, QUAD_UVS, QUAD_VERTEX_POSITIONS},
+    pipeline::Pipeline,
+    texture::{Texture, TextureSamplerType},
+    RenderContext, Renderer,
+};
+
+/// Width of a side of the color grading cube, the LUT texture is `LUT_SIZE * LUT_SIZE` by `LUT_SIZE`
+pub const LUT_SIZE: u32 = 16;
+
+/// The effects every renderer starts with, all disabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostEffect {
+    Bloom,
+    Vignette,
+    /// Screen curvature and scanlines
+    Crt,
+    ChromaticAberration,
+    /// Color grading with a lookup texture, see [`Renderer::set_color_grading_lut`]
+    ColorGrading,
+}
+
+impl PostEffect {
+    pub const ALL: [PostEffect; 5] = [
+        PostEffect::Bloom,
+        PostEffect::Vignette,
+        PostEffect::Crt,
+        PostEffect::ChromaticAberration,
+        PostEffect::ColorGrading,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostEffect::Bloom => "Bloom",
+            PostEffect::Vignette => "Vignette",
+            PostEffect::Crt => "CRT",
+            PostEffect::ChromaticAberration => "Chromatic aberration",
+            PostEffect::ColorGrading => "Color grading",
+        }
+    }
+}
+
+/// A fullscreen pass run after the scene, its material samples the output of the previous pass
+/// from the texture binding like a sprite
+#[derive(Debug, Clone)]
+pub struct PostProcessPass {
+    pub name: String,
+    pub material: ArenaId<Pipeline>,
+    pub enabled: bool,
+}
+
+/// The chain of passes and the two textures they render back and forth between
+#[derive(Default)]
+pub(crate) struct PostProcessing {
+    pub(crate) passes: Vec<PostProcessPass>,
+    targets: Option<[ArenaId<Texture>; 2]>,
+    /// The material of [`PostEffect::ColorGrading`], its LUT is swapped in place
+    color_grading: ArenaId<Pipeline>,
+}
+
+#[derive(Debug)]
+pub struct BloomMaterial;
+
+impl Material for BloomMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/post_bloom.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        "Bloom Material"
+    }
+}
+
+#[derive(Debug)]
+pub struct VignetteMaterial;
+
+impl Material for VignetteMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/post_vignette.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        "Vignette Material"
+    }
+}
+
+#[derive(Debug)]
+pub struct CrtMaterial;
+
+impl Material for CrtMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/post_crt.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        "CRT Material"
+    }
+}
+
+#[derive(Debug)]
+pub struct ChromaticAberrationMaterial;
+
+impl Material for ChromaticAberrationMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/post_chromatic_aberration.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        "Chromatic Aberration Material"
+    }
+}
+
+/// Grades the colors with a `LUT_SIZE` cube stored as slices side by side, blue picks the slice
+/// The LUT should be an sRGB image loaded as [`TextureFormat::Rgba8Unorm`] so it's sampled as authored
+/// It's the only texture of the material's params, so its bind group is cached with them
+#[derive(Debug)]
+pub struct LutMaterial;
+
+impl Material for LutMaterial {
+    fn shader(&self) -> ShaderModuleDescriptor<'_> {
+        include_wgsl!("./default_shaders/post_lut.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        "LUT Material"
+    }
+}
+
+/// A LUT that leaves every color as it is
+pub fn identity_lut() -> Vec<u8> {
+    let mut bytes = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+    let step = |value: u32| (value * 255 / (LUT_SIZE - 1)) as u8;
+
+    for green in 0..LUT_SIZE {
+        for blue in 0..LUT_SIZE {
+            for red in 0..LUT_SIZE {
+                bytes.extend_from_slice(&[step(red), step(green), step(blue), 255]);
+            }
+        }
+    }
+
+    bytes
+}
+
+impl Renderer {
+    /// Appends an enabled pass to the end of the post processing chain
+    pub fn add_post_process_pass(&mut self, name: &str, material: ArenaId<Pipeline>) {
+        self.post_processing.passes.push(PostProcessPass {
+            name: name.to_string(),
+            material,
+            enabled: true,
+        });
+    }
+
+    /// The passes in the order they run, including the disabled ones
+    pub fn post_process_passes(&self) -> &[PostProcessPass] {
+        &self.post_processing.passes
+    }
+
+    /// Lets passes be toggled, reordered or removed
+    pub fn post_process_passes_mut(&mut self) -> &mut Vec<PostProcessPass> {
+        &mut self.post_processing.passes
+    }
+
+    /// Toggles every pass with the given name
+    pub fn set_post_process_enabled(&mut self, name: &str, enabled: bool) {
+        self.post_processing
+            .passes
+            .iter_mut()
+            .filter(|pass| pass.name == name)
+            .for_each(|pass| pass.enabled = enabled);
+    }
+
+    pub fn set_post_effect_enabled(&mut self, effect: PostEffect, enabled: bool) {
+        self.set_post_process_enabled(effect.name(), enabled);
+    }
+
+    /// Replaces the lookup texture used by [`PostEffect::ColorGrading`]
+    pub fn set_color_grading_lut(&mut self, lut: ArenaId<Texture>) {
+        // The pipeline is kept, the params bind group is rebuilt the next time the pass draws
+        if let Some(params) = self.material_params::<()>(self.post_processing.color_grading) {
+            self.set_params_texture(params, 0, lut);
+        }
+    }
+
+    pub(crate) fn add_builtin_post_effects(&mut self) {
+        let lut = self.add_texture_from_bytes(
+            &identity_lut(),
+            (LUT_SIZE * LUT_SIZE, LUT_SIZE),
+            TextureSamplerType::Linear,
+            TextureFormat::Rgba8Unorm,
+        );
+
+        for effect in PostEffect::ALL {
+            let material = match effect {
+                PostEffect::Bloom => self.push_material(BloomMaterial),
+                PostEffect::Vignette => self.push_material(VignetteMaterial),
+                PostEffect::Crt => self.push_material(CrtMaterial),
+                PostEffect::ChromaticAberration => self.push_material(ChromaticAberrationMaterial),
+                PostEffect::ColorGrading => {
+                    self.post_processing.color_grading =
+                        self.push_material_with_params(LutMaterial, (), vec![lut]);
+                    self.post_processing.color_grading
+                }
+            };
+
+            self.post_processing.passes.push(PostProcessPass {
+                name: effect.name().to_string(),
+                material,
+                enabled: false,
+            });
+        }
+    }
+
+    /// Sends the scene into the first post processing target when any pass is enabled
+    /// Returns the view to render the scene into, and the real output view if it was swapped out
+    pub(crate) fn begin_post_processing(
+        &mut self,
+        view: wgpu::TextureView,
+    ) -> (wgpu::TextureView, Option<wgpu::TextureView>) {
+        if !self.post_processing.passes.iter().any(|pass| pass.enabled) {
+            return (view, None);
+        }
+
+        let [scene_target, _] = self.post_process_targets();
+        let scene_view = self
+            .textures
+            .get(scene_target)
+            .unwrap()
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        (scene_view, Some(view))
+    }
+
+    /// Runs the enabled passes over the scene, the last one drawing into the frame
+    /// Should be called after rendering the scene and before anything that shouldn't be
+    /// post processed, like egui
+    pub fn apply_post_processing(&mut self, render_context: &mut RenderContext) {
+        let Some(output_view) = render_context.post_process_output.take() else {
+            return;
+        };
+
+        let materials: Vec<ArenaId<Pipeline>> = self
+            .post_processing
+            .passes
+            .iter()
+            .filter(|pass| pass.enabled)
+            .map(|pass| pass.material)
+            .collect();
+        // Every pass was disabled after the scene was sent to the target, it still has to reach the frame
+        let materials = if materials.is_empty() {
+            vec![self.material_map.default]
+        } else {
+            materials
+        };
+        let targets = self.post_process_targets();
+
+        for (index, material) in materials.iter().enumerate() {
+            let source = targets[index % 2];
+
+            if index + 1 == materials.len() {
+                self.render_fullscreen_pass(
+                    render_context,
+                    source,
+                    &output_view,
+                    *material,
+                    "Post processing",
+                );
+            } else {
+                let view = self
+                    .textures
+                    .get(targets[(index + 1) % 2])
+                    .unwrap()
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                self.render_fullscreen_pass(
+                    render_context,
+                    source,
+                    &view,
+                    *material,
+                    "Post processing",
+                );
+            }
+        }
+
+        render_context.view = output_view;
+    }
+
+    /// Called on resize, the targets are recreated at the new size when they're next needed
+    pub(crate) fn drop_post_process_targets(&mut self) {
+        if let Some(targets) = self.post_processing.targets.take() {
+            for texture in targets {
+                self.invalidate_texture_bind_groups(texture);
+                self.msaa_targets.remove(&texture);
+                self.textures.remove(texture);
+            }
+        }
+    }
+
+    fn post_process_targets(&mut self) -> [ArenaId<Texture>; 2] {
+        if let Some(targets) = self.post_processing.targets {
+            return targets;
+        }
+
+        let sampler = *self
+            .default_texture_samplers
+            .get(&TextureSamplerType::Linear)
+            .unwrap();
+        let targets = [(); 2].map(|_| {
+            let texture = Texture::create_render_target(
+                &self.device,
+                self.get_viewport_size(),
+                self.surface_config.format,
+                sampler,
+            );
+            self.textures.insert(texture)
+        });
+        self.post_processing.targets = Some(targets);
+
+        targets
+    }
+
+    /// Draws `source` through the material as a quad covering the whole view
+    pub(crate) fn render_fullscreen_pass(
+        &mut self,
+        render_context: &mut RenderContext,
+        source: ArenaId<Texture>,
+        view: &wgpu::TextureView,
+        material: ArenaId<Pipeline>,
+        timer_label: &'static str,
+    ) {
+        // The quad is already in clip space
+        let camera = Camera {
+            projection: Projection::Custom(Mat4::IDENTITY),
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+        };
+
+        let queued_meshes =
+            std::mem::replace(&mut self.meshes, vec![fullscreen_quad(source, material)]);
+        let draw_calls = self.prepare_mesh_batch(camera.position);
+        self.meshes = queued_meshes;
+
+        let camera_offset = self.write_camera_uniform(&camera, (1, 1));
+        // Material pipelines are multisampled, the pass clears so the source can key it
+        let msaa_views = self.msaa_views(source, UVec2::from(self.get_viewport_size()));
+
+        let timed_pass = self.begin_timed_pass(&mut render_context.command_encoder, timer_label);
+        let pass_stats = {
+            let mut render_pass =
+                render_context
+                    .command_encoder
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Post Process Pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: msaa_views.as_ref().map_or(view, |(color, _)| color),
+                            resolve_target: msaa_views.as_ref().map(|_| view),
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[camera_offset]);
+
+            super::render_queued_draw_calls(
+                &draw_calls,
+                &mut render_pass,
+                false,
+                &self.materials,
+                &self.params,
+                &self.texture_bind_groups,
+                self.vertex_buffer.buffer(),
+                self.index_buffer.buffer(),
+            )
+        };
+        self.end_timed_pass(&mut render_context.command_encoder, timed_pass);
+        self.frame_stats.add_pass(&pass_stats);
+    }
+}
+
+fn fullscreen_quad(source: ArenaId<Texture>, material: ArenaId<Pipeline>) -> Mesh {
+    let vertices = VertexData {
+        positions: QUAD_VERTEX_POSITIONS
+            .map(|position| (position * 2.).extend(0.).into())
+            .to_vec(),
+        uvs: QUAD_UVS.map(Into::into).to_vec(),
+        colors: vec![[1.; 4]; 4],
+        normals: Vec::new(),
+    };
+
+    MeshBuilder::new()
+        .with_vertices(vertices)
+        .with_indices(Indices::U16(QUAD_INDICES.to_vec()))
+        .with_texture(source)
+        .with_material(material)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::TextureFormat;
+
+    use super::{PostEffect, LUT_SIZE};
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer,
+        texture::TextureSamplerType, Renderer,
+    };
+
+    #[test]
+    fn post_processing_chain_reads_back() {
+        let mut renderer = test_renderer((16, 16));
+        renderer.set_post_effect_enabled(PostEffect::ColorGrading, true);
+        renderer.set_post_effect_enabled(PostEffect::Vignette, true);
+
+        let mut ctx = renderer.begin();
+        renderer.render(&mut ctx, Some(Color::WHITE), &Camera::orthographic());
+        renderer.apply_post_processing(&mut ctx);
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        let pixel = |x: usize, y: usize| &image.data[(y * 16 + x) * 4..][..4];
+        // The identity LUT keeps the center white, the vignette darkens the corners
+        assert!(pixel(8, 8)[..3].iter().all(|channel| *channel >= 250));
+        assert!(pixel(0, 0)[0] < 200);
+    }
+
+    #[test]
+    fn color_grading_lut_is_replaced_in_place() {
+        let mut renderer = test_renderer((4, 4));
+        renderer.set_post_effect_enabled(PostEffect::ColorGrading, true);
+        let material = |renderer: &Renderer| {
+            renderer
+                .post_process_passes()
+                .iter()
+                .find(|pass| pass.name == PostEffect::ColorGrading.name())
+                .unwrap()
+                .material
+        };
+        let before = material(&renderer);
+        let lut_bind_group = |renderer: &Renderer| {
+            let params = renderer.materials[before].default_params.unwrap();
+            renderer.params[params].bind_group.is_some()
+        };
+        let draw_frame = |renderer: &mut Renderer| {
+            let mut ctx = renderer.begin();
+            renderer.render(&mut ctx, Some(Color::WHITE), &Camera::orthographic());
+            renderer.apply_post_processing(&mut ctx);
+            renderer.end_frame(ctx);
+        };
+
+        // The bind group is built by the first frame and kept for the next ones
+        draw_frame(&mut renderer);
+        assert!(lut_bind_group(&renderer));
+
+        let black = [0, 0, 0, 255].repeat((LUT_SIZE * LUT_SIZE * LUT_SIZE) as usize);
+        let lut = renderer.add_texture_from_bytes(
+            &black,
+            (LUT_SIZE * LUT_SIZE, LUT_SIZE),
+            TextureSamplerType::Linear,
+            TextureFormat::Rgba8Unorm,
+        );
+        renderer.set_color_grading_lut(lut);
+        assert_eq!(material(&renderer), before);
+        assert!(!lut_bind_group(&renderer));
+
+        draw_frame(&mut renderer);
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn disabling_passes_mid_frame_keeps_the_scene() {
+        let mut renderer = test_renderer((4, 4));
+        renderer.set_post_effect_enabled(PostEffect::Vignette, true);
+
+        let mut ctx = renderer.begin();
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.set_post_effect_enabled(PostEffect::Vignette, false);
+        renderer.apply_post_processing(&mut ctx);
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+    }
+}
@@ -1,12 +1,10 @@
-use std::{collections::BTreeMap, ops::Add};
+use std::ops::Add;
 
 use glam::Vec2;
 
 use crate::{
     components::color::Color,
-    mesh::{
-        AttributeValue, Mesh, MeshAttribute, Vertex, QUAD_INDICES, QUAD_UVS, QUAD_VERTEX_POSITIONS,
-    },
+    mesh::{Mesh, MeshBuilder, VertexData, QUAD_INDICES, QUAD_UVS, QUAD_VERTEX_POSITIONS},
     Anchor, ArenaId, Transform,
 };
 
@@ -54,6 +52,11 @@ impl Rect {
         self.max - self.min
     }
 
+    /// Whether the rects overlap, touching edges don't count
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.min.cmplt(other.max).all() && other.min.cmplt(self.max).all()
+    }
+
     pub fn new(size: Vec2) -> Self {
         Self {
             min: Vec2::ZERO,
@@ -70,30 +73,22 @@ impl Rect {
                 .into()
         });
 
-        let vertices = positions
-            .iter()
-            .zip(QUAD_UVS)
-            .map(|(position, uv)| {
-                Vertex(BTreeMap::from([
-                    (MeshAttribute::Position, AttributeValue::Position(*position)),
-                    (MeshAttribute::UV, AttributeValue::UV(uv.into())),
-                    (
-                        MeshAttribute::Color,
-                        AttributeValue::Color(color.as_rgba_f32()),
-                    ),
-                ]))
-            })
-            .collect();
+        let vertices = VertexData {
+            positions: positions.to_vec(),
+            uvs: QUAD_UVS.map(Into::into).to_vec(),
+            colors: vec![color.as_rgba_f32(); 4],
+            normals: Vec::new(),
+        };
 
         let material_handle = ArenaId::first();
 
-        Mesh::new(
-            Some(ArenaId::first()),
-            material_handle,
-            vertices,
-            crate::mesh::Indices::U16(QUAD_INDICES.to_vec()),
-            (transform.position).length(),
-        )
+        MeshBuilder::new()
+            .with_texture(ArenaId::first())
+            .with_material(material_handle)
+            .with_vertices(vertices)
+            .with_indices(crate::mesh::Indices::U16(QUAD_INDICES.to_vec()))
+            .with_sort_position(transform.position)
+            .build()
     }
 }
 
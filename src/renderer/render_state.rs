@@ -0,0 +1,221 @@
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, CompareFunction, DepthBiasState,
+    TextureFormat,
+};
+
+/// Format of every depth texture, with a stencil so materials can mask each other
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth24PlusStencil8;
+
+/// How a material's output is combined with what's already in the target
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BlendMode {
+    /// Regular transparency
+    #[default]
+    Alpha,
+    /// For colors that were already multiplied by their alpha
+    PremultipliedAlpha,
+    /// Adds onto the target, for glows and particles
+    Additive,
+    /// Darkens the target by the color, for shadows and tinting
+    Multiply,
+    /// Brightens the target, the inverse of multiply
+    Screen,
+    /// Overwrites the target, alpha included
+    Replace,
+    Custom(BlendState),
+}
+
+impl BlendMode {
+    pub fn blend_state(&self) -> BlendState {
+        match self {
+            BlendMode::Alpha => BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::Zero,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            },
+            BlendMode::Multiply => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::Dst,
+                    dst_factor: BlendFactor::Zero,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+            BlendMode::Screen => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::OneMinusDst,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+            BlendMode::Replace => BlendState::REPLACE,
+            BlendMode::Custom(blend_state) => *blend_state,
+        }
+    }
+}
+
+/// Depth test and write settings, only used by materials with [`crate::material::Material::use_depth_stencil`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthState {
+    /// [`CompareFunction::Always`] turns the depth test off
+    pub compare: CompareFunction,
+    pub write: bool,
+    pub bias: DepthBiasState,
+}
+
+impl DepthState {
+    /// Tested against the depth buffer without writing to it, for transparent meshes
+    pub fn read_only() -> Self {
+        Self {
+            write: false,
+            ..Default::default()
+        }
+    }
+
+    /// Always drawn, and never hides anything drawn after
+    pub fn disabled() -> Self {
+        Self {
+            compare: CompareFunction::Always,
+            write: false,
+            bias: DepthBiasState::default(),
+        }
+    }
+
+    pub fn with_bias(mut self, bias: DepthBiasState) -> Self {
+        self.bias = bias;
+        self
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            compare: CompareFunction::Less,
+            write: true,
+            bias: DepthBiasState::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::{BlendMode, DepthState};
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        material::Material, sprite::Sprite, transform::Transform,
+    };
+
+    #[derive(Debug)]
+    struct AdditiveMaterial;
+
+    impl Material for AdditiveMaterial {
+        fn blend_mode(&self) -> BlendMode {
+            BlendMode::Additive
+        }
+    }
+
+    #[derive(Debug)]
+    struct DepthTestedMaterial;
+
+    impl Material for DepthTestedMaterial {
+        fn use_depth_stencil(&self) -> bool {
+            true
+        }
+
+        fn depth_state(&self) -> DepthState {
+            DepthState::disabled()
+        }
+    }
+
+    #[test]
+    fn depth_material_draws_in_2d() {
+        let mut renderer = test_renderer((8, 8));
+        assert!(!renderer.mode_3d);
+        let material = renderer.push_material(DepthTestedMaterial);
+
+        let mut ctx = renderer.begin();
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(8.)),
+            color: Color::GREEN.as_rgba_f32(),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn default_sprite_draws_next_to_depth_material() {
+        let mut renderer = test_renderer((8, 8));
+        let material = renderer.push_material(DepthTestedMaterial);
+
+        // The pass gets a depth-stencil attachment, which the default sprite pipeline has to accept
+        let mut ctx = renderer.begin();
+        let half = |x: f32, color: Color, material| {
+            (
+                Sprite {
+                    custom_size: Some(Vec2::new(4., 8.)),
+                    color: color.as_rgba_f32(),
+                    material,
+                    ..Sprite::new(ArenaId::first())
+                },
+                Transform::from_xyz(x, 0., 0.),
+            )
+        };
+        let (sprite, transform) = half(-2., Color::BLUE, None);
+        renderer.draw_sprite(&sprite, transform);
+        let (sprite, transform) = half(2., Color::GREEN, Some(material));
+        renderer.draw_sprite(&sprite, transform);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [0, 0, 255, 255]
+            } else {
+                [0, 255, 0, 255]
+            };
+            assert_eq!(pixel, expected, "pixel {index}");
+        }
+    }
+
+    #[test]
+    fn additive_material_blends() {
+        let mut renderer = test_renderer((8, 8));
+        let material = renderer.push_material(AdditiveMaterial);
+
+        let mut ctx = renderer.begin();
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(8.)),
+            color: Color::GREEN.as_rgba_f32(),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image
+            .data
+            .chunks(4)
+            .all(|pixel| pixel == [255, 255, 0, 255]));
+    }
+}
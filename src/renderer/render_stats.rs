@@ -0,0 +1,391 @@
+use std::{
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "egui")]
+use egui_inspect::EguiInspect;
+use wgpu::{CommandEncoder, Device, Queue};
+
+use super::Renderer;
+
+/// Passes timed per frame, later passes aren't timed
+const MAX_TIMED_PASSES: u32 = 32;
+
+const READ_PENDING: u8 = 0;
+const READ_DONE: u8 = 1;
+const READ_FAILED: u8 = 2;
+
+/// GPU time a pass took
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub label: &'static str,
+    pub duration: Duration,
+}
+
+/// What the renderer did in the last finished frame, see [`Renderer::render_stats`]
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    /// Meshes queued for drawing, before batching
+    pub meshes: u32,
+    /// Draw calls after batching
+    pub draw_calls: u32,
+    pub vertices: u32,
+    pub indices: u32,
+    pub instances: u32,
+    /// Bytes written to the vertex and index buffers
+    pub uploaded_bytes: u64,
+    pub pipeline_switches: u32,
+    /// Bind groups set because the group in their slot changed
+    pub bind_group_switches: u32,
+    /// Textures alive at the end of the frame
+    pub textures: u32,
+    /// Estimated GPU memory of those textures, including their mips, in bytes
+    pub texture_memory: u64,
+    /// Empty unless the adapter supports timestamp queries, see [`Renderer::gpu_timing_supported`]
+    /// Read back without stalling, so they trail the other stats by a frame or two
+    pub pass_timings: Vec<PassTiming>,
+}
+
+impl RenderStats {
+    pub(crate) fn add_pass(&mut self, pass: &PassStats) {
+        self.draw_calls += pass.draw_calls;
+        self.pipeline_switches += pass.pipeline_switches;
+        self.bind_group_switches += pass.bind_group_switches;
+    }
+}
+
+#[cfg(feature = "egui")]
+impl EguiInspect for RenderStats {
+    fn inspect(&self, _label: &str, ui: &mut egui::Ui) {
+        egui::Grid::new("render_stats")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                let rows = [
+                    ("Meshes", self.meshes.to_string()),
+                    ("Draw calls", self.draw_calls.to_string()),
+                    ("Vertices", self.vertices.to_string()),
+                    ("Indices", self.indices.to_string()),
+                    ("Instances", self.instances.to_string()),
+                    (
+                        "Uploaded",
+                        format!("{:.1} KiB", self.uploaded_bytes as f64 / 1024.),
+                    ),
+                    ("Pipeline switches", self.pipeline_switches.to_string()),
+                    ("Bind group switches", self.bind_group_switches.to_string()),
+                    ("Textures", self.textures.to_string()),
+                    (
+                        "Texture memory",
+                        format!("{:.1} MiB", self.texture_memory as f64 / (1024. * 1024.)),
+                    ),
+                ];
+                for (label, value) in rows {
+                    ui.label(label);
+                    ui.label(value);
+                    ui.end_row();
+                }
+                for timing in &self.pass_timings {
+                    ui.label(timing.label);
+                    ui.label(format!("{:.3} ms", timing.duration.as_secs_f64() * 1000.));
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        self.inspect(label, ui);
+    }
+}
+
+/// State changes made recording draw calls into a pass
+#[derive(Debug, Default)]
+pub(crate) struct PassStats {
+    pub(crate) draw_calls: u32,
+    pub(crate) pipeline_switches: u32,
+    pub(crate) bind_group_switches: u32,
+}
+
+impl PassStats {
+    /// One pipeline, bind group and draw, like the fullscreen passes
+    pub(crate) fn single_draw() -> Self {
+        Self {
+            draw_calls: 1,
+            pipeline_switches: 1,
+            bind_group_switches: 1,
+        }
+    }
+}
+
+/// Writes timestamps around passes and reads them back once the GPU is done with the frame
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    /// Passes timed in the frame being recorded
+    labels: Vec<&'static str>,
+    /// Passes of the frame being read back, nothing is timed until the read buffer is free
+    reading: Option<Vec<&'static str>>,
+    read_state: Arc<AtomicU8>,
+    last_timings: Vec<PassTiming>,
+}
+
+impl GpuTimer {
+    /// None when the device doesn't have timestamp queries
+    pub(crate) fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_count = MAX_TIMED_PASSES * 2;
+        let buffer_size = query_count as u64 * wgpu::QUERY_SIZE as u64;
+
+        Some(Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: query_count,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp resolve buffer"),
+                size: buffer_size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp read buffer"),
+                size: buffer_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            labels: Vec::new(),
+            reading: None,
+            read_state: Arc::new(AtomicU8::new(READ_PENDING)),
+            last_timings: Vec::new(),
+        })
+    }
+
+    /// Writes the start of a pass, the returned index ends it with [`GpuTimer::end_pass`]
+    pub(crate) fn begin_pass(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        label: &'static str,
+    ) -> Option<u32> {
+        if self.reading.is_some() || self.labels.len() as u32 >= MAX_TIMED_PASSES {
+            return None;
+        }
+
+        let index = self.labels.len() as u32;
+        self.labels.push(label);
+        encoder.write_timestamp(&self.query_set, index * 2);
+
+        Some(index)
+    }
+
+    pub(crate) fn end_pass(&self, encoder: &mut CommandEncoder, pass: Option<u32>) {
+        if let Some(index) = pass {
+            encoder.write_timestamp(&self.query_set, index * 2 + 1);
+        }
+    }
+
+    /// Copies the timestamps of the frame into the read buffer, before the encoder is finished
+    pub(crate) fn resolve(&self, encoder: &mut CommandEncoder) {
+        if self.labels.is_empty() {
+            return;
+        }
+
+        let query_count = self.labels.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.read_buffer,
+            0,
+            query_count as u64 * wgpu::QUERY_SIZE as u64,
+        );
+    }
+
+    /// Starts reading the timestamps back once the frame is submitted
+    pub(crate) fn map(&mut self) {
+        if self.labels.is_empty() || self.reading.is_some() {
+            return;
+        }
+
+        self.read_state.store(READ_PENDING, Ordering::Release);
+        let read_state = self.read_state.clone();
+        self.read_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let state = if result.is_ok() {
+                    READ_DONE
+                } else {
+                    READ_FAILED
+                };
+                read_state.store(state, Ordering::Release);
+            });
+        self.reading = Some(std::mem::take(&mut self.labels));
+    }
+
+    /// Timings of the last frame that finished reading back
+    pub(crate) fn collect(&mut self, device: &Device) -> &[PassTiming] {
+        device.poll(wgpu::Maintain::Poll);
+
+        match self.read_state.load(Ordering::Acquire) {
+            READ_DONE if self.reading.is_some() => {
+                let labels = self.reading.take().unwrap_or_default();
+                {
+                    let data = self.read_buffer.slice(..).get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                    self.last_timings = labels
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, label)| {
+                            let ticks =
+                                timestamps[index * 2 + 1].saturating_sub(timestamps[index * 2]);
+                            PassTiming {
+                                label,
+                                duration: Duration::from_nanos(
+                                    (ticks as f64 * self.period as f64) as u64,
+                                ),
+                            }
+                        })
+                        .collect();
+                }
+                self.read_buffer.unmap();
+            }
+            READ_FAILED => self.reading = None,
+            _ => {}
+        }
+
+        &self.last_timings
+    }
+}
+
+/// Rough GPU memory of a texture and its mips
+fn texture_memory(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    // Depth formats don't have a fixed size, the driver picks one
+    let block_size = format.block_size(None).unwrap_or(4) as u64;
+    let size = texture.size();
+
+    let bytes: u64 = (0..texture.mip_level_count())
+        .map(|level| {
+            let width = (size.width >> level).max(1).div_ceil(block_width) as u64;
+            let height = (size.height >> level).max(1).div_ceil(block_height) as u64;
+            width * height * size.depth_or_array_layers as u64 * block_size
+        })
+        .sum();
+
+    bytes * texture.sample_count() as u64
+}
+
+impl Renderer {
+    /// Stats of the last finished frame
+    pub fn render_stats(&self) -> &RenderStats {
+        &self.render_stats
+    }
+
+    /// Whether [`RenderStats::pass_timings`] get filled in, the adapter needs timestamp queries
+    pub fn gpu_timing_supported(&self) -> bool {
+        self.gpu_timer.is_some()
+    }
+
+    /// Starts timing a pass on the GPU, see [`Renderer::end_timed_pass`]
+    pub(crate) fn begin_timed_pass(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        label: &'static str,
+    ) -> Option<u32> {
+        self.gpu_timer
+            .as_mut()
+            .and_then(|timer| timer.begin_pass(encoder, label))
+    }
+
+    pub(crate) fn end_timed_pass(&self, encoder: &mut CommandEncoder, pass: Option<u32>) {
+        if let Some(timer) = &self.gpu_timer {
+            timer.end_pass(encoder, pass);
+        }
+    }
+
+    /// Fills in the texture stats and makes the frame's stats the ones [`Renderer::render_stats`] returns
+    pub(crate) fn finish_frame_stats(&mut self) {
+        let mut stats = std::mem::take(&mut self.frame_stats);
+        for texture in self.textures.iter() {
+            stats.textures += 1;
+            stats.texture_memory += texture_memory(&texture.texture);
+        }
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.map();
+            stats.pass_timings = timer.collect(&self.device).to_vec();
+        }
+
+        self.render_stats = stats;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        mesh::MeshBuilder, sprite::Sprite, transform::Transform,
+    };
+
+    #[test]
+    fn unchanged_bind_groups_are_not_rebound() {
+        let mut renderer = test_renderer((8, 8));
+        let material = renderer.material_map.default;
+        let quad = |cast_shadows| {
+            MeshBuilder::quad(Vec2::splat(8.), Transform::IDENTITY)
+                .with_texture(ArenaId::first())
+                .with_material(material)
+                .with_cast_shadows(cast_shadows)
+                .build()
+        };
+
+        // Meshes that only differ in casting shadows aren't batched, but share the texture
+        let mut ctx = renderer.begin();
+        renderer.push(quad(false));
+        renderer.push(quad(true));
+        renderer.render(&mut ctx, Some(Color::BLACK), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let stats = renderer.render_stats();
+        assert_eq!(stats.draw_calls, 2);
+        assert_eq!(stats.pipeline_switches, 1);
+        assert_eq!(stats.bind_group_switches, 1);
+    }
+
+    #[test]
+    fn render_stats_count_batched_sprites() {
+        let mut renderer = test_renderer((8, 8));
+
+        let mut ctx = renderer.begin();
+        let sprite = Sprite {
+            custom_size: Some(Vec2::splat(2.)),
+            ..Sprite::new(ArenaId::first())
+        };
+        for x in 0..3 {
+            renderer.draw_sprite(&sprite, Transform::from_position(Vec3::X * x as f32));
+        }
+        renderer.render(&mut ctx, Some(Color::BLACK), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let stats = renderer.render_stats();
+        assert_eq!(stats.meshes, 3);
+        assert_eq!(stats.draw_calls, 1);
+        assert_eq!(stats.pipeline_switches, 1);
+        assert_eq!(stats.instances, 3);
+        assert_eq!(stats.vertices, 0);
+        assert!(stats.textures > 0);
+        assert!(stats.texture_memory > 0);
+    }
+}
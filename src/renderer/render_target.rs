@@ -0,0 +1,168 @@
+use glam::UVec2;
+
+use crate::{arena::ArenaId, components::color::Color};
+
+use super::{
+    camera::Camera,
+    texture::{Texture, TextureSamplerType},
+    Renderer,
+};
+
+/// An offscreen texture the mesh queue can be rendered into
+/// The color texture lives in [`Renderer::textures`] so it can be drawn with a [`crate::sprite::Sprite`]
+#[derive(Debug, Clone, Copy)]
+pub struct RenderTarget {
+    pub texture: ArenaId<Texture>,
+    pub(crate) depth_texture: ArenaId<Texture>,
+    pub size: UVec2,
+}
+
+impl Renderer {
+    /// Creates a [`RenderTarget`] the same format as the window surface
+    /// so every material pipeline can render into it
+    pub fn create_render_target(
+        &mut self,
+        size: UVec2,
+        sampler: impl Into<TextureSamplerType>,
+    ) -> RenderTarget {
+        let sampler = self.get_sampler(sampler.into());
+        let texture = Texture::create_render_target(
+            &self.device,
+            (size.x, size.y),
+            self.surface_config.format,
+            sampler,
+        );
+
+        let depth_texture = Texture::create_depth_texture(
+            &self.device,
+            (size.x, size.y),
+            *self
+                .default_texture_samplers
+                .get(&TextureSamplerType::Depth)
+                .unwrap(),
+        );
+
+        RenderTarget {
+            texture: self.textures.insert(texture),
+            depth_texture: self.textures.insert(depth_texture),
+            size,
+        }
+    }
+
+    /// Renders the queued meshes into the [`RenderTarget`] instead of the window
+    /// The work is submitted straight away, so the target can be drawn as a sprite later in the same frame
+    pub fn render_to_target(
+        &mut self,
+        target: &RenderTarget,
+        clear_color: Option<Color>,
+        camera: &Camera,
+    ) {
+        let view = self
+            .textures
+            .get(target.texture)
+            .expect("Render target texture is missing")
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut command_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Target Encoder"),
+                });
+
+        self.render_to_view(
+            &mut command_encoder,
+            &view,
+            target.depth_texture,
+            (target.size.x, target.size.y),
+            clear_color,
+            camera,
+        );
+
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+    }
+
+    /// Removes the render target textures from the renderer
+    pub fn remove_render_target(&mut self, target: RenderTarget) {
+        self.invalidate_texture_bind_groups(target.texture);
+        self.msaa_targets.remove(&target.depth_texture);
+        self.textures.remove(target.texture);
+        self.textures.remove(target.depth_texture);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, Vec2};
+    use wgpu::AddressMode;
+
+    use crate::{
+        arena::ArenaId,
+        camera::Camera,
+        components::color::Color,
+        headless::test_renderer,
+        rect::Rect,
+        sprite::Sprite,
+        texture::{SamplerConfig, TextureSamplerType},
+        transform::Transform,
+    };
+
+    #[test]
+    fn render_target_draws_as_sprite() {
+        let mut renderer = test_renderer((8, 8));
+
+        let target = renderer.create_render_target(UVec2::splat(8), TextureSamplerType::Nearest);
+        renderer.render_to_target(&target, Some(Color::GREEN), &Camera::orthographic());
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&Sprite::new(target.texture), Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn render_target_with_custom_sampler() {
+        let mut renderer = test_renderer((8, 8));
+        let repeat = SamplerConfig::nearest().with_wrap(AddressMode::Repeat);
+
+        let target = renderer.create_render_target(UVec2::new(2, 1), repeat);
+        assert_eq!(
+            renderer.get_sampler(repeat.into()),
+            renderer.textures.get(target.texture).unwrap().sampler
+        );
+
+        // A red and a blue pixel, tiled across the frame by the repeat wrap
+        for (x, color) in [(-0.5, Color::RED), (0.5, Color::BLUE)] {
+            let pixel = Sprite {
+                custom_size: Some(Vec2::ONE),
+                color: color.as_rgba_f32(),
+                ..Sprite::new(ArenaId::first())
+            };
+            renderer.draw_sprite(&pixel, Transform::from_xyz(x, 0., 0.));
+        }
+        renderer.render_to_target(&target, Some(Color::GREEN), &Camera::orthographic());
+
+        let sprite = Sprite {
+            texture_rect: Some(Rect::new(Vec2::new(4., 1.))),
+            custom_size: Some(Vec2::splat(8.)),
+            ..Sprite::new(target.texture)
+        };
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::GREEN), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 / 2 % 2 == 0 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            assert_eq!(pixel, expected, "pixel {index}");
+        }
+    }
+}
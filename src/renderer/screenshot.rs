@@ -0,0 +1,258 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use wgpu::{TextureFormat, TextureView};
+
+use super::{
+    errors::RenderError,
+    texture::{Image, Texture, TextureSamplerType},
+    RenderContext, Renderer,
+};
+
+/// Folder the debug screenshot hotkey saves into, relative to the working directory
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Where in the frame [`crate::Engine::capture_screenshot`] reads it back
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaptureStage {
+    /// After post processing, without the egui windows
+    #[default]
+    BeforeEgui,
+    AfterEgui,
+}
+
+/// A path in [`SCREENSHOT_DIR`] named after the current time
+pub fn timestamped_screenshot_path() -> PathBuf {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Path::new(SCREENSHOT_DIR).join(format!(
+        "screenshot_{}_{:03}.png",
+        since_epoch.as_secs(),
+        since_epoch.subsec_millis()
+    ))
+}
+
+impl Image {
+    /// Saves an 8 bit RGBA image, like one from [`Renderer::capture_frame`], as a PNG
+    /// Creates the missing parent folders
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if !matches!(
+            self.format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+        ) {
+            return Err(format!("Can't save {:?} images as PNG", self.format));
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|error| format!("{}: {error}", parent.display()))?;
+        }
+
+        image::save_buffer_with_format(
+            path,
+            &self.data,
+            self.dimensions.0,
+            self.dimensions.1,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+        .map_err(|error| format!("{}: {error}", path.display()))
+    }
+}
+
+impl Renderer {
+    /// Lets [`Renderer::capture_frame`] read the next frame of a window
+    /// Call it before [`Renderer::begin`], the frame renders into a texture that's copied
+    /// to the surface by [`Renderer::end_frame`], as not every backend can read the surface back
+    /// Headless frames can always be captured
+    pub fn request_frame_capture(&mut self) {
+        self.capture_requested = true;
+    }
+
+    /// Reads back what has been rendered into the frame so far as sRGB encoded RGBA
+    /// Call it after [`Renderer::apply_post_processing`] for the finished scene,
+    /// or after [`Renderer::render_egui`] to include the egui windows
+    /// Submits the commands recorded so far and blocks until the GPU is done with them
+    pub fn capture_frame(&self, render_context: &mut RenderContext) -> Result<Image, RenderError> {
+        let handle = if render_context.surface_view.is_some() {
+            self.capture_target
+        } else if render_context.output.is_some() {
+            return Err(RenderError::new(
+                "Call Renderer::request_frame_capture before Renderer::begin to capture a window",
+            ));
+        } else {
+            self.headless_target
+        };
+        let texture = &self
+            .textures
+            .get(handle.expect("Missing the texture the frame renders into"))
+            .unwrap()
+            .texture;
+
+        let command_encoder = std::mem::replace(
+            &mut render_context.command_encoder,
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                }),
+        );
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+
+        frame_to_rgba8(self.read_wgpu_texture(texture))
+    }
+}
+
+impl Renderer {
+    /// Swaps the surface view for the capture target when a capture was requested
+    /// Returns the view to render the frame into, and the surface view if it was swapped out
+    pub(crate) fn begin_frame_capture(
+        &mut self,
+        view: TextureView,
+    ) -> (TextureView, Option<TextureView>) {
+        if !self.capture_requested || self.surface.is_none() {
+            return (view, None);
+        }
+
+        let handle = match self.capture_target {
+            Some(handle) => handle,
+            None => {
+                let texture = Texture::create_render_target(
+                    &self.device,
+                    self.get_viewport_size(),
+                    self.surface_config.format,
+                    *self
+                        .default_texture_samplers
+                        .get(&TextureSamplerType::Nearest)
+                        .unwrap(),
+                );
+                let handle = self.textures.insert(texture);
+                self.capture_target = Some(handle);
+                handle
+            }
+        };
+        let capture_view = self
+            .textures
+            .get(handle)
+            .unwrap()
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        (capture_view, Some(view))
+    }
+
+    /// Copies the captured frame to the surface so it's still presented
+    pub(crate) fn finish_frame_capture(&mut self, render_context: &mut RenderContext) {
+        self.capture_requested = false;
+        let (Some(surface_view), Some(target)) =
+            (render_context.surface_view.take(), self.capture_target)
+        else {
+            return;
+        };
+
+        let material = self.material_map.default;
+        self.render_fullscreen_pass(
+            render_context,
+            target,
+            &surface_view,
+            material,
+            "Frame copy",
+        );
+    }
+
+    /// Called on resize, the target is recreated at the new size by the next capture
+    pub(crate) fn drop_capture_target(&mut self) {
+        if let Some(texture) = self.capture_target.take() {
+            self.invalidate_texture_bind_groups(texture);
+            self.msaa_targets.remove(&texture);
+            self.textures.remove(texture);
+        }
+    }
+}
+
+/// Swaps BGRA frames to RGBA, the bytes stay sRGB encoded since PNGs are sRGB too
+fn frame_to_rgba8(mut image: Image) -> Result<Image, RenderError> {
+    match image.format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+            for pixel in image.data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        format => {
+            return Err(RenderError::new(format!(
+                "Can't capture frames in {format:?}"
+            )))
+        }
+    }
+
+    image.format = if image.format.is_srgb() {
+        TextureFormat::Rgba8UnormSrgb
+    } else {
+        TextureFormat::Rgba8Unorm
+    };
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::TextureFormat;
+
+    use super::frame_to_rgba8;
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, texture::Image,
+    };
+
+    #[test]
+    fn bgra_frames_swap_to_rgba() {
+        let image = frame_to_rgba8(Image {
+            data: vec![1, 2, 3, 4],
+            dimensions: (1, 1),
+            format: TextureFormat::Bgra8UnormSrgb,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(image.data, [3, 2, 1, 4]);
+        assert_eq!(image.format, TextureFormat::Rgba8UnormSrgb);
+    }
+
+    #[test]
+    fn float_frames_are_rejected() {
+        let image = Image {
+            data: vec![0; 8],
+            dimensions: (1, 1),
+            format: TextureFormat::Rgba16Float,
+            ..Default::default()
+        };
+
+        assert!(frame_to_rgba8(image).is_err());
+    }
+
+    #[test]
+    fn capture_frame_saves_png() {
+        let mut renderer = test_renderer((4, 2));
+
+        // Captured before the frame ends, like the engine does before egui
+        let mut ctx = renderer.begin();
+        renderer.render(&mut ctx, Some(Color::BLUE), &Camera::orthographic());
+        let image = renderer.capture_frame(&mut ctx).unwrap();
+        renderer.end_frame(ctx);
+
+        assert_eq!(image.dimensions, (4, 2));
+        assert!(image.data.chunks(4).all(|pixel| pixel == [0, 0, 255, 255]));
+
+        let path = std::env::temp_dir()
+            .join("nimbus_capture_frame_test")
+            .join("frame.png");
+        image.save_png(&path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(saved.dimensions(), (4, 2));
+        assert_eq!(saved.into_raw(), image.data);
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use wgpu::{ShaderModuleDescriptor, ShaderSource};
+
+use super::{errors::RenderError, material::Material, mesh::MeshAttribute, Renderer};
+
+/// Expands the directives of material WGSL before it's compiled
+///
+/// - `#import nimbus::view` pastes a registered module in, once per shader
+/// - `#define NAME` sets a shader def for the rest of the source
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or drop the lines between them
+///
+/// Materials set defs with [`Material::shader_defs`], on top of `HAS_TEXTURE` when the material
+/// has a texture and `VERTEX_UV`, `VERTEX_COLOR` and `VERTEX_NORMAL` for its vertex attributes,
+/// instanced materials don't get vertex defs
+pub struct ShaderPreprocessor {
+    modules: HashMap<String, Cow<'static, str>>,
+}
+
+impl Default for ShaderPreprocessor {
+    fn default() -> Self {
+        let mut preprocessor = Self {
+            modules: HashMap::default(),
+        };
+        preprocessor.add_module(
+            "nimbus::view",
+            include_str!("./default_shaders/modules/view.wgsl"),
+        );
+        preprocessor.add_module(
+            "nimbus::sprite",
+            include_str!("./default_shaders/modules/sprite.wgsl"),
+        );
+        preprocessor.add_module(
+            "nimbus::lighting",
+            include_str!("./default_shaders/modules/lighting.wgsl"),
+        );
+
+        preprocessor
+    }
+}
+
+/// A `#ifdef` block being read
+struct Conditional {
+    condition: bool,
+    in_else: bool,
+}
+
+impl Conditional {
+    fn keeps_lines(&self) -> bool {
+        self.condition != self.in_else
+    }
+}
+
+struct PreprocessState {
+    defs: HashSet<String>,
+    imported: HashSet<String>,
+    output: String,
+}
+
+impl ShaderPreprocessor {
+    /// Registers a module shaders can `#import`, replacing one with the same name
+    pub fn add_module(&mut self, name: impl Into<String>, source: impl Into<Cow<'static, str>>) {
+        self.modules.insert(name.into(), source.into());
+    }
+
+    /// Expands `source` with `defs` set, imported modules are pasted where they're first imported
+    pub fn process(&self, source: &str, defs: &[String]) -> Result<String, RenderError> {
+        let mut state = PreprocessState {
+            defs: defs.iter().cloned().collect(),
+            imported: HashSet::new(),
+            output: String::with_capacity(source.len()),
+        };
+        self.process_source("shader", source, &mut state)?;
+
+        Ok(state.output)
+    }
+
+    fn process_source(
+        &self,
+        name: &str,
+        source: &str,
+        state: &mut PreprocessState,
+    ) -> Result<(), RenderError> {
+        let mut conditionals: Vec<Conditional> = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let error =
+                |message: String| RenderError::new(format!("{name}:{}: {message}", index + 1));
+            let active = conditionals.iter().all(Conditional::keeps_lines);
+
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    state.output.push_str(line);
+                    state.output.push('\n');
+                }
+                continue;
+            };
+
+            let mut words = directive.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let argument = words.next();
+            if words.next().is_some() {
+                return Err(error(format!("#{keyword} takes at most one argument")));
+            }
+
+            match (keyword, argument) {
+                ("ifdef" | "ifndef", Some(def)) => conditionals.push(Conditional {
+                    condition: state.defs.contains(def) == (keyword == "ifdef"),
+                    in_else: false,
+                }),
+                ("else", None) => match conditionals.last_mut() {
+                    Some(conditional) if !conditional.in_else => conditional.in_else = true,
+                    _ => return Err(error("#else without #ifdef".to_string())),
+                },
+                ("endif", None) => {
+                    if conditionals.pop().is_none() {
+                        return Err(error("#endif without #ifdef".to_string()));
+                    }
+                }
+                ("define", Some(def)) => {
+                    if active {
+                        state.defs.insert(def.to_string());
+                    }
+                }
+                ("import", Some(module)) => {
+                    if active && state.imported.insert(module.to_string()) {
+                        let Some(module_source) = self.modules.get(module) else {
+                            return Err(error(format!("unknown module {module}")));
+                        };
+                        self.process_source(module, module_source, state)?;
+                    }
+                }
+                _ => return Err(error(format!("invalid directive #{directive}"))),
+            }
+        }
+
+        if !conditionals.is_empty() {
+            return Err(RenderError::new(format!("{name}: #ifdef without #endif")));
+        }
+
+        Ok(())
+    }
+}
+
+/// Defs every shader of `material` gets
+fn material_shader_defs(material: &(impl Material + ?Sized)) -> Vec<String> {
+    let mut defs = material.shader_defs();
+    if material.has_texture() {
+        defs.push("HAS_TEXTURE".to_string());
+    }
+    let attributes = if material.instanced() {
+        Default::default()
+    } else {
+        material.vertex_attributes()
+    };
+    for attribute in attributes {
+        match attribute {
+            MeshAttribute::UV => defs.push("VERTEX_UV".to_string()),
+            MeshAttribute::Color => defs.push("VERTEX_COLOR".to_string()),
+            MeshAttribute::Normal => defs.push("VERTEX_NORMAL".to_string()),
+            MeshAttribute::Position => {}
+        }
+    }
+
+    defs
+}
+
+impl Renderer {
+    /// Registers a module material shaders can `#import`, see [`ShaderPreprocessor`]
+    /// Only pipelines created afterwards see it
+    pub fn add_shader_module(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<Cow<'static, str>>,
+    ) {
+        self.shader_preprocessor.add_module(name, source);
+    }
+
+    /// Expands the directives of a WGSL shader with the defs of `material`
+    pub(crate) fn preprocess_shader<'a>(
+        &self,
+        material: &(impl Material + ?Sized),
+        shader: ShaderModuleDescriptor<'a>,
+    ) -> Result<ShaderModuleDescriptor<'a>, RenderError> {
+        let source = match shader.source {
+            ShaderSource::Wgsl(source) => ShaderSource::Wgsl(
+                self.shader_preprocessor
+                    .process(&source, &material_shader_defs(material))
+                    .map_err(|error| {
+                        RenderError::new(format!(
+                            "Shader of {} failed to preprocess: {}",
+                            material.label(),
+                            error.message
+                        ))
+                    })?
+                    .into(),
+            ),
+            source => source,
+        };
+
+        Ok(ShaderModuleDescriptor {
+            label: shader.label,
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::ShaderPreprocessor;
+    use crate::{
+        arena::ArenaId, camera::Camera, components::color::Color, headless::test_renderer,
+        material::Material, sprite::Sprite, transform::Transform,
+    };
+
+    fn process(source: &str, defs: &[&str]) -> String {
+        let defs: Vec<String> = defs.iter().map(|def| def.to_string()).collect();
+        ShaderPreprocessor::default()
+            .process(source, &defs)
+            .unwrap()
+    }
+
+    #[test]
+    fn ifdef_keeps_matching_branch() {
+        let source = "#ifdef A\na\n#else\nnot a\n#endif\n#ifndef B\nnot b\n#endif";
+
+        assert_eq!(process(source, &["A"]), "a\nnot b\n");
+        assert_eq!(process(source, &["B"]), "not a\n");
+    }
+
+    #[test]
+    fn nested_blocks_and_defines() {
+        let source =
+            "#define A\n#ifdef B\n#ifdef A\nboth\n#endif\n#else\n#ifdef A\nonly a\n#endif\n#endif";
+
+        assert_eq!(process(source, &["B"]), "both\n");
+        assert_eq!(process(source, &[]), "only a\n");
+    }
+
+    #[test]
+    fn modules_are_imported_once() {
+        let mut preprocessor = ShaderPreprocessor::default();
+        preprocessor.add_module("game::common", "#import nimbus::view\nfn common() {}");
+
+        let output = preprocessor
+            .process("#import nimbus::view\n#import game::common", &[])
+            .unwrap();
+        assert_eq!(output.matches("struct View").count(), 1);
+        assert!(output.contains("fn common() {}"));
+    }
+
+    #[test]
+    fn invalid_directives_fail() {
+        let preprocessor = ShaderPreprocessor::default();
+
+        for source in [
+            "#import game::missing",
+            "#ifdef A",
+            "#endif",
+            "#ifdef A\n#else\n#else",
+        ] {
+            assert!(preprocessor.process(source, &[]).is_err(), "{source}");
+        }
+    }
+
+    #[derive(Debug)]
+    struct VariantMaterial {
+        blue: bool,
+    }
+
+    impl Material for VariantMaterial {
+        fn shader(&self) -> wgpu::ShaderModuleDescriptor<'_> {
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Variant shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    "#import nimbus::sprite\n\
+                     @vertex fn vertex(v: VertexInput) -> VertexOutput { return sprite_vertex(v); }\n\
+                     @fragment fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {\n\
+                     #ifdef BLUE\n\
+                     return vec4<f32>(0.0, 0.0, 1.0, 1.0);\n\
+                     #else\n\
+                     return sprite_color(in);\n\
+                     #endif\n\
+                     }"
+                    .into(),
+                ),
+            }
+        }
+
+        fn shader_defs(&self) -> Vec<String> {
+            if self.blue {
+                vec!["BLUE".to_string()]
+            } else {
+                Vec::new()
+            }
+        }
+
+        fn has_texture(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn shader_defs_select_variant() {
+        let mut renderer = test_renderer((8, 8));
+        let vertex_color = renderer.push_material(VariantMaterial { blue: false });
+        let blue = renderer.push_material(VariantMaterial { blue: true });
+
+        let half = |material| Sprite {
+            color: Color::GREEN.as_rgba_f32(),
+            custom_size: Some(Vec2::new(4., 8.)),
+            material: Some(material),
+            ..Sprite::new(ArenaId::first())
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&half(vertex_color), Transform::from_xyz(-2., 0., 0.));
+        renderer.draw_sprite(&half(blue), Transform::from_xyz(2., 0., 0.));
+        renderer.render(&mut ctx, Some(Color::RED), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [0, 255, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            assert_eq!(pixel, expected);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use glam::Vec3;
+
+use super::{mesh::Mesh, Renderer};
+
+/// How meshes on the same layer are ordered, lowest key is drawn first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    /// Higher Z is drawn on top
+    Z,
+    /// Lower Y is drawn on top, for top down games
+    Y,
+    /// Sorts by the position projected onto the axis
+    Axis(Vec3),
+    /// Uses the value given with [`crate::mesh::MeshBuilder::with_sort_value`]
+    Custom,
+}
+
+impl SortKey {
+    fn value(&self, mesh: &Mesh) -> f32 {
+        match self {
+            SortKey::Z => mesh.sort_position.z,
+            SortKey::Y => -mesh.sort_position.y,
+            SortKey::Axis(axis) => mesh.sort_position.dot(*axis),
+            SortKey::Custom => mesh.sort_value,
+        }
+    }
+}
+
+impl Renderer {
+    /// Sets how meshes on `layer` are sorted, layers without one use [`Renderer::sorting_axis`]
+    pub fn set_layer_sort_key(&mut self, layer: i32, sort_key: SortKey) {
+        self.layer_sort_keys.insert(layer, sort_key);
+    }
+
+    /// Splits the queue into opaque meshes sorted front to back, followed by everything else
+    /// sorted back to front by layer then sort key
+    pub(crate) fn sort_meshes(&self, meshes: Vec<Mesh>, camera_position: Vec3) -> Vec<Mesh> {
+        let (mut opaque, mut transparent): (Vec<Mesh>, Vec<Mesh>) =
+            meshes.into_iter().partition(|mesh| {
+                self.materials
+                    .get(mesh.material_handle)
+                    .is_some_and(|pipeline| pipeline.material.is_opaque())
+            });
+
+        sort_front_to_back(&mut opaque, camera_position);
+        sort_by_layer(
+            &mut transparent,
+            &self.layer_sort_keys,
+            SortKey::Axis(self.sorting_axis),
+        );
+
+        opaque.append(&mut transparent);
+
+        opaque
+    }
+}
+
+/// Stable sort, meshes with equal keys keep the order they were drawn in
+fn sort_by_layer(
+    meshes: &mut Vec<Mesh>,
+    layer_sort_keys: &HashMap<i32, SortKey>,
+    default_sort_key: SortKey,
+) {
+    let mut keyed: Vec<(i32, f32, Mesh)> = meshes
+        .drain(..)
+        .map(|mesh| {
+            let sort_key = layer_sort_keys
+                .get(&mesh.layer)
+                .unwrap_or(&default_sort_key);
+
+            (mesh.layer, sort_key.value(&mesh), mesh)
+        })
+        .collect();
+
+    keyed.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.total_cmp(&b.1)));
+
+    meshes.extend(keyed.into_iter().map(|(_, _, mesh)| mesh));
+}
+
+/// Nearest first so the depth test can skip the fragments behind them
+fn sort_front_to_back(meshes: &mut [Mesh], camera_position: Vec3) {
+    meshes.sort_by(|a, b| {
+        a.sort_position
+            .distance_squared(camera_position)
+            .partial_cmp(&b.sort_position.distance_squared(camera_position))
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::Vec3;
+
+    use super::{sort_by_layer, sort_front_to_back, SortKey};
+    use crate::{
+        arena::ArenaId,
+        mesh::{Indices, Mesh, MeshBuilder, VertexData},
+    };
+
+    fn mesh(layer: i32, position: Vec3, sort_value: f32) -> Mesh {
+        MeshBuilder::new()
+            .with_layer(layer)
+            .with_sort_position(position)
+            .with_sort_value(sort_value)
+            .build()
+    }
+
+    fn sort_values(meshes: &[Mesh]) -> Vec<f32> {
+        meshes.iter().map(|mesh| mesh.sort_value).collect()
+    }
+
+    #[test]
+    fn layers_sort_before_keys() {
+        let mut meshes = vec![
+            mesh(1, Vec3::new(0., 0., -5.), 0.),
+            mesh(0, Vec3::new(0., 0., 3.), 1.),
+            mesh(0, Vec3::new(0., 0., 1.), 2.),
+        ];
+        sort_by_layer(&mut meshes, &HashMap::new(), SortKey::Z);
+
+        assert_eq!(sort_values(&meshes), vec![2., 1., 0.]);
+    }
+
+    #[test]
+    fn y_sort_is_stable() {
+        let mut meshes = vec![
+            mesh(0, Vec3::new(0., 10., 0.), 0.),
+            mesh(0, Vec3::new(0., 20., 0.), 1.),
+            mesh(0, Vec3::new(5., 10., 0.), 2.),
+            mesh(0, Vec3::new(0., 20., 0.), 3.),
+        ];
+        sort_by_layer(&mut meshes, &HashMap::from([(0, SortKey::Y)]), SortKey::Z);
+
+        assert_eq!(sort_values(&meshes), vec![1., 3., 0., 2.]);
+    }
+
+    #[test]
+    fn new_meshes_sort_by_value_on_default_key() {
+        let new_mesh = |sort_value| {
+            Mesh::new(
+                None,
+                ArenaId::first(),
+                VertexData::default(),
+                Indices::default(),
+                sort_value,
+            )
+        };
+        let mut meshes = vec![new_mesh(2.), new_mesh(0.), new_mesh(1.)];
+        sort_by_layer(&mut meshes, &HashMap::new(), SortKey::Axis(Vec3::Z));
+
+        assert_eq!(sort_values(&meshes), vec![0., 1., 2.]);
+    }
+
+    #[test]
+    fn opaque_sorts_front_to_back() {
+        let mut meshes = vec![
+            mesh(0, Vec3::new(0., 0., -10.), 0.),
+            mesh(0, Vec3::new(0., 0., -2.), 1.),
+            mesh(0, Vec3::new(0., 0., -5.), 2.),
+        ];
+        sort_front_to_back(&mut meshes, Vec3::ZERO);
+
+        assert_eq!(sort_values(&meshes), vec![1., 2., 0.]);
+    }
+}
@@ -1,6 +1,10 @@
 use glam::Vec2;
 
-use crate::{arena::ArenaId, pipeline::Pipeline};
+use crate::{
+    arena::ArenaId,
+    material_params::{MaterialParams, ParamsHandle},
+    pipeline::Pipeline,
+};
 
 use super::{rect::Rect, texture::Texture};
 
@@ -14,6 +18,14 @@ pub struct Sprite {
     pub custom_size: Option<Vec2>,
     pub flip_x: bool,
     pub flip_y: bool,
+    /// Sprites on higher layers are drawn on top, see [`crate::sorting::SortKey`]
+    pub layer: i32,
+    /// Shades the sprite with the 2D lights, the texture should be in a non-sRGB format
+    /// with green pointing up, its alpha masks it
+    pub normal_map: Option<ArenaId<Texture>>,
+    /// Params of [`Sprite::material`] from [`crate::Renderer::create_params_instance`],
+    /// `None` uses the material's default params
+    pub params: Option<ArenaId<MaterialParams>>,
 }
 
 impl Default for Sprite {
@@ -27,6 +39,9 @@ impl Default for Sprite {
             custom_size: None,
             flip_x: false,
             flip_y: false,
+            layer: 0,
+            normal_map: None,
+            params: None,
         }
     }
 }
@@ -47,6 +62,21 @@ impl Sprite {
         self.flip_x = flip_x;
         self
     }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: ArenaId<Texture>) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    pub fn with_params<T>(mut self, params: ParamsHandle<T>) -> Self {
+        self.params = Some(params.id());
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
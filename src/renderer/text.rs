@@ -20,6 +20,7 @@ pub struct Text {
     value: String,
     font_size: f32,
     pub color: Color,
+    pub layer: i32,
     vertical_alignment: VerticalAlign,
     horizontal_alignment: HorizontalAlign,
     y_axis_orientation: CoordinateSystem,
@@ -34,6 +35,11 @@ impl Text {
         }
     }
 
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
     pub fn with_color(mut self, color: Color) -> Self {
         self.color = color;
         self
@@ -75,6 +81,7 @@ impl Default for Text {
             horizontal_alignment: HorizontalAlign::Center,
             y_axis_orientation: CoordinateSystem::PositiveYUp,
             color: Color::WHITE, // White
+            layer: 0,
         }
     }
 }
@@ -93,6 +100,7 @@ impl Renderer {
                 temp_texture_data.dimensions,
                 crate::texture::TextureSamplerType::Linear,
                 TextureFormat::Rgba8UnormSrgb,
+                false,
             );
 
             // Update texture or insert new texture
@@ -103,7 +111,7 @@ impl Renderer {
                 .texture_ids
                 .get(&(FloatOrd(text.font_size)))
             {
-                *self.textures.get_mut(*handle).unwrap() = texture;
+                self.replace_texture(*handle, texture);
             } else {
                 let texture_handle = self.textures.insert(texture);
 
@@ -1,13 +1,18 @@
 use glam::Vec2;
 
 use wgpu::{
-    BindGroup, BindGroupLayout, BindingResource, Device, Extent3d, Queue, Sampler,
-    SurfaceConfiguration, TextureFormat,
+    AddressMode, BindGroup, BindGroupLayout, BindingResource, BindingType, Device, Extent3d,
+    FilterMode, Queue, Sampler, ShaderStages, TextureFormat,
 };
 
 use crate::arena::ArenaId;
 
-use super::{bind_groups::BindGroupBuilder, Renderer};
+use super::{
+    bind_groups::{BindGroupBuilder, BindGroupLayoutBuilder},
+    mipmaps::{can_generate_mipmaps, mip_level_count},
+    render_state::DEPTH_FORMAT,
+    Renderer,
+};
 
 #[derive(Default, Debug, PartialEq, Hash, Eq, Clone, Copy)]
 pub enum TextureSamplerType {
@@ -15,6 +20,115 @@ pub enum TextureSamplerType {
     #[default]
     Nearest,
     Depth,
+    /// Wrap modes, filters and anisotropy of your choosing
+    Custom(SamplerConfig),
+}
+
+impl TextureSamplerType {
+    /// `None` for the depth comparison sampler
+    pub fn config(&self) -> Option<SamplerConfig> {
+        match self {
+            TextureSamplerType::Linear => Some(SamplerConfig::linear()),
+            TextureSamplerType::Nearest => Some(SamplerConfig::nearest()),
+            TextureSamplerType::Depth => None,
+            TextureSamplerType::Custom(config) => Some(*config),
+        }
+    }
+}
+
+impl From<SamplerConfig> for TextureSamplerType {
+    fn from(config: SamplerConfig) -> Self {
+        TextureSamplerType::Custom(config)
+    }
+}
+
+/// How a texture is sampled, samplers with the same config are shared in [`Renderer::samplers`]
+#[derive(Debug, PartialEq, Hash, Eq, Clone, Copy)]
+pub struct SamplerConfig {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    /// Max anisotropy, 1 turns it off
+    pub anisotropy: u16,
+}
+
+impl SamplerConfig {
+    /// Clamped nearest filtering, like [`TextureSamplerType::Nearest`]
+    pub fn nearest() -> Self {
+        Self {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            anisotropy: 1,
+        }
+    }
+
+    /// Clamped linear filtering, like [`TextureSamplerType::Linear`]
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Self::nearest()
+        }
+    }
+
+    /// Sets the wrap mode of both axes, [`AddressMode::Repeat`] tiles the texture
+    pub fn with_wrap(mut self, address_mode: AddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self
+    }
+
+    /// Turns anisotropy back off when a filter isn't linear
+    pub fn with_filters(mut self, mag_filter: FilterMode, min_filter: FilterMode) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        if !self.all_linear() {
+            self.anisotropy = 1;
+        }
+        self
+    }
+
+    /// Anisotropic filtering needs every filter to be linear, so they're all set to it
+    /// Clamped to 1 through 16
+    pub fn with_anisotropy(mut self, anisotropy: u16) -> Self {
+        self.anisotropy = anisotropy.clamp(1, 16);
+        if self.anisotropy > 1 {
+            self.mag_filter = FilterMode::Linear;
+            self.min_filter = FilterMode::Linear;
+            self.mipmap_filter = FilterMode::Linear;
+        }
+        self
+    }
+
+    fn all_linear(&self) -> bool {
+        [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == FilterMode::Linear)
+    }
+
+    pub(crate) fn create_sampler(&self, device: &Device) -> Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture sampler"),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            // wgpu rejects anisotropy without linear filters, configs built by hand can still have it
+            anisotropy_clamp: if self.all_linear() {
+                self.anisotropy.clamp(1, 16)
+            } else {
+                1
+            },
+            ..Default::default()
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -23,6 +137,21 @@ pub struct Image {
     pub dimensions: (u32, u32),
     pub sampler: TextureSamplerType,
     pub format: TextureFormat,
+    /// Generates a full mip chain on upload, so the texture doesn't shimmer when drawn small
+    /// Ignored for formats that can't be rendered into
+    pub mipmaps: bool,
+}
+
+impl Image {
+    pub fn with_sampler(mut self, sampler: impl Into<TextureSamplerType>) -> Self {
+        self.sampler = sampler.into();
+        self
+    }
+
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
 }
 
 impl Default for Image {
@@ -32,6 +161,7 @@ impl Default for Image {
             dimensions: Default::default(),
             sampler: Default::default(),
             format: TextureFormat::Rgba8UnormSrgb,
+            mipmaps: false,
         }
     }
 }
@@ -91,13 +221,13 @@ impl Texture {
 
     pub(crate) fn create_depth_texture(
         device: &Device,
-        surface_config: &SurfaceConfiguration,
+        size: (u32, u32),
         sampler: ArenaId<Sampler>,
     ) -> Self {
+        let dimensions = Vec2::new(size.0 as f32, size.1 as f32);
         let size = wgpu::Extent3d {
-            // 2.
-            width: surface_config.width,
-            height: surface_config.height,
+            width: size.0,
+            height: size.1,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
@@ -106,9 +236,9 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[TextureFormat::Depth32Float],
+            view_formats: &[DEPTH_FORMAT],
         };
         let texture = device.create_texture(&desc);
 
@@ -118,11 +248,70 @@ impl Texture {
         Texture {
             texture,
             view,
-            dimensions: Vec2::new(surface_config.width as f32, surface_config.height as f32),
+            dimensions,
+            sampler,
+        }
+    }
+
+    /// Creates a texture that can be rendered into, sampled and copied back to the CPU
+    pub(crate) fn create_render_target(
+        device: &Device,
+        size: (u32, u32),
+        format: TextureFormat,
+        sampler: ArenaId<Sampler>,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render target texture"),
+            size: Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Texture {
+            texture,
+            view,
+            dimensions: Vec2::new(size.0 as f32, size.1 as f32),
             sampler,
         }
     }
 
+    /// Layout for a texture and its sampler, shared by every material that has a texture
+    pub(crate) fn create_bind_group_layout(device: &Device, filterable: bool) -> BindGroupLayout {
+        BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable },
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Sampler(if filterable {
+                    wgpu::SamplerBindingType::Filtering
+                } else {
+                    wgpu::SamplerBindingType::NonFiltering
+                }),
+                None,
+            )
+            .build(device, Some("texture_bind_group_layout"))
+    }
+
     pub fn create_bind_group(
         &self,
         device: &Device,
@@ -140,7 +329,14 @@ impl Renderer {
     /// Loads a texture to the GPU
     /// Returns a handle to the texture ref
     pub fn add_texture(&mut self, image: Image) -> ArenaId<Texture> {
-        self.add_texture_from_bytes(&image.data, image.dimensions, image.sampler, image.format)
+        let texture = self.add_texture_bytes(
+            &image.data,
+            image.dimensions,
+            image.sampler,
+            image.format,
+            image.mipmaps,
+        );
+        self.textures.insert(texture)
     }
 
     /// Loads a texture to the GPU by passing the image bytes
@@ -152,7 +348,7 @@ impl Renderer {
         sampler: TextureSamplerType,
         format: TextureFormat,
     ) -> ArenaId<Texture> {
-        let texture = self.add_texture_bytes(bytes, size, sampler, format);
+        let texture = self.add_texture_bytes(bytes, size, sampler, format, false);
         self.textures.insert(texture)
     }
 
@@ -162,7 +358,23 @@ impl Renderer {
         size: (u32, u32),
         texture_sampler_type: TextureSamplerType,
         format: TextureFormat,
+        mipmaps: bool,
     ) -> Texture {
+        let mipmaps = mipmaps && can_generate_mipmaps(&self.device, format);
+        let (mip_level_count, usage) = if mipmaps {
+            (
+                mip_level_count(size),
+                wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+        } else {
+            (
+                1,
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            )
+        };
+
         let size = Extent3d {
             width: size.0 as _,
             height: size.1 as _,
@@ -172,11 +384,11 @@ impl Renderer {
         let texture_descriptor = wgpu::TextureDescriptor {
             label: None,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            usage,
             view_formats: &[],
         };
 
@@ -198,6 +410,9 @@ impl Renderer {
             },
             size,
         );
+        if mipmaps {
+            self.generate_mipmaps(&texture);
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
@@ -205,25 +420,203 @@ impl Renderer {
             texture,
             view,
             dimensions: Vec2::new(size.width as f32, size.height as f32),
-            sampler: *self
-                .default_texture_samplers
-                .get(&texture_sampler_type)
-                .unwrap(),
+            sampler: self.get_sampler(texture_sampler_type),
         }
     }
 
+    /// Handle of the sampler in [`Renderer::samplers`], created the first time a config is used
+    pub fn get_sampler(&mut self, sampler: TextureSamplerType) -> ArenaId<Sampler> {
+        let Some(config) = sampler.config() else {
+            return self.default_texture_samplers[&sampler];
+        };
+
+        if let Some(handle) = self.sampler_cache.get(&config) {
+            return *handle;
+        }
+        let handle = self.samplers.insert(config.create_sampler(&self.device));
+        self.sampler_cache.insert(config, handle);
+
+        handle
+    }
+
+    /// Changes how a texture is sampled, without reuploading it
+    pub fn set_texture_sampler(
+        &mut self,
+        handle: ArenaId<Texture>,
+        sampler: impl Into<TextureSamplerType>,
+    ) {
+        let sampler = self.get_sampler(sampler.into());
+        self.textures
+            .get_mut(handle)
+            .expect("No texture to change the sampler of")
+            .sampler = sampler;
+        self.invalidate_params_bind_groups(handle);
+    }
+
     pub(crate) fn replace_texture(&mut self, handle: ArenaId<Texture>, texture: Texture) {
+        self.invalidate_texture_bind_groups(handle);
         *self
             .textures
             .get_mut(handle)
             .expect("No texture to replace") = texture;
     }
 
+    /// Copies a texture back from the GPU into an [`Image`]
+    /// The texture must have been created with `COPY_SRC` usage, like a render target
+    /// Blocks until the GPU has finished all submitted work
+    pub fn read_texture(&self, handle: ArenaId<Texture>) -> Image {
+        let texture = self.textures.get(handle).expect("No texture to read");
+        self.read_wgpu_texture(&texture.texture)
+    }
+
+    /// [`Renderer::read_texture`] for textures outside the arena, like the swapchain frame
+    pub(crate) fn read_wgpu_texture(&self, texture: &wgpu::Texture) -> Image {
+        let (width, height) = (texture.width(), texture.height());
+        let format = texture.format();
+        let block_size = format.block_size(None).unwrap();
+
+        let unpadded_bytes_per_row = block_size * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (unpadded_bytes_per_row + align - 1) / align * align;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Texture readback buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture readback encoder"),
+            });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Failed to map texture readback buffer");
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        // Rows are padded to COPY_BYTES_PER_ROW_ALIGNMENT, strip it so the image is tightly packed
+        let data = buffer_slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| row[..unpadded_bytes_per_row as usize].to_vec())
+            .collect::<Vec<u8>>();
+        buffer.unmap();
+
+        Image {
+            data,
+            dimensions: (width, height),
+            format,
+            ..Default::default()
+        }
+    }
+
     /// Replaces the given texture handle
     /// Useful for hot reloading
     pub fn replace_image(&mut self, handle: ArenaId<Texture>, image: Image) {
-        let texture: Texture =
-            self.add_texture_bytes(&image.data, image.dimensions, image.sampler, image.format);
+        let texture: Texture = self.add_texture_bytes(
+            &image.data,
+            image.dimensions,
+            image.sampler,
+            image.format,
+            image.mipmaps,
+        );
         self.replace_texture(handle, texture)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use wgpu::{AddressMode, FilterMode};
+
+    use super::{Image, SamplerConfig, TextureSamplerType};
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, rect::Rect,
+        sprite::Sprite, transform::Transform,
+    };
+
+    #[test]
+    fn nearest_filters_turn_off_anisotropy() {
+        let config = SamplerConfig::linear()
+            .with_anisotropy(8)
+            .with_filters(FilterMode::Nearest, FilterMode::Nearest);
+        assert_eq!(config.anisotropy, 1);
+
+        let config = SamplerConfig::nearest()
+            .with_anisotropy(8)
+            .with_filters(FilterMode::Linear, FilterMode::Linear);
+        assert_eq!(config.anisotropy, 8);
+    }
+
+    #[test]
+    fn repeat_sampler_tiles_texture() {
+        let mut renderer = test_renderer((8, 8));
+        let repeat = SamplerConfig::nearest().with_wrap(AddressMode::Repeat);
+        let texture = renderer.add_texture(
+            Image {
+                data: vec![255, 0, 0, 255, 0, 0, 255, 255],
+                dimensions: (2, 1),
+                ..Default::default()
+            }
+            .with_sampler(repeat),
+        );
+        assert_eq!(
+            renderer.get_sampler(repeat.into()),
+            renderer.textures.get(texture).unwrap().sampler
+        );
+        assert_eq!(
+            renderer.get_sampler(SamplerConfig::linear().into()),
+            renderer.get_sampler(TextureSamplerType::Linear)
+        );
+
+        // The rect is twice as wide as the texture, so it's drawn twice
+        let sprite = Sprite {
+            texture_rect: Some(Rect::new(Vec2::new(4., 1.))),
+            custom_size: Some(Vec2::splat(8.)),
+            ..Sprite::new(texture)
+        };
+
+        let mut ctx = renderer.begin();
+        renderer.draw_sprite(&sprite, Transform::IDENTITY);
+        renderer.render(&mut ctx, Some(Color::GREEN), &Camera::orthographic());
+        renderer.end_frame(ctx);
+
+        let image = renderer.read_frame();
+        for (index, pixel) in image.data.chunks(4).enumerate() {
+            let expected = if index % 8 / 2 % 2 == 0 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            assert_eq!(pixel, expected, "pixel {index}");
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use glam::{UVec2, Vec2, Vec3};
+
+use crate::{
+    components::color::Color,
+    mesh::{Indices, Mesh, MeshBuilder, VertexData},
+};
+
+use super::{
+    camera::Camera, material::MaterialHandle, rect::Rect, texture_atlas::TextureAtlas, Renderer,
+};
+
+/// Tiles along each side of a chunk, unless set with [`Tilemap::with_chunk_size`]
+pub const DEFAULT_CHUNK_SIZE: u32 = 16;
+
+/// Clockwise quarter turns of a tile
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileRotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl TileRotation {
+    fn quarter_turns(self) -> usize {
+        match self {
+            TileRotation::None => 0,
+            TileRotation::Clockwise90 => 1,
+            TileRotation::Clockwise180 => 2,
+            TileRotation::Clockwise270 => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tile {
+    /// Index into [`TextureAtlas::textures`]
+    pub index: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Applied after the flips
+    pub rotation: TileRotation,
+    /// Multiplies the texture color, white without one
+    pub tint: Option<Color>,
+}
+
+impl Tile {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_flip_x(mut self, flip_x: bool) -> Self {
+        self.flip_x = flip_x;
+        self
+    }
+
+    pub fn with_flip_y(mut self, flip_y: bool) -> Self {
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: TileRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+
+    /// Atlas corners shown by the quad corners, ordered like [`crate::mesh::QUAD_VERTEX_POSITIONS`]
+    fn uvs(&self, rect: Rect) -> [Vec2; 4] {
+        let mut uvs = [
+            Vec2::new(rect.min.x, rect.max.y),
+            rect.max,
+            Vec2::new(rect.max.x, rect.min.y),
+            rect.min,
+        ];
+        if self.flip_x {
+            uvs = [uvs[1], uvs[0], uvs[3], uvs[2]];
+        }
+        if self.flip_y {
+            uvs = [uvs[3], uvs[2], uvs[1], uvs[0]];
+        }
+        uvs.rotate_left(self.rotation.quarter_turns());
+
+        uvs
+    }
+}
+
+#[derive(Debug, Default)]
+struct TilemapChunk {
+    /// None when every tile of the chunk is empty
+    mesh: Option<Mesh>,
+    dirty: bool,
+}
+
+#[derive(Debug)]
+pub struct TilemapLayer {
+    tiles: Vec<Option<Tile>>,
+    chunks: Vec<TilemapChunk>,
+    /// Render layer of the tiles, see [`crate::sprite::Sprite::layer`]
+    pub layer: i32,
+    pub visible: bool,
+}
+
+/// A grid of atlas tiles on one or more layers, tile (0, 0) being the top left one
+///
+/// Each layer is split into square chunks with a cached mesh, a chunk's mesh is only rebuilt
+/// after one of its tiles changes and only chunks the camera sees are drawn,
+/// see [`Renderer::draw_tilemap`]
+#[derive(Debug)]
+pub struct Tilemap {
+    atlas: TextureAtlas,
+    /// World position of the top left corner of the map
+    position: Vec3,
+    tile_size: Vec2,
+    size: UVec2,
+    chunk_size: u32,
+    layers: Vec<TilemapLayer>,
+    /// Drawn with the default material without one
+    pub material: Option<MaterialHandle>,
+}
+
+impl Tilemap {
+    /// An empty map of `size` tiles without layers, tiles are as big as the atlas tiles
+    pub fn new(atlas: TextureAtlas, size: UVec2) -> Self {
+        Self {
+            tile_size: atlas.tile_size,
+            atlas,
+            position: Vec3::ZERO,
+            size,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            layers: Vec::new(),
+            material: None,
+        }
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.set_position(position);
+        self
+    }
+
+    pub fn with_tile_size(mut self, tile_size: Vec2) -> Self {
+        self.set_tile_size(tile_size);
+        self
+    }
+
+    /// Smaller chunks cull more precisely and rebuild faster, but take more draw work
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        assert!(chunk_size > 0, "Tilemap chunks need at least one tile");
+        self.chunk_size = chunk_size;
+        let chunk_count = self.chunk_count();
+        for layer in &mut self.layers {
+            layer.chunks = Self::dirty_chunks(chunk_count);
+        }
+        self
+    }
+
+    /// Adds an empty layer drawn on the render `layer`, returning its index
+    pub fn add_layer(&mut self, layer: i32) -> usize {
+        self.layers.push(TilemapLayer {
+            tiles: vec![None; (self.size.x * self.size.y) as usize],
+            chunks: Self::dirty_chunks(self.chunk_count()),
+            layer,
+            visible: true,
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn atlas(&self) -> &TextureAtlas {
+        &self.atlas
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn tile_size(&self) -> Vec2 {
+        self.tile_size
+    }
+
+    /// Size of the map in tiles
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    pub fn layers(&self) -> &[TilemapLayer] {
+        &self.layers
+    }
+
+    pub fn layer_mut(&mut self, layer: usize) -> &mut TilemapLayer {
+        &mut self.layers[layer]
+    }
+
+    pub fn set_atlas(&mut self, atlas: TextureAtlas) {
+        self.atlas = atlas;
+        self.mark_all_dirty();
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+        self.mark_all_dirty();
+    }
+
+    pub fn set_tile_size(&mut self, tile_size: Vec2) {
+        self.tile_size = tile_size;
+        self.mark_all_dirty();
+    }
+
+    /// The tile at `position`, None for empty tiles and positions outside the map
+    pub fn get_tile(&self, layer: usize, position: UVec2) -> Option<Tile> {
+        let index = self.tile_index(position)?;
+        self.layers[layer].tiles[index]
+    }
+
+    /// Sets or clears the tile at `position`, only its chunk gets rebuilt
+    pub fn set_tile(&mut self, layer: usize, position: UVec2, tile: Option<Tile>) {
+        let index = self
+            .tile_index(position)
+            .unwrap_or_else(|| panic!("Tile {position} is outside the tilemap"));
+        let chunk = self.chunk_index(position / self.chunk_size);
+
+        let layer = &mut self.layers[layer];
+        if layer.tiles[index] != tile {
+            layer.tiles[index] = tile;
+            layer.chunks[chunk].dirty = true;
+        }
+    }
+
+    /// Sets every tile of the layer
+    pub fn fill(&mut self, layer: usize, tile: Option<Tile>) {
+        let layer = &mut self.layers[layer];
+        layer.tiles.fill(tile);
+        for chunk in &mut layer.chunks {
+            chunk.dirty = true;
+        }
+    }
+
+    /// World area covered by the tile at `position`
+    pub fn tile_rect(&self, position: UVec2) -> Rect {
+        self.area_rect(position, UVec2::ONE)
+    }
+
+    /// The tile covering a world position, None outside the map
+    pub fn world_to_tile(&self, world_position: Vec2) -> Option<UVec2> {
+        let offset = (world_position - self.position.truncate()) * Vec2::new(1., -1.);
+        let tile = (offset / self.tile_size).floor();
+        if tile.cmplt(Vec2::ZERO).any() {
+            return None;
+        }
+
+        let tile = tile.as_uvec2();
+        tile.cmplt(self.size).all().then_some(tile)
+    }
+
+    fn tile_index(&self, position: UVec2) -> Option<usize> {
+        position
+            .cmplt(self.size)
+            .all()
+            .then_some((position.y * self.size.x + position.x) as usize)
+    }
+
+    /// Chunks along each axis, the last ones may be partly outside the map
+    fn chunks_per_axis(&self) -> UVec2 {
+        (self.size + UVec2::splat(self.chunk_size - 1)) / self.chunk_size
+    }
+
+    fn chunk_count(&self) -> usize {
+        let chunks = self.chunks_per_axis();
+        (chunks.x * chunks.y) as usize
+    }
+
+    fn chunk_index(&self, chunk: UVec2) -> usize {
+        (chunk.y * self.chunks_per_axis().x + chunk.x) as usize
+    }
+
+    fn dirty_chunks(count: usize) -> Vec<TilemapChunk> {
+        (0..count)
+            .map(|_| TilemapChunk {
+                mesh: None,
+                dirty: true,
+            })
+            .collect()
+    }
+
+    fn mark_all_dirty(&mut self) {
+        for layer in &mut self.layers {
+            for chunk in &mut layer.chunks {
+                chunk.dirty = true;
+            }
+        }
+    }
+
+    /// World area of `size` tiles starting at the tile `start`
+    fn area_rect(&self, start: UVec2, size: UVec2) -> Rect {
+        let top_left =
+            self.position.truncate() + start.as_vec2() * self.tile_size * Vec2::new(1., -1.);
+        let bottom_right = top_left + size.as_vec2() * self.tile_size * Vec2::new(1., -1.);
+        Rect::from_corners(top_left, bottom_right)
+    }
+
+    fn chunk_tiles(&self, chunk: UVec2) -> (UVec2, UVec2) {
+        let start = chunk * self.chunk_size;
+        let end = (start + UVec2::splat(self.chunk_size)).min(self.size);
+        (start, end)
+    }
+
+    fn build_chunk_mesh(&self, layer: usize, chunk: UVec2, image_size: Vec2) -> Option<Mesh> {
+        let (start, end) = self.chunk_tiles(chunk);
+        let tiles = &self.layers[layer].tiles;
+
+        let mut vertices = VertexData::default();
+        let mut quad_count = 0;
+        for y in start.y..end.y {
+            for x in start.x..end.x {
+                let position = UVec2::new(x, y);
+                let Some(tile) = tiles[(y * self.size.x + x) as usize] else {
+                    continue;
+                };
+                let Some(rect) = self.atlas.textures.get(tile.index).copied() else {
+                    continue;
+                };
+
+                let bounds = self.tile_rect(position);
+                let corners = [
+                    bounds.min,
+                    Vec2::new(bounds.max.x, bounds.min.y),
+                    bounds.max,
+                    Vec2::new(bounds.min.x, bounds.max.y),
+                ];
+                vertices
+                    .positions
+                    .extend(corners.map(|corner| corner.extend(self.position.z).to_array()));
+                vertices
+                    .uvs
+                    .extend(tile.uvs(rect).map(|uv| (uv / image_size).to_array()));
+                let color = tile.tint.map_or([1.; 4], Color::as_rgba_f32);
+                vertices.colors.extend([color; 4]);
+                quad_count += 1;
+            }
+        }
+
+        (quad_count > 0).then(|| {
+            MeshBuilder::new()
+                .with_indices(Indices::quads(quad_count))
+                .with_vertices(vertices)
+                .with_texture(self.atlas.texture_handle)
+                .with_sort_position(self.position)
+                .build()
+        })
+    }
+}
+
+impl Renderer {
+    /// Draws the chunks of visible layers that `camera` sees, rebuilding the ones whose tiles changed
+    /// Every chunk is drawn for cameras without an orthographic projection
+    pub fn draw_tilemap(&mut self, tilemap: &mut Tilemap, camera: &Camera) {
+        let visible_rect = camera.visible_rect_2d(self.get_render_size());
+        let image_size = self
+            .textures
+            .get(tilemap.atlas.texture_handle)
+            .expect("Tilemap is missing texture")
+            .dimensions;
+        let material = tilemap.material.unwrap_or(self.material_map.default);
+        let chunks = tilemap.chunks_per_axis();
+
+        for layer in 0..tilemap.layers.len() {
+            if !tilemap.layers[layer].visible {
+                continue;
+            }
+
+            for chunk in (0..chunks.y).flat_map(|y| (0..chunks.x).map(move |x| UVec2::new(x, y))) {
+                if let Some(visible_rect) = &visible_rect {
+                    let (start, end) = tilemap.chunk_tiles(chunk);
+                    if !visible_rect.intersects(&tilemap.area_rect(start, end - start)) {
+                        continue;
+                    }
+                }
+
+                let index = tilemap.chunk_index(chunk);
+                let cached = &tilemap.layers[layer].chunks[index];
+                let material_changed = cached
+                    .mesh
+                    .as_ref()
+                    .is_some_and(|mesh| mesh.material_handle != material);
+                if cached.dirty || material_changed {
+                    let mut mesh = tilemap.build_chunk_mesh(layer, chunk, image_size);
+                    if let Some(mesh) = &mut mesh {
+                        mesh.material_handle = material;
+                        self.upload_mesh(mesh);
+                    }
+                    tilemap.layers[layer].chunks[index] = TilemapChunk { mesh, dirty: false };
+                }
+
+                let tilemap_layer = &tilemap.layers[layer];
+                if let Some(mesh) = &tilemap_layer.chunks[index].mesh {
+                    // The vertices stay in the chunk's buffers, so the clone only shares them
+                    let mut mesh = mesh.clone();
+                    mesh.layer = tilemap_layer.layer;
+                    self.push(mesh);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, Vec2, Vec3};
+
+    use super::{Tile, TileRotation, Tilemap};
+    use crate::{
+        camera::Camera, components::color::Color, headless::test_renderer, rect::Rect,
+        texture::Image, texture_atlas::TextureAtlas,
+    };
+
+    #[test]
+    fn set_tile_only_dirties_its_chunk() {
+        let mut tilemap =
+            Tilemap::new(TextureAtlas::default(), UVec2::new(10, 5)).with_chunk_size(4);
+        let layer = tilemap.add_layer(0);
+        assert_eq!(tilemap.layers[layer].chunks.len(), 3 * 2);
+        for chunk in &mut tilemap.layers[layer].chunks {
+            chunk.dirty = false;
+        }
+
+        tilemap.set_tile(layer, UVec2::new(9, 4), Some(Tile::new(1)));
+        let dirty: Vec<_> = tilemap.layers[layer]
+            .chunks
+            .iter()
+            .map(|chunk| chunk.dirty)
+            .collect();
+        assert_eq!(dirty, [false, false, false, false, false, true]);
+        assert_eq!(
+            tilemap.get_tile(layer, UVec2::new(9, 4)),
+            Some(Tile::new(1))
+        );
+        assert_eq!(tilemap.get_tile(layer, UVec2::new(10, 4)), None);
+    }
+
+    #[test]
+    fn tiles_are_placed_down_from_the_top_left() {
+        let tilemap =
+            Tilemap::new(TextureAtlas::default(), UVec2::new(4, 4)).with_tile_size(Vec2::splat(2.));
+
+        let rect = tilemap.tile_rect(UVec2::new(1, 0));
+        assert_eq!(
+            (rect.min, rect.max),
+            (Vec2::new(2., -2.), Vec2::new(4., 0.))
+        );
+        assert_eq!(
+            tilemap.world_to_tile(Vec2::new(3., -1.)),
+            Some(UVec2::new(1, 0))
+        );
+        assert_eq!(tilemap.world_to_tile(Vec2::new(3., 1.)), None);
+        assert_eq!(tilemap.world_to_tile(Vec2::new(9., -1.)), None);
+    }
+
+    #[test]
+    fn rotation_turns_uvs_clockwise() {
+        let rect = Rect::new(Vec2::ONE);
+        let rotated = Tile::new(0)
+            .with_rotation(TileRotation::Clockwise90)
+            .uvs(rect);
+        // The bottom left corner shows what was in the bottom right
+        assert_eq!(rotated[0], Vec2::new(1., 1.));
+        assert_eq!(rotated[3], Vec2::new(0., 1.));
+
+        let flipped = Tile::new(0).with_flip_x(true).uvs(rect);
+        assert_eq!(flipped[0], Vec2::new(1., 1.));
+        assert_eq!(flipped[2], Vec2::new(0., 0.));
+    }
+
+    #[test]
+    fn tilemap_draws_visible_chunks() {
+        let mut renderer = test_renderer((8, 8));
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        let texture = renderer.add_texture(Image {
+            data: [red, blue].concat(),
+            dimensions: (2, 1),
+            ..Default::default()
+        });
+        let atlas = TextureAtlas::new(texture, Vec2::ONE, 2, 1);
+
+        // 4x4 chunks, the camera sees the 8x8 tiles around the origin, so the top left 2x2 chunks
+        let mut tilemap = Tilemap::new(atlas, UVec2::new(32, 32))
+            .with_chunk_size(4)
+            .with_position(Vec3::new(-4., 4., 0.));
+        let layer = tilemap.add_layer(0);
+        tilemap.fill(layer, Some(Tile::new(0)));
+        tilemap.set_tile(layer, UVec2::new(0, 0), Some(Tile::new(1)));
+
+        // The chunks are only uploaded by the first frame
+        for frame in 0..2 {
+            let mut ctx = renderer.begin();
+            renderer.draw_tilemap(&mut tilemap, &Camera::orthographic());
+            assert_eq!(renderer.meshes.len(), 4);
+            renderer.render(&mut ctx, Some(Color::BLACK), &Camera::orthographic());
+            renderer.end_frame(ctx);
+            assert_eq!(renderer.render_stats().uploaded_bytes == 0, frame == 1);
+
+            let image = renderer.read_frame();
+            for (index, pixel) in image.data.chunks(4).enumerate() {
+                let expected = if index == 0 { blue } else { red };
+                assert_eq!(pixel, expected, "pixel {index}");
+            }
+        }
+    }
+}
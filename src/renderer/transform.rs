@@ -32,6 +32,12 @@ impl Transform {
         point
     }
 
+    /// Rotates a normal, scaling it by the inverse scale so it stays perpendicular to the surface
+    #[inline]
+    pub fn transform_normal(&self, normal: Vec3) -> Vec3 {
+        (self.rotation * (normal / self.scale)).normalize_or_zero()
+    }
+
     #[inline]
     pub fn compute_matrix(&self) -> Mat4 {
         Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
@@ -1,16 +1,14 @@
 use glam::Vec2;
 
-use crate::{
-    components::color::Color,
-    mesh::{Mesh, MeshBuilder},
-    Rect, Transform,
-};
+use crate::{components::color::Color, mesh::Mesh, sprite::Anchor, Rect, Transform};
 
-use super::Renderer;
+use super::{nine_slice::NineSlice, Renderer};
 
 #[derive(Debug)]
 pub struct LayoutTheme {
     pub color: Color,
+    /// Drawn stretched over the panel instead of the flat color
+    pub frame: Option<NineSlice>,
     pub padding: f32,
 }
 
@@ -18,6 +16,7 @@ impl Default for LayoutTheme {
     fn default() -> Self {
         LayoutTheme {
             color: Color::DARK_GRAY,
+            frame: None,
             padding: 5f32,
         }
     }
@@ -49,12 +48,17 @@ impl Layout {
         self.size.y
     }
 
+    /// `position` is the corner the layout grows from, the min corner with Y up
+    fn background_rect(&self) -> Rect {
+        Rect::from_corners(self.position, self.position + self.size)
+    }
+
     pub fn get_render_meta(&mut self) -> Vec<Mesh> {
         let mut meta = Vec::default();
 
         if let Some(theme) = &self.layout_theme {
-            if theme.color != Color::NONE {
-                meta.push(Rect::from_center_size(self.size, self.position).into_mesh(theme.color));
+            if theme.frame.is_none() && theme.color != Color::NONE {
+                meta.push(self.background_rect().into_mesh(theme.color));
             }
         }
 
@@ -84,7 +88,18 @@ impl Renderer {
             .pop()
             .expect("Missing layout when popping");
 
-        self.ui_render_data.append(&mut layout.get_render_meta());
+        if let Some(frame) = layout.layout_theme.as_ref().and_then(|theme| theme.frame) {
+            let rect = layout.background_rect();
+            let mut frame = frame.with_size(rect.size());
+            // Y points up, so the rect's min is its bottom left corner
+            frame.sprite.anchor = Anchor::BottomLeft;
+            let mesh =
+                self.nine_slice_to_mesh(&frame, Transform::from_position(rect.min.extend(0.)));
+            self.push(mesh);
+        }
+        for mesh in layout.get_render_meta() {
+            self.push(mesh);
+        }
     }
 
     pub fn get_available_space(&self) -> Vec2 {
@@ -114,10 +129,15 @@ impl Renderer {
     }
 
     pub fn panel(&mut self, callback: impl FnOnce(&mut Self)) {
+        self.panel_with_theme(LayoutTheme::default(), callback);
+    }
+
+    /// A panel drawn with `theme`, like one with a nine slice [`LayoutTheme::frame`]
+    pub fn panel_with_theme(&mut self, theme: LayoutTheme, callback: impl FnOnce(&mut Self)) {
         let available_space = self.get_available_space();
         let layout = Layout {
             size: available_space,
-            layout_theme: Some(LayoutTheme::default()),
+            layout_theme: Some(theme),
             position: self.get_next_available_position(),
             ..Default::default()
         };
@@ -127,3 +147,60 @@ impl Renderer {
         self.pop_layout();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::{Layout, LayoutTheme};
+    use crate::{
+        camera::Camera,
+        components::color::Color,
+        headless::test_renderer,
+        nine_slice::{NineSlice, SliceBorder},
+        sprite::Sprite,
+        texture::Image,
+    };
+
+    #[test]
+    fn themed_panel_draws_frame() {
+        let mut renderer = test_renderer((8, 8));
+        // Red corners, blue edges and a white center, one pixel each
+        let (red, blue, white) = ([255, 0, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]);
+        let texture = renderer.add_texture(Image {
+            data: [red, blue, red, blue, white, blue, red, blue, red].concat(),
+            dimensions: (3, 3),
+            ..Default::default()
+        });
+        let frame = NineSlice::new(Sprite::new(texture), SliceBorder::all(1.));
+
+        // Two frames, the panel meshes are drawn and drained every frame
+        for _ in 0..2 {
+            let mut ctx = renderer.begin();
+            renderer.current_layout.push(Layout {
+                size: Vec2::splat(8.),
+                position: Vec2::splat(-4.),
+                ..Default::default()
+            });
+            renderer.panel_with_theme(
+                LayoutTheme {
+                    frame: Some(frame),
+                    ..Default::default()
+                },
+                |_| {},
+            );
+            renderer.current_layout.pop();
+            renderer.render(&mut ctx, Some(Color::BLACK), &Camera::orthographic());
+            renderer.end_frame(ctx);
+            assert!(renderer.meshes.is_empty());
+        }
+
+        let image = renderer.read_frame();
+        let pixel = |x: usize, y: usize| &image.data[(y * 8 + x) * 4..][..4];
+        for (x, y) in [(0, 0), (7, 0), (0, 7), (7, 7)] {
+            assert_eq!(pixel(x, y), red, "corner {x}, {y}");
+        }
+        assert_eq!(pixel(3, 0), blue);
+        assert_eq!(pixel(3, 4), white);
+    }
+}
@@ -4,7 +4,7 @@ pub mod sdl_window;
 #[cfg(feature = "winit")]
 pub mod winit_window;
 
-use crate::{Engine, Nimbus};
+use crate::{pixel_perfect::PixelPerfectSettings, Engine, Nimbus};
 use glam::UVec2;
 
 #[cfg(feature = "winit")]
@@ -30,6 +30,8 @@ pub struct WindowDescriptor<'a> {
     /// - iOS / Android / Web: Unsupported.
     pub resizable: bool,
     pub render_resolution: Option<UVec2>,
+    /// Renders at `render_resolution` and scales up to the window, requires `render_resolution`
+    pub pixel_perfect: Option<PixelPerfectSettings>,
 }
 
 impl<'a> Default for WindowDescriptor<'a> {
@@ -40,6 +42,7 @@ impl<'a> Default for WindowDescriptor<'a> {
             title: "Nimbus Engine",
             resizable: false,
             render_resolution: None,
+            pixel_perfect: None,
         }
     }
 }